@@ -0,0 +1,597 @@
+//! Scrapers for the travel advisory pages of Amtrak's state supported corridors.
+//!
+//! Every corridor website is an [`AdvisorySource`]. The trait only knows how to find the page and parse it,
+//! fetching is shared in [`fetch_advisories`] so that new corridors only need a parser.
+//!
+//! Most corridor sites are built from one of two layouts, so the shared helpers `parse_card_advisories`
+//! (one element per advisory) and `parse_heading_advisories` (a heading followed by paragraphs) cover them.
+
+use crate::alert_classifier::classify_alert;
+use crate::alert_ids::stable_alert_id;
+use crate::{long_name_to_route_id, route_name_to_route_id};
+use gtfs_realtime::FeedEntity;
+use gtfs_structures::Gtfs;
+use scraper::{ElementRef, Html, Selector};
+
+pub trait AdvisorySource: Send + Sync {
+    /// Prefix of the alert ids produced by this source, e.g. `PAC_SURF`.
+    fn id_prefix(&self) -> &'static str;
+
+    /// `route_long_name` of the corridor in the Amtrak GTFS.
+    fn route_long_name(&self) -> &'static str;
+
+    /// Travel advisory page of the corridor.
+    fn url(&self) -> &'static str;
+
+    /// Parses the advisory page into alert entities informing `route_id`.
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity>;
+
+    /// Finds the corridor's route in the schedule, see [`route_name_to_route_id`].
+    fn route_id(&self, gtfs: &Gtfs) -> Option<String> {
+        route_name_to_route_id(&long_name_to_route_id(gtfs), self.route_long_name())
+    }
+}
+
+/// Every corridor scraper included in this crate.
+pub fn default_advisory_sources() -> Vec<Box<dyn AdvisorySource>> {
+    vec![
+        Box::new(crate::pacific_surfliner_website::PacificSurfliner),
+        Box::new(crate::capitol_corridor_website::CapitolCorridor),
+        Box::new(crate::cascades_website::Cascades),
+        Box::new(crate::gold_runner_website::GoldRunner),
+        Box::new(crate::downeaster_website::Downeaster),
+        Box::new(crate::hiawatha_website::Hiawatha),
+    ]
+}
+
+/// Downloads and parses a single advisory page.
+/// Returns no alerts when the corridor doesn't exist in the GTFS, since there would be nothing to inform.
+pub async fn fetch_advisories(
+    source: &dyn AdvisorySource,
+    client: &reqwest::Client,
+    gtfs: &Gtfs,
+) -> Result<Vec<FeedEntity>, Box<dyn std::error::Error + Sync + Send>> {
+    let route_id = source.route_id(gtfs);
+
+    if route_id.is_none() {
+        return Ok(vec![]);
+    }
+
+    let resp = client.get(source.url()).send().await?;
+    let text = resp.text().await?;
+
+    Ok(source.parse(&text, route_id))
+}
+
+/// Fetches every source at once. A failing website is logged and skipped so it can't take down the whole alerts feed.
+pub async fn fetch_all_advisories(
+    sources: &[Box<dyn AdvisorySource>],
+    client: &reqwest::Client,
+    gtfs: &Gtfs,
+) -> Vec<FeedEntity> {
+    let results = futures::future::join_all(
+        sources
+            .iter()
+            .map(|source| fetch_advisories(source.as_ref(), client, gtfs)),
+    )
+    .await;
+
+    let mut alerts = vec![];
+
+    for (source, result) in sources.iter().zip(results) {
+        match result {
+            Ok(mut source_alerts) => alerts.append(&mut source_alerts),
+            Err(e) => {
+                eprintln!("Error fetching {} alerts: {}", source.route_long_name(), e);
+            }
+        }
+    }
+
+    alerts
+}
+
+/// Collapses all whitespace of an element's text into single spaces.
+pub(crate) fn normalized_text(element: &ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Appends an element to a description, formatting lists as `- item` lines.
+pub(crate) fn push_description(description: &mut String, element: &ElementRef) {
+    let text = normalized_text(element);
+
+    if text.is_empty() {
+        return;
+    }
+
+    if matches!(element.value().name(), "ul" | "ol") {
+        for li in element.select(&Selector::parse("li").unwrap()) {
+            description.push_str(&format!("- {}\n", normalized_text(&li)));
+        }
+    } else {
+        description.push_str(&text);
+        description.push_str("\n\n");
+    }
+}
+
+/// Builds the alert entity for one scraped advisory, with a stable id and classified cause and effect.
+pub(crate) fn advisory_entity(
+    id_prefix: &str,
+    section: &str,
+    title: String,
+    description: &str,
+    route_id: Option<String>,
+) -> FeedEntity {
+    let id = stable_alert_id(id_prefix, section, &title);
+
+    let description = description.trim().to_string();
+    let classification = classify_alert(Some(section), &title, &description);
+
+    FeedEntity {
+        id,
+        is_deleted: Some(false),
+        trip_update: None,
+        vehicle: None,
+        stop: None,
+        shape: None,
+        trip_modifications: None,
+        alert: Some(gtfs_realtime::Alert {
+            active_period: vec![],
+            informed_entity: vec![gtfs_realtime::EntitySelector {
+                agency_id: None,
+                route_id,
+                route_type: None,
+                trip: None,
+                stop_id: None,
+                direction_id: None,
+            }],
+            cause: Some(classification.cause as i32),
+            effect: Some(classification.effect as i32),
+            url: None,
+            header_text: Some(gtfs_realtime::TranslatedString {
+                translation: vec![gtfs_realtime::translated_string::Translation {
+                    text: title,
+                    language: Some("en".to_string()),
+                }],
+            }),
+            description_text: Some(gtfs_realtime::TranslatedString {
+                translation: vec![gtfs_realtime::translated_string::Translation {
+                    text: description,
+                    language: Some("en".to_string()),
+                }],
+            }),
+            tts_header_text: None,
+            tts_description_text: None,
+            severity_level: Some(classification.severity_level as i32),
+            image: None,
+            image_alternative_text: None,
+            cause_detail: None,
+            effect_detail: None,
+        }),
+    }
+}
+
+/// CSS selectors for sites that wrap every advisory in its own element.
+pub(crate) struct CardSelectors {
+    /// One match per advisory
+    pub card: &'static str,
+    /// Title, searched inside the card
+    pub title: &'static str,
+    /// Body, searched inside the card. Every match is appended to the description.
+    pub body: &'static str,
+    /// Optional category label inside the card, used as the section
+    pub section: Option<&'static str>,
+}
+
+pub(crate) fn parse_card_advisories(
+    text: &str,
+    selectors: &CardSelectors,
+    id_prefix: &str,
+    default_section: &str,
+    route_id: Option<String>,
+) -> Vec<FeedEntity> {
+    let document = Html::parse_document(text);
+
+    let card_selector = Selector::parse(selectors.card).unwrap();
+    let title_selector = Selector::parse(selectors.title).unwrap();
+    let body_selector = Selector::parse(selectors.body).unwrap();
+    let section_selector = selectors.section.map(|s| Selector::parse(s).unwrap());
+
+    let mut alerts = Vec::new();
+
+    for card in document.select(&card_selector) {
+        let title = match card.select(&title_selector).next() {
+            Some(title) => normalized_text(&title),
+            None => continue,
+        };
+
+        if title.is_empty() {
+            continue;
+        }
+
+        let section = section_selector
+            .as_ref()
+            .and_then(|selector| card.select(selector).next())
+            .map(|section| normalized_text(&section))
+            .filter(|section| !section.is_empty())
+            .unwrap_or_else(|| default_section.to_string());
+
+        let mut description = String::new();
+
+        for body in card.select(&body_selector) {
+            // Bodies are usually a wrapper div, describe its block children so lists stay formatted
+            let children = body
+                .children()
+                .filter_map(ElementRef::wrap)
+                .collect::<Vec<ElementRef>>();
+
+            if children.is_empty() {
+                push_description(&mut description, &body);
+            } else {
+                for child in children {
+                    push_description(&mut description, &child);
+                }
+            }
+        }
+
+        alerts.push(advisory_entity(
+            id_prefix,
+            &section,
+            title,
+            &description,
+            route_id.clone(),
+        ));
+    }
+
+    alerts
+}
+
+/// Parses sites where advisories are a `title_tag` heading followed by content, optionally grouped
+/// under `section_tag` headings. Everything in between two headings becomes the description.
+pub(crate) fn parse_heading_advisories(
+    text: &str,
+    container: &str,
+    section_tag: Option<&str>,
+    title_tag: &str,
+    id_prefix: &str,
+    default_section: &str,
+    route_id: Option<String>,
+) -> Vec<FeedEntity> {
+    let document = Html::parse_document(text);
+    let container_selector = Selector::parse(container).unwrap();
+
+    let mut alerts = Vec::new();
+
+    for container in document.select(&container_selector) {
+        let mut section = default_section.to_string();
+        let mut current: Option<(String, String)> = None;
+
+        for element in container.children().filter_map(ElementRef::wrap) {
+            let name = element.value().name();
+
+            if Some(name) == section_tag || name == title_tag {
+                if let Some((title, description)) = current.take() {
+                    alerts.push(advisory_entity(
+                        id_prefix,
+                        &section,
+                        title,
+                        &description,
+                        route_id.clone(),
+                    ));
+                }
+
+                let heading = normalized_text(&element);
+
+                if heading.is_empty() {
+                    continue;
+                }
+
+                if name == title_tag {
+                    current = Some((heading, String::new()));
+                } else {
+                    section = heading;
+                }
+            } else if let Some((_, description)) = current.as_mut() {
+                push_description(description, &element);
+            }
+        }
+
+        if let Some((title, description)) = current.take() {
+            alerts.push(advisory_entity(
+                id_prefix,
+                &section,
+                title,
+                &description,
+                route_id.clone(),
+            ));
+        }
+    }
+
+    alerts
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use gtfs_realtime::FeedEntity;
+
+    pub fn header(entity: &FeedEntity) -> &str {
+        &entity
+            .alert
+            .as_ref()
+            .unwrap()
+            .header_text
+            .as_ref()
+            .unwrap()
+            .translation[0]
+            .text
+    }
+
+    pub fn description(entity: &FeedEntity) -> &str {
+        &entity
+            .alert
+            .as_ref()
+            .unwrap()
+            .description_text
+            .as_ref()
+            .unwrap()
+            .translation[0]
+            .text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capitol_corridor_website::CapitolCorridor;
+    use crate::cascades_website::Cascades;
+    use crate::downeaster_website::Downeaster;
+    use crate::gold_runner_website::GoldRunner;
+    use crate::hiawatha_website::Hiawatha;
+    use gtfs_realtime::alert::{Cause, Effect};
+
+    #[test]
+    fn route_lookup_by_long_name() {
+        let mut routes = std::collections::HashMap::new();
+        routes.insert(
+            "84".to_string(),
+            gtfs_structures::Route {
+                id: "84".to_string(),
+                long_name: Some("Capitol Corridor".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let gtfs = Gtfs {
+            routes,
+            ..Default::default()
+        };
+
+        let sources = default_advisory_sources();
+
+        let found = sources
+            .iter()
+            .filter_map(|source| source.route_id(&gtfs))
+            .collect::<Vec<String>>();
+
+        // Gold Runner isn't in the Amtrak GTFS and always uses its own route id
+        assert_eq!(found, vec!["84".to_string(), "GR".to_string()]);
+    }
+
+    #[test]
+    fn heading_layout_sections() {
+        let html = r#"
+        <main>
+            <h2>Service Alerts</h2>
+            <h3>Schedule Change</h3>
+            <p>Train 1 will depart later.</p>
+            <ul><li>Effective Monday</li></ul>
+            <h2>Station Notices</h2>
+            <h3>Elevator Outage</h3>
+            <p>The elevator is out of service.</p>
+        </main>
+        "#;
+
+        let alerts = parse_heading_advisories(
+            html,
+            "main",
+            Some("h2"),
+            "h3",
+            "TEST",
+            "Alerts",
+            Some("R".to_string()),
+        );
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(test_helpers::header(&alerts[0]), "Schedule Change");
+        assert_eq!(
+            test_helpers::description(&alerts[0]),
+            "Train 1 will depart later.\n\n- Effective Monday"
+        );
+        assert_eq!(
+            alerts[1].id,
+            stable_alert_id("TEST", "Station Notices", "Elevator Outage")
+        );
+    }
+
+    struct ExpectedAlert {
+        header: &'static str,
+        /// Part of the description
+        description: &'static str,
+        cause: Cause,
+        effect: Effect,
+    }
+
+    #[test]
+    fn corridor_fixtures() {
+        let fixtures: [(&dyn AdvisorySource, &str, Vec<ExpectedAlert>); 5] = [
+            (
+                &CapitolCorridor,
+                include_str!("../tests/fixtures/capitol_corridor_alerts.html"),
+                // The alert without a title is skipped
+                vec![
+                    ExpectedAlert {
+                        header: "Weekend Track Work Between Sacramento and Roseville",
+                        description: "- Bus 3521 departs Sacramento at 8:05 AM",
+                        cause: Cause::Maintenance,
+                        effect: Effect::ModifiedService,
+                    },
+                    ExpectedAlert {
+                        header: "Richmond Station Elevator Out of Service",
+                        description: "",
+                        cause: Cause::Maintenance,
+                        effect: Effect::AccessibilityIssue,
+                    },
+                ],
+            ),
+            (
+                &Cascades,
+                include_str!("../tests/fixtures/cascades_alerts.html"),
+                vec![
+                    ExpectedAlert {
+                        header: "Mudslide Moratorium Between Seattle and Everett",
+                        description: "replaced by buses",
+                        cause: Cause::Weather,
+                        effect: Effect::ModifiedService,
+                    },
+                    ExpectedAlert {
+                        header: "Tacoma Dome Station Parking Changes",
+                        description: "",
+                        cause: Cause::Construction,
+                        effect: Effect::OtherEffect,
+                    },
+                ],
+            ),
+            (
+                &Downeaster,
+                include_str!("../tests/fixtures/downeaster_alerts.html"),
+                vec![
+                    ExpectedAlert {
+                        header: "Train 681 Canceled January 14",
+                        description: "due to a crew shortage.\n\nPassengers may travel on train 683",
+                        cause: Cause::UnknownCause,
+                        effect: Effect::NoService,
+                    },
+                    ExpectedAlert {
+                        header: "Winter Weather Reminder",
+                        description: "",
+                        cause: Cause::Weather,
+                        effect: Effect::UnknownEffect,
+                    },
+                ],
+            ),
+            (
+                &GoldRunner,
+                include_str!("../tests/fixtures/gold_runner_alerts.html"),
+                vec![
+                    ExpectedAlert {
+                        header: "Modified Schedule for Trains 711 and 718",
+                        description: "- Train 718 will arrive Oakland 15 minutes later.",
+                        cause: Cause::Maintenance,
+                        // "15 minutes later" is a new schedule, not a delay
+                        effect: Effect::ModifiedService,
+                    },
+                    ExpectedAlert {
+                        header: "Thruway Bus Route 18 Suspended",
+                        description: "",
+                        cause: Cause::UnknownCause,
+                        effect: Effect::NoService,
+                    },
+                    ExpectedAlert {
+                        header: "Merced Station Ticket Office Hours",
+                        description: "",
+                        cause: Cause::UnknownCause,
+                        effect: Effect::OtherEffect,
+                    },
+                ],
+            ),
+            (
+                &Hiawatha,
+                include_str!("../tests/fixtures/hiawatha_alerts.html"),
+                // The page title isn't an alert
+                vec![
+                    ExpectedAlert {
+                        header: "Sturtevant Station Platform Construction",
+                        description: "Effective February 2, 2026",
+                        cause: Cause::Construction,
+                        effect: Effect::StopMoved,
+                    },
+                    ExpectedAlert {
+                        header: "Reduced Service on Presidents Day",
+                        description: "- Trains 329 and 336 will not operate.",
+                        cause: Cause::Holiday,
+                        effect: Effect::NoService,
+                    },
+                ],
+            ),
+        ];
+
+        for (source, html, expected) in fixtures {
+            let alerts = source.parse(html, Some("R".to_string()));
+
+            assert_eq!(
+                alerts
+                    .iter()
+                    .map(test_helpers::header)
+                    .collect::<Vec<&str>>(),
+                expected.iter().map(|e| e.header).collect::<Vec<&str>>(),
+                "{}",
+                source.id_prefix()
+            );
+
+            for (entity, expected) in alerts.iter().zip(&expected) {
+                let alert = entity.alert.as_ref().unwrap();
+
+                assert!(entity.id.starts_with(&format!("{}_", source.id_prefix())));
+                assert!(
+                    test_helpers::description(entity).contains(expected.description),
+                    "{}",
+                    expected.header
+                );
+                assert_eq!(
+                    alert.cause,
+                    Some(expected.cause as i32),
+                    "{}",
+                    expected.header
+                );
+                assert_eq!(
+                    alert.effect,
+                    Some(expected.effect as i32),
+                    "{}",
+                    expected.header
+                );
+                assert_eq!(alert.informed_entity[0].route_id, Some("R".to_string()));
+            }
+        }
+
+        // The alert type label must not leak into the description
+        let cascades = Cascades.parse(include_str!("../tests/fixtures/cascades_alerts.html"), None);
+        assert!(!test_helpers::description(&cascades[0]).contains("Service Disruption"));
+
+        let downeaster = Downeaster.parse(
+            include_str!("../tests/fixtures/downeaster_alerts.html"),
+            None,
+        );
+        assert_eq!(
+            downeaster[0].alert.as_ref().unwrap().severity_level,
+            Some(gtfs_realtime::alert::SeverityLevel::Severe as i32)
+        );
+
+        // Gold Runner groups alerts under section headings
+        let gold_runner = GoldRunner.parse(
+            include_str!("../tests/fixtures/gold_runner_alerts.html"),
+            None,
+        );
+        assert_eq!(
+            gold_runner[2].id,
+            stable_alert_id(
+                "GOLD_RUN",
+                "Station Notices",
+                "Merced Station Ticket Office Hours"
+            )
+        );
+    }
+}
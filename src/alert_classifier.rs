@@ -0,0 +1,401 @@
+//! Keyword and section based classification of advisory text into GTFS-rt cause, effect and severity.
+//!
+//! Used for scraped website advisories (where the page section is known, e.g. "Track Closures")
+//! as well as for free text ASM alerts, where only the message text is available.
+
+use gtfs_realtime::alert::{Cause, Effect, SeverityLevel};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlertClassification {
+    pub cause: Cause,
+    pub effect: Effect,
+    pub severity_level: SeverityLevel,
+}
+
+impl AlertClassification {
+    /// Writes the classification into the cause, effect and severity fields of an alert.
+    pub fn apply(&self, alert: &mut gtfs_realtime::Alert) {
+        alert.cause = Some(self.cause as i32);
+        alert.effect = Some(self.effect as i32);
+        alert.severity_level = Some(self.severity_level as i32);
+    }
+}
+
+// Checked in order, the first rule with a matching keyword wins.
+// Bus substitutions come before cancellations, since "trains canceled, bus connections provided"
+// still leaves riders with a (modified) service.
+const EFFECT_RULES: &[(&[&str], Effect)] = &[
+    (
+        &[
+            "bus connection*",
+            "bus bridge*",
+            "substitute bus*",
+            "bus substitution*",
+            "replaced by bus*",
+            "buses will",
+            "bus service will",
+        ],
+        Effect::ModifiedService,
+    ),
+    (
+        &[
+            "cancel*",
+            "no service",
+            "suspended",
+            "will not operate",
+            "will not run",
+            "not operating",
+        ],
+        Effect::NoService,
+    ),
+    (&["detour*", "rerout*", "re-rout*"], Effect::Detour),
+    (
+        &[
+            "elevator*",
+            "escalator*",
+            "wheelchair*",
+            "accessib*",
+            "ramp*",
+        ],
+        Effect::AccessibilityIssue,
+    ),
+    (
+        &[
+            "delay*",
+            "minutes late",
+            "running late",
+            "late",
+            "holding",
+            "slow order*",
+        ],
+        Effect::SignificantDelays,
+    ),
+    (
+        &[
+            "additional train*",
+            "extra train*",
+            "added train*",
+            "additional service",
+        ],
+        Effect::AdditionalService,
+    ),
+    (
+        &[
+            "schedule change*",
+            "modified schedule*",
+            "timetable*",
+            "will not stop",
+            "will depart",
+            "will terminate",
+            "will originate",
+        ],
+        Effect::ModifiedService,
+    ),
+    // Only where trains are boarded, a moved parking lot or ticket office isn't a moved stop
+    (
+        &[
+            "station relocat*",
+            "platform relocat*",
+            "stop relocat*",
+            "station has moved",
+            "platform has moved",
+            "stop has moved",
+            "temporary platform*",
+            "temporary station*",
+            "boarding location*",
+            "board from",
+            "board at",
+        ],
+        Effect::StopMoved,
+    ),
+    (&["reduced service", "fewer trains"], Effect::ReducedService),
+];
+
+const CAUSE_RULES: &[(&[&str], Cause)] = &[
+    (
+        &[
+            "weather",
+            "storm*",
+            "flood*",
+            "snow*",
+            "extreme heat",
+            "heat restriction*",
+            "wildfire*",
+            "hurricane*",
+            "high wind*",
+            "mudslide*",
+            "landslide*",
+        ],
+        Cause::Weather,
+    ),
+    (
+        &["police", "law enforcement", "security"],
+        Cause::PoliceActivity,
+    ),
+    (&["medical"], Cause::MedicalEmergency),
+    (
+        &[
+            "trespass*",
+            "collision*",
+            "derail*",
+            "accident*",
+            "struck",
+            "grade crossing incident*",
+        ],
+        Cause::Accident,
+    ),
+    (
+        &[
+            "mechanical",
+            "equipment",
+            "signal*",
+            "technical",
+            "disabled train*",
+        ],
+        Cause::TechnicalProblem,
+    ),
+    (
+        &[
+            "construction",
+            "project",
+            "projects",
+            "improvement*",
+            "upgrade*",
+        ],
+        Cause::Construction,
+    ),
+    (
+        &["maintenance", "track work", "repair*", "inspection*"],
+        Cause::Maintenance,
+    ),
+    (&["strike*", "labor action*"], Cause::Strike),
+    (&["demonstration*", "protest*"], Cause::Demonstration),
+    (
+        &[
+            "holiday*",
+            "thanksgiving",
+            "christmas",
+            "new year*",
+            "memorial day",
+            "labor day",
+        ],
+        Cause::Holiday,
+    ),
+];
+
+// Keywords are whole words or phrases, so "late" matches "late." but "project" doesn't match "projected".
+// A keyword ending in `*` is a stem that only has to start at a word boundary, so "cancel*" matches
+// "canceled" but "ramp*" doesn't match "tramp".
+fn contains_keyword(text: &str, keyword: &str) -> bool {
+    let (keyword, is_stem) = match keyword.strip_suffix('*') {
+        Some(stem) => (stem, true),
+        None => (keyword, false),
+    };
+
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+
+    text.match_indices(keyword).any(|(i, _)| {
+        is_boundary(text[..i].chars().next_back())
+            && (is_stem || is_boundary(text[i + keyword.len()..].chars().next()))
+    })
+}
+
+fn first_match<T: Copy>(text: &str, rules: &[(&[&str], T)]) -> Option<T> {
+    rules
+        .iter()
+        .find(|(keywords, _)| {
+            keywords
+                .iter()
+                .any(|keyword| contains_keyword(text, keyword))
+        })
+        .map(|(_, value)| *value)
+}
+
+fn section_defaults(section: &str) -> (Option<Cause>, Option<Effect>) {
+    let section = section.to_lowercase();
+
+    if section.contains("track closure") {
+        (Some(Cause::Maintenance), Some(Effect::NoService))
+    } else if section.contains("station notice") {
+        (None, Some(Effect::OtherEffect))
+    } else if section.contains("service update") {
+        (None, Some(Effect::ModifiedService))
+    } else {
+        (None, None)
+    }
+}
+
+fn severity_for_effect(effect: Effect) -> SeverityLevel {
+    match effect {
+        Effect::NoService => SeverityLevel::Severe,
+        Effect::Detour
+        | Effect::ModifiedService
+        | Effect::SignificantDelays
+        | Effect::ReducedService
+        | Effect::StopMoved => SeverityLevel::Warning,
+        Effect::AccessibilityIssue
+        | Effect::AdditionalService
+        | Effect::OtherEffect
+        | Effect::NoEffect => SeverityLevel::Info,
+        Effect::UnknownEffect => SeverityLevel::UnknownSeverity,
+    }
+}
+
+/// Classifies an advisory from its (optional) page section, title and description.
+///
+/// Keywords found in the title and description take priority over the section defaults,
+/// so a "Track Closures" advisory that mentions bus connections becomes `MODIFIED_SERVICE`
+/// rather than `NO_SERVICE`.
+pub fn classify_alert(
+    section: Option<&str>,
+    title: &str,
+    description: &str,
+) -> AlertClassification {
+    let text = format!("{}\n{}", title, description).to_lowercase();
+
+    let (section_cause, section_effect) = section.map(section_defaults).unwrap_or((None, None));
+
+    let effect = first_match(&text, EFFECT_RULES)
+        .or(section_effect)
+        .unwrap_or(Effect::UnknownEffect);
+
+    let cause = first_match(&text, CAUSE_RULES)
+        .or(section_cause)
+        .unwrap_or(Cause::UnknownCause);
+
+    AlertClassification {
+        cause,
+        effect,
+        severity_level: severity_for_effect(effect),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_closure_section_defaults_to_no_service() {
+        let c = classify_alert(
+            Some("Track Closures"),
+            "Temporary Track Closure January 6",
+            "Trains will not run between Goleta and San Luis Obispo.",
+        );
+
+        assert_eq!(c.effect, Effect::NoService);
+        assert_eq!(c.cause, Cause::Maintenance);
+        assert_eq!(c.severity_level, SeverityLevel::Severe);
+    }
+
+    #[test]
+    fn bus_connections_are_modified_service() {
+        let c = classify_alert(
+            Some("Track Closures"),
+            "Temporary Track Closure",
+            "The bus connections will be as follows:",
+        );
+
+        assert_eq!(c.effect, Effect::ModifiedService);
+        assert_eq!(c.severity_level, SeverityLevel::Warning);
+    }
+
+    #[test]
+    fn station_notices() {
+        let elevator = classify_alert(
+            Some("Station Notices"),
+            "IRVINE TRAIN STATION ELEVATOR MAINTENANCE",
+            "The City of Irvine will perform upcoming maintenance...",
+        );
+
+        assert_eq!(elevator.effect, Effect::AccessibilityIssue);
+        assert_eq!(elevator.cause, Cause::Maintenance);
+        assert_eq!(elevator.severity_level, SeverityLevel::Info);
+
+        let parking = classify_alert(
+            Some("Station Notices"),
+            "PARKING LOT CLOSED AT GUADALUPE STATION",
+            "Guadalupe Station Parking Lot...",
+        );
+
+        assert_eq!(parking.effect, Effect::OtherEffect);
+        assert_eq!(parking.cause, Cause::UnknownCause);
+
+        // "window" must not be read as wind, nor "tickets" as any other keyword
+        let ticket_window = classify_alert(
+            Some("Station Notices"),
+            "TEMPORARY TICKET WINDOW CLOSURES",
+            "Pacific Surfliner trains continue to serve all stations.",
+        );
+
+        assert_eq!(ticket_window.effect, Effect::OtherEffect);
+        assert_eq!(ticket_window.cause, Cause::UnknownCause);
+
+        let moved_parking = classify_alert(
+            Some("Station Notices"),
+            "Tacoma Dome Station Parking Changes",
+            "Short term parking has moved to the north lot during construction.",
+        );
+
+        assert_eq!(moved_parking.effect, Effect::OtherEffect);
+        assert_eq!(moved_parking.cause, Cause::Construction);
+
+        let temporary_platform = classify_alert(
+            Some("Station Notices"),
+            "Sturtevant Station Platform Construction",
+            "Passengers will board from a temporary platform on the west side of the tracks.",
+        );
+
+        assert_eq!(temporary_platform.effect, Effect::StopMoved);
+    }
+
+    #[test]
+    fn asm_style_text() {
+        let c = classify_alert(
+            None,
+            "",
+            "Train 5 is currently operating approximately 45 minutes late due to mechanical issues.",
+        );
+
+        assert_eq!(c.effect, Effect::SignificantDelays);
+        assert_eq!(c.cause, Cause::TechnicalProblem);
+    }
+
+    #[test]
+    fn keywords_are_whole_words() {
+        for text in [
+            "Train 5 is running 20 minutes late.",
+            "Train 5 is late, passengers should allow extra time.",
+        ] {
+            assert_eq!(
+                classify_alert(None, "", text).effect,
+                Effect::SignificantDelays,
+                "{}",
+                text
+            );
+        }
+
+        let projected = classify_alert(
+            None,
+            "",
+            "The projected arrival of train 5 in Chicago is 45 minutes late.",
+        );
+
+        assert_eq!(projected.effect, Effect::SignificantDelays);
+        assert_eq!(projected.cause, Cause::UnknownCause);
+
+        // Stems still match their longer forms
+        let canceled = classify_alert(None, "Train 5 Canceled", "Due to flooding.");
+
+        assert_eq!(canceled.effect, Effect::NoService);
+        assert_eq!(canceled.cause, Cause::Weather);
+    }
+
+    #[test]
+    fn unknown_text() {
+        let c = classify_alert(None, "", "Thank you for riding.");
+
+        assert_eq!(c.effect, Effect::UnknownEffect);
+        assert_eq!(c.cause, Cause::UnknownCause);
+        assert_eq!(c.severity_level, SeverityLevel::UnknownSeverity);
+    }
+}
@@ -0,0 +1,131 @@
+//! Stable, content-addressed ids for alerts.
+//!
+//! `DefaultHasher` output may change between Rust releases, so ids are built from SHA-256 instead.
+//! The id only covers the section and title of an advisory, so it stays the same while an advisory
+//! is edited. Edits are surfaced through the alerts feed's `feed_version`, which is a digest of every
+//! alert's content.
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use gtfs_realtime::FeedEntity;
+
+// Separates hashed fields so that ("ab", "c") and ("a", "bc") don't collide
+const FIELD_SEPARATOR: &str = "\u{1f}";
+
+fn sha256_hex(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+
+    for part in parts {
+        hasher.input_str(part);
+        hasher.input_str(FIELD_SEPARATOR);
+    }
+
+    hasher.result_str()
+}
+
+/// Builds an alert id like `PAC_SURF_ff21e636586a557c` from the advisory's section and title.
+pub fn stable_alert_id(prefix: &str, section: &str, title: &str) -> String {
+    let hash = sha256_hex(&[section.trim(), title.trim()]);
+
+    format!("{}_{}", prefix, &hash[..16])
+}
+
+fn translated_text(text: &Option<gtfs_realtime::TranslatedString>) -> String {
+    text.as_ref()
+        .map(|text| {
+            text.translation
+                .iter()
+                .map(|t| t.text.as_str())
+                .collect::<Vec<&str>>()
+                .join(FIELD_SEPARATOR)
+        })
+        .unwrap_or_default()
+}
+
+/// Digest of the user visible content of an alert, changes whenever the text, cause, effect or severity is edited.
+pub fn alert_content_digest(alert: &gtfs_realtime::Alert) -> String {
+    sha256_hex(&[
+        &translated_text(&alert.header_text),
+        &translated_text(&alert.description_text),
+        &translated_text(&alert.url),
+        &format!("{:?}", alert.cause),
+        &format!("{:?}", alert.effect),
+        &format!("{:?}", alert.severity_level),
+    ])
+}
+
+/// Version string for a whole alerts feed.
+///
+/// Order independent, and only changes when an alert is added, removed or its content is edited.
+pub fn alerts_feed_version(entities: &[FeedEntity]) -> String {
+    let mut entries = entities
+        .iter()
+        .filter_map(|entity| {
+            entity
+                .alert
+                .as_ref()
+                .map(|alert| format!("{}={}", entity.id, alert_content_digest(alert)))
+        })
+        .collect::<Vec<String>>();
+
+    entries.sort();
+
+    let entries = entries.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+
+    sha256_hex(&entries)[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert_entity(id: &str, description: &str) -> FeedEntity {
+        FeedEntity {
+            id: id.to_string(),
+            alert: Some(gtfs_realtime::Alert {
+                description_text: Some(gtfs_realtime::TranslatedString {
+                    translation: vec![gtfs_realtime::translated_string::Translation {
+                        text: description.to_string(),
+                        language: Some("en".to_string()),
+                    }],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        // Pinned value, must never change between releases
+        assert_eq!(
+            stable_alert_id("PAC_SURF", "Track Closures", "Temporary Track Closure"),
+            "PAC_SURF_ff21e636586a557c"
+        );
+        assert_eq!(
+            stable_alert_id("PAC_SURF", "Track Closures", "Temporary Track Closure"),
+            stable_alert_id("PAC_SURF", " Track Closures", "Temporary Track Closure ")
+        );
+        assert_ne!(
+            stable_alert_id("PAC_SURF", "Track Closures", "Temporary Track Closure"),
+            stable_alert_id("PAC_SURF", "Service Updates", "Temporary Track Closure")
+        );
+        assert_ne!(
+            stable_alert_id("PAC_SURF", "ab", "c"),
+            stable_alert_id("PAC_SURF", "a", "bc")
+        );
+    }
+
+    #[test]
+    fn feed_version_tracks_edits() {
+        let a = alert_entity("A", "Trains will not run.");
+        let b = alert_entity("B", "Elevator out of service.");
+        let a_edited = alert_entity("A", "Trains will not run, buses will operate instead.");
+
+        let version = alerts_feed_version(&[a.clone(), b.clone()]);
+
+        assert_eq!(version, alerts_feed_version(&[b.clone(), a.clone()]));
+        assert_ne!(version, alerts_feed_version(&[a_edited, b.clone()]));
+        assert_ne!(version, alerts_feed_version(&[a]));
+    }
+}
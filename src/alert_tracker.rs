@@ -0,0 +1,202 @@
+//! Change detection between consecutive alert feeds.
+//!
+//! Feed every `GtfsAmtrakResults.alerts` message into an [`AlertTracker`] to learn which alerts are new,
+//! which were edited and which disappeared since the previous poll.
+//!
+//! ```
+//! use amtrak_gtfs_rt::alert_tracker::AlertTracker;
+//! # let mut alerts_feed = gtfs_realtime::FeedMessage::default();
+//!
+//! let mut tracker = AlertTracker::new();
+//!
+//! let changes = tracker.ingest(&alerts_feed);
+//!
+//! if !changes.is_empty() {
+//!     // notify riders about changes.added and changes.changed
+//! }
+//!
+//! // keep removed alerts in the published feed once, marked as deleted
+//! changes.append_deleted_entities(&mut alerts_feed);
+//! ```
+
+use gtfs_realtime::{FeedEntity, FeedMessage};
+use std::collections::HashMap;
+
+#[derive(Default, Debug, Clone)]
+pub struct AlertTracker {
+    known: HashMap<String, FeedEntity>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct AlertChanges {
+    /// Alerts that weren't in the previous feed
+    pub added: Vec<FeedEntity>,
+    /// Alerts whose id was already known, but whose content changed
+    pub changed: Vec<FeedEntity>,
+    /// The last known version of alerts missing from the new feed
+    pub removed: Vec<FeedEntity>,
+}
+
+impl AlertChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Entities with `is_deleted` set for every removed alert.
+    /// Only the alert is kept, so consumers don't also delete the trip update or vehicle that carried it.
+    pub fn deleted_entities(&self) -> Vec<FeedEntity> {
+        self.removed
+            .iter()
+            .map(|entity| FeedEntity {
+                id: entity.id.clone(),
+                is_deleted: Some(true),
+                alert: entity.alert.clone(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub fn append_deleted_entities(&self, feed: &mut FeedMessage) {
+        feed.entity.append(&mut self.deleted_entities());
+    }
+}
+
+impl AlertTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compares an alerts feed to the previously ingested one, then remembers it for the next call.
+    ///
+    /// Entities without an alert and entities already marked as deleted are ignored.
+    /// Results are sorted by entity id.
+    pub fn ingest(&mut self, feed: &FeedMessage) -> AlertChanges {
+        let mut changes = AlertChanges::default();
+
+        let mut current: HashMap<String, FeedEntity> = HashMap::new();
+
+        for entity in &feed.entity {
+            if entity.alert.is_none() || entity.is_deleted == Some(true) {
+                continue;
+            }
+
+            current.insert(entity.id.clone(), entity.clone());
+        }
+
+        for (id, entity) in current.iter() {
+            match self.known.get(id) {
+                None => changes.added.push(entity.clone()),
+                Some(previous) if previous.alert != entity.alert => {
+                    changes.changed.push(entity.clone())
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (id, entity) in self.known.iter() {
+            if !current.contains_key(id) {
+                changes.removed.push(entity.clone());
+            }
+        }
+
+        changes.added.sort_by(|a, b| a.id.cmp(&b.id));
+        changes.changed.sort_by(|a, b| a.id.cmp(&b.id));
+        changes.removed.sort_by(|a, b| a.id.cmp(&b.id));
+
+        self.known = current;
+
+        changes
+    }
+
+    /// Ids of the alerts in the last ingested feed.
+    pub fn known_ids(&self) -> impl Iterator<Item = &String> {
+        self.known.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(id: &str, text: &str) -> FeedEntity {
+        FeedEntity {
+            id: id.to_string(),
+            is_deleted: Some(false),
+            alert: Some(gtfs_realtime::Alert {
+                header_text: Some(gtfs_realtime::TranslatedString {
+                    translation: vec![gtfs_realtime::translated_string::Translation {
+                        text: text.to_string(),
+                        language: Some("en".to_string()),
+                    }],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn feed(entity: Vec<FeedEntity>) -> FeedMessage {
+        FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity,
+        }
+    }
+
+    fn ids(entities: &[FeedEntity]) -> Vec<&str> {
+        entities.iter().map(|x| x.id.as_str()).collect()
+    }
+
+    #[test]
+    fn added_changed_removed() {
+        let mut tracker = AlertTracker::new();
+
+        let first = tracker.ingest(&feed(vec![alert("A", "a"), alert("B", "b")]));
+        assert_eq!(ids(&first.added), vec!["A", "B"]);
+        assert!(first.changed.is_empty() && first.removed.is_empty());
+
+        // Same content again, nothing to report
+        assert!(
+            tracker
+                .ingest(&feed(vec![alert("B", "b"), alert("A", "a")]))
+                .is_empty()
+        );
+
+        let second = tracker.ingest(&feed(vec![alert("B", "b edited"), alert("C", "c")]));
+        assert_eq!(ids(&second.added), vec!["C"]);
+        assert_eq!(ids(&second.changed), vec!["B"]);
+        assert_eq!(ids(&second.removed), vec!["A"]);
+
+        let mut output = feed(vec![alert("B", "b edited"), alert("C", "c")]);
+        second.append_deleted_entities(&mut output);
+
+        assert_eq!(output.entity.len(), 3);
+        assert_eq!(output.entity[2].id, "A");
+        assert_eq!(output.entity[2].is_deleted, Some(true));
+
+        // A removed alert is only reported once
+        assert!(
+            tracker
+                .ingest(&feed(vec![alert("B", "b edited"), alert("C", "c")]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn ignores_non_alerts_and_deleted() {
+        let mut tracker = AlertTracker::new();
+
+        let mut deleted = alert("D", "d");
+        deleted.is_deleted = Some(true);
+
+        let vehicle_only = FeedEntity {
+            id: "V".to_string(),
+            vehicle: Some(gtfs_realtime::VehiclePosition::default()),
+            ..Default::default()
+        };
+
+        let changes = tracker.ingest(&feed(vec![deleted, vehicle_only, alert("A", "a")]));
+
+        assert_eq!(ids(&changes.added), vec!["A"]);
+        assert_eq!(tracker.known_ids().collect::<Vec<&String>>(), vec!["A"]);
+    }
+}
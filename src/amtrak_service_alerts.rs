@@ -0,0 +1,501 @@
+//! Amtrak.com service alerts and notices.
+//!
+//! Amtrak publishes nationwide service alerts (detours, bus bridges, cancellations) which aren't tied to a single train.
+//! Both the JSON listing and the rendered HTML listing are supported, pass whichever document you have
+//! to [`parse_amtrak_service_alerts`].
+//!
+//! Alerts are informed to routes by their Amtrak route name, using the same long name mapping as the trip updates.
+//! When the listing doesn't name the routes, route names mentioned in the alert text are used instead,
+//! and alerts without any route are informed to the whole agency.
+
+use crate::advisory_source::{advisory_entity, normalized_text, push_description};
+use crate::{long_name_to_route_id, route_name_to_route_id};
+use gtfs_realtime::FeedEntity;
+use gtfs_structures::Gtfs;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AmtrakServiceAlertsJson {
+    Wrapped { alerts: Vec<AmtrakServiceAlert> },
+    List(Vec<AmtrakServiceAlert>),
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AmtrakServiceAlert {
+    /// Amtrak's id of the alert, keeps the entity id stable when the title is edited
+    pub id: Option<String>,
+    #[serde(alias = "headline")]
+    pub title: String,
+    /// May contain HTML
+    #[serde(alias = "body", alias = "description")]
+    pub content: String,
+    #[serde(alias = "type")]
+    pub category: Option<String>,
+    /// Amtrak route names, e.g. "Coast Starlight"
+    pub routes: Vec<String>,
+    /// Station codes
+    pub stations: Vec<String>,
+    /// ISO 8601
+    pub start_date: Option<String>,
+    /// ISO 8601
+    pub end_date: Option<String>,
+    pub url: Option<String>,
+}
+
+const ID_PREFIX: &str = "AMTRAK";
+const DEFAULT_SECTION: &str = "Service Alerts";
+
+/// Parses an Amtrak.com service alert listing, either the JSON listing or the HTML page.
+pub fn parse_amtrak_service_alerts(
+    document: &str,
+    gtfs: &Gtfs,
+) -> Result<Vec<FeedEntity>, Box<dyn std::error::Error + Sync + Send>> {
+    let trimmed = document.trim_start();
+
+    let alerts = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        parse_amtrak_service_alerts_json(trimmed)?
+    } else {
+        parse_amtrak_service_alerts_html(document)
+    };
+
+    Ok(amtrak_service_alerts_to_gtfs_rt(&alerts, gtfs))
+}
+
+pub async fn fetch_amtrak_service_alerts(
+    client: &reqwest::Client,
+    gtfs: &Gtfs,
+    url: &str,
+) -> Result<Vec<FeedEntity>, Box<dyn std::error::Error + Sync + Send>> {
+    let resp = client.get(url).send().await?;
+    let text = resp.text().await?;
+
+    parse_amtrak_service_alerts(&text, gtfs)
+}
+
+pub fn parse_amtrak_service_alerts_json(
+    text: &str,
+) -> Result<Vec<AmtrakServiceAlert>, serde_json::Error> {
+    let alerts = match serde_json::from_str::<AmtrakServiceAlertsJson>(text)? {
+        AmtrakServiceAlertsJson::Wrapped { alerts } => alerts,
+        AmtrakServiceAlertsJson::List(alerts) => alerts,
+    };
+
+    Ok(alerts
+        .into_iter()
+        .filter(|alert| !alert.title.trim().is_empty())
+        .map(|alert| AmtrakServiceAlert {
+            content: html_to_text(&alert.content),
+            ..alert
+        })
+        .collect())
+}
+
+/// Parses the rendered listing, where every alert is an `.alert-listing__item`.
+/// Routes and stations come from the `data-routes` and `data-stations` attributes, separated by `|`.
+pub fn parse_amtrak_service_alerts_html(text: &str) -> Vec<AmtrakServiceAlert> {
+    let document = Html::parse_document(text);
+
+    let item_selector = Selector::parse(".alert-listing__item").unwrap();
+    let title_selector = Selector::parse(".alert-listing__title").unwrap();
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let category_selector = Selector::parse(".alert-listing__category").unwrap();
+    let body_selector = Selector::parse(".alert-listing__body").unwrap();
+    let time_selector = Selector::parse("time[datetime]").unwrap();
+
+    let split_attr = |item: &ElementRef, attr: &str| -> Vec<String> {
+        item.value()
+            .attr(attr)
+            .map(|value| {
+                value
+                    .split('|')
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut alerts = vec![];
+
+    for item in document.select(&item_selector) {
+        let title_element = match item.select(&title_selector).next() {
+            Some(title_element) => title_element,
+            None => continue,
+        };
+
+        let title = normalized_text(&title_element);
+
+        if title.is_empty() {
+            continue;
+        }
+
+        let mut content = String::new();
+
+        for body in item.select(&body_selector) {
+            for child in body.children().filter_map(ElementRef::wrap) {
+                push_description(&mut content, &child);
+            }
+        }
+
+        let times = item
+            .select(&time_selector)
+            .filter_map(|time| time.value().attr("datetime").map(|x| x.to_string()))
+            .collect::<Vec<String>>();
+
+        alerts.push(AmtrakServiceAlert {
+            id: item.value().attr("data-alert-id").map(|x| x.to_string()),
+            title,
+            content: content.trim().to_string(),
+            category: item
+                .select(&category_selector)
+                .next()
+                .map(|category| normalized_text(&category)),
+            routes: split_attr(&item, "data-routes"),
+            stations: split_attr(&item, "data-stations"),
+            start_date: times.first().cloned(),
+            end_date: times.get(1).cloned(),
+            url: title_element
+                .select(&link_selector)
+                .next()
+                .and_then(|link| link.value().attr("href"))
+                .map(|href| match href.starts_with('/') {
+                    true => format!("https://www.amtrak.com{}", href),
+                    false => href.to_string(),
+                }),
+        });
+    }
+
+    alerts
+}
+
+fn html_to_text(fragment: &str) -> String {
+    if !fragment.contains('<') {
+        return fragment.trim().to_string();
+    }
+
+    let fragment = Html::parse_fragment(fragment);
+    let mut text = String::new();
+
+    for child in fragment.root_element().children() {
+        if let Some(element) = ElementRef::wrap(child) {
+            push_description(&mut text, &element);
+        } else if let Some(loose_text) = child.value().as_text() {
+            let loose_text = loose_text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            if !loose_text.is_empty() {
+                text.push_str(&loose_text);
+                text.push_str("\n\n");
+            }
+        }
+    }
+
+    text.trim().to_string()
+}
+
+/// Removes every whole word occurrence of `name` from `text`, returns whether there was one.
+/// "Cardinal" is found in "the Cardinal is late" but not in "Cardinals game".
+fn take_route_name(text: &mut String, name: &str) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric();
+
+    let occurrences = text
+        .match_indices(name)
+        .filter(|(start, _)| {
+            let before = text[..*start].chars().next_back();
+            let after = text[start + name.len()..].chars().next();
+
+            !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+        })
+        .map(|(start, _)| start)
+        .collect::<Vec<usize>>();
+
+    // Back to front so the earlier indices stay valid
+    for start in occurrences.iter().rev() {
+        text.replace_range(*start..start + name.len(), "\n");
+    }
+
+    !occurrences.is_empty()
+}
+
+fn iso_to_unix(text: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc3339(text)
+        .ok()
+        .and_then(|dt| dt.timestamp().try_into().ok())
+}
+
+pub fn amtrak_service_alerts_to_gtfs_rt(
+    alerts: &[AmtrakServiceAlert],
+    gtfs: &Gtfs,
+) -> Vec<FeedEntity> {
+    let long_names = long_name_to_route_id(gtfs);
+
+    // Longest first, so "Northeast Regional" doesn't also count as a shorter name it contains
+    let mut known_route_names = long_names
+        .keys()
+        .map(|x| x.as_str())
+        .chain(std::iter::once("Gold Runner"))
+        .collect::<Vec<&str>>();
+    known_route_names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+
+    let agency_id = gtfs.agencies.first().and_then(|agency| agency.id.clone());
+
+    alerts
+        .iter()
+        .map(|alert| {
+            let route_names: Vec<String> = match alert.routes.is_empty() {
+                false => alert.routes.clone(),
+                true => {
+                    let mut text = format!("{}\n{}", alert.title, alert.content);
+                    let mut found = vec![];
+
+                    for name in &known_route_names {
+                        if take_route_name(&mut text, name) {
+                            found.push(name.to_string());
+                        }
+                    }
+
+                    found
+                }
+            };
+
+            let mut route_ids = route_names
+                .iter()
+                .filter_map(|name| route_name_to_route_id(&long_names, name))
+                .collect::<Vec<String>>();
+            route_ids.sort();
+            route_ids.dedup();
+
+            let section = alert.category.as_deref().unwrap_or(DEFAULT_SECTION);
+
+            let mut entity = advisory_entity(
+                ID_PREFIX,
+                section,
+                alert.title.clone(),
+                &alert.content,
+                None,
+            );
+
+            if let Some(id) = alert
+                .id
+                .as_deref()
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+            {
+                entity.id = format!("{}_{}", ID_PREFIX, id);
+            }
+
+            let gtfs_alert = entity.alert.as_mut().unwrap();
+
+            let mut informed_entity = route_ids
+                .into_iter()
+                .map(|route_id| gtfs_realtime::EntitySelector {
+                    route_id: Some(route_id),
+                    ..Default::default()
+                })
+                .chain(
+                    alert
+                        .stations
+                        .iter()
+                        .map(|station| gtfs_realtime::EntitySelector {
+                            stop_id: Some(station.clone()),
+                            ..Default::default()
+                        }),
+                )
+                .collect::<Vec<gtfs_realtime::EntitySelector>>();
+
+            if informed_entity.is_empty() {
+                informed_entity.push(gtfs_realtime::EntitySelector {
+                    agency_id: agency_id.clone(),
+                    ..Default::default()
+                });
+            }
+
+            gtfs_alert.informed_entity = informed_entity;
+
+            let start = alert.start_date.as_deref().and_then(iso_to_unix);
+            let end = alert.end_date.as_deref().and_then(iso_to_unix);
+
+            if start.is_some() || end.is_some() {
+                gtfs_alert.active_period = vec![gtfs_realtime::TimeRange { start, end }];
+            }
+
+            gtfs_alert.url = alert
+                .url
+                .as_ref()
+                .map(|url| gtfs_realtime::TranslatedString {
+                    translation: vec![gtfs_realtime::translated_string::Translation {
+                        text: url.clone(),
+                        language: Some("en".to_string()),
+                    }],
+                });
+
+            entity
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::advisory_source::test_helpers::{description, header};
+    use gtfs_realtime::alert::Effect;
+
+    fn test_gtfs() -> Gtfs {
+        let mut routes = std::collections::HashMap::new();
+
+        for (id, name) in [
+            ("88", "Northeast Regional"),
+            ("94", "Coast Starlight"),
+            ("40751", "Empire Builder"),
+            ("12", "Cardinal"),
+        ] {
+            routes.insert(
+                id.to_string(),
+                gtfs_structures::Route {
+                    id: id.to_string(),
+                    long_name: Some(name.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        Gtfs {
+            routes,
+            agencies: vec![gtfs_structures::Agency {
+                id: Some("51".to_string()),
+                name: "Amtrak".to_string(),
+                url: "https://www.amtrak.com".to_string(),
+                timezone: "America/New_York".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn route_ids(entity: &FeedEntity) -> Vec<String> {
+        entity
+            .alert
+            .as_ref()
+            .unwrap()
+            .informed_entity
+            .iter()
+            .filter_map(|x| x.route_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_fixture() {
+        let json = include_str!("../tests/fixtures/amtrak_service_alerts.json");
+
+        let alerts = parse_amtrak_service_alerts(json, &test_gtfs()).unwrap();
+
+        assert_eq!(alerts.len(), 3);
+
+        // Listing ids are kept
+        assert_eq!(alerts[0].id, "AMTRAK_coast-starlight-bus-bridge-2026-01");
+
+        // Explicit routes
+        assert_eq!(
+            header(&alerts[0]),
+            "Coast Starlight Bus Bridge Between Sacramento and Klamath Falls"
+        );
+        assert_eq!(route_ids(&alerts[0]), vec!["94".to_string()]);
+        assert_eq!(
+            alerts[0].alert.as_ref().unwrap().effect,
+            Some(Effect::ModifiedService as i32)
+        );
+        assert_eq!(
+            alerts[0].alert.as_ref().unwrap().active_period,
+            vec![gtfs_realtime::TimeRange {
+                start: Some(1768118400),
+                end: Some(1768291200),
+            }]
+        );
+        // HTML in the body is flattened
+        assert!(!description(&alerts[0]).contains('<'));
+        assert!(description(&alerts[0]).contains("- Train 11 will terminate at Sacramento"));
+
+        // Routes found in the text, including Gold Runner which isn't in the Amtrak GTFS
+        assert_eq!(
+            route_ids(&alerts[1]),
+            vec!["88".to_string(), "GR".to_string()]
+        );
+        assert_eq!(
+            alerts[1].alert.as_ref().unwrap().informed_entity[2].stop_id,
+            Some("NYP".to_string())
+        );
+
+        // No routes at all, informs the agency
+        let informed = &alerts[2].alert.as_ref().unwrap().informed_entity;
+        assert_eq!(informed.len(), 1);
+        assert_eq!(informed[0].agency_id, Some("51".to_string()));
+    }
+
+    #[test]
+    fn test_html_fixture() {
+        let html = include_str!("../tests/fixtures/amtrak_service_alerts.html");
+
+        let alerts = parse_amtrak_service_alerts(html, &test_gtfs()).unwrap();
+
+        assert_eq!(alerts.len(), 2);
+
+        assert_eq!(
+            header(&alerts[0]),
+            "Empire Builder Detour Between Minot and Fargo"
+        );
+        assert_eq!(route_ids(&alerts[0]), vec!["40751".to_string()]);
+        assert_eq!(
+            alerts[0].alert.as_ref().unwrap().effect,
+            Some(Effect::Detour as i32)
+        );
+        assert_eq!(
+            alerts[0]
+                .alert
+                .as_ref()
+                .unwrap()
+                .url
+                .as_ref()
+                .unwrap()
+                .translation[0]
+                .text,
+            "https://www.amtrak.com/alerts/empire-builder-detour"
+        );
+
+        assert_eq!(
+            header(&alerts[1]),
+            "Northeast Regional Trains 171 and 176 Canceled"
+        );
+        assert_eq!(route_ids(&alerts[1]), vec!["88".to_string()]);
+        assert_eq!(
+            alerts[1].alert.as_ref().unwrap().effect,
+            Some(Effect::NoService as i32)
+        );
+    }
+
+    #[test]
+    fn route_names_are_whole_words() {
+        let alerts = [AmtrakServiceAlert {
+            title: "Extra Trains for the Cardinals Game".to_string(),
+            content: "The Cardinal and Northeast Regional trains run as scheduled.".to_string(),
+            ..Default::default()
+        }];
+
+        let entities = amtrak_service_alerts_to_gtfs_rt(&alerts, &test_gtfs());
+        assert_eq!(
+            route_ids(&entities[0]),
+            vec!["12".to_string(), "88".to_string()]
+        );
+
+        let alerts = [AmtrakServiceAlert {
+            title: "Extra Trains for the Cardinals Game".to_string(),
+            ..Default::default()
+        }];
+
+        let entities = amtrak_service_alerts_to_gtfs_rt(&alerts, &test_gtfs());
+        assert!(route_ids(&entities[0]).is_empty());
+        // Without a listing id, the id comes from the title
+        assert!(entities[0].id.starts_with("AMTRAK_"));
+    }
+}
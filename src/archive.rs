@@ -0,0 +1,567 @@
+//! Local archive of raw payloads and produced feeds, for analysis and replay.
+//!
+//! Every snapshot is a set of gzip files named after the poll's unix time in milliseconds, so polls in the
+//! same second don't collide, in one directory per UTC hour:
+//!
+//! ```text
+//! <root>/2026/01/19/17/1768842000500-track-a-train.txt.gz
+//! <root>/2026/01/19/17/1768842000500-asm.json.gz
+//! <root>/2026/01/19/17/1768842000500-advisories.pb.gz
+//! <root>/2026/01/19/17/1768842000500-trip-updates.pb.gz
+//! <root>/2026/01/19/17/1768842000500-vehicle-positions.pb.gz
+//! <root>/2026/01/19/17/1768842000500-alerts.pb.gz
+//! ```
+//!
+//! Only the encrypted Track-A-Train response is stored, as received; it's decrypted again when read.
+//! Feeds are stored as encoded protobuf `FeedMessage`s. Files missing from a snapshot, e.g. the ASM response
+//! when it couldn't be fetched, are `None` when read back.
+
+use crate::{AmtrakPayloads, GtfsAmtrakResults, decrypt_track_a_train};
+use chrono::{DateTime, NaiveDate};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use gtfs_realtime::FeedMessage;
+use prost::Message;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    /// A stored feed isn't a valid `FeedMessage`
+    Decode(prost::DecodeError),
+    /// A stored text file isn't valid UTF-8
+    Utf8(std::string::FromUtf8Error),
+    /// The time can't be represented as a date
+    InvalidTimestamp(i64),
+    /// A snapshot was already archived at this time, in milliseconds
+    SnapshotExists(i64),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "archive io error: {}", e),
+            ArchiveError::Decode(e) => write!(f, "invalid feed in archive: {}", e),
+            ArchiveError::Utf8(e) => write!(f, "invalid text in archive: {}", e),
+            ArchiveError::InvalidTimestamp(timestamp_ms) => {
+                write!(f, "invalid snapshot timestamp {}", timestamp_ms)
+            }
+            ArchiveError::SnapshotExists(timestamp_ms) => {
+                write!(f, "snapshot {} already archived", timestamp_ms)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<prost::DecodeError> for ArchiveError {
+    fn from(e: prost::DecodeError) -> Self {
+        ArchiveError::Decode(e)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for ArchiveError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        ArchiveError::Utf8(e)
+    }
+}
+
+const TRACK_A_TRAIN: &str = "track-a-train.txt";
+const ASM: &str = "asm.json";
+const ADVISORIES: &str = "advisories.pb";
+const TRIP_UPDATES: &str = "trip-updates.pb";
+const VEHICLE_POSITIONS: &str = "vehicle-positions.pb";
+const ALERTS: &str = "alerts.pb";
+
+/// One archived poll.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Unix time of the poll, milliseconds
+    pub timestamp_ms: i64,
+    /// Encrypted Track-A-Train response
+    pub track_a_train: Option<String>,
+    /// Decrypted Track-A-Train response. Not archived, decrypted from `track_a_train` when read.
+    pub track_a_train_geojson: Option<String>,
+    pub asm: Option<String>,
+    pub advisories: Option<FeedMessage>,
+    pub trip_updates: Option<FeedMessage>,
+    pub vehicle_positions: Option<FeedMessage>,
+    pub alerts: Option<FeedMessage>,
+}
+
+impl Snapshot {
+    /// Unix time of the poll, seconds.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp_ms.div_euclid(1000)
+    }
+
+    /// The payloads of the poll, to process them again. `None` without a Track-A-Train response.
+    pub fn payloads(&self) -> Option<AmtrakPayloads> {
+        Some(AmtrakPayloads {
+            track_a_train: self.track_a_train.clone()?,
+            asm: self.asm.clone(),
+            advisories: self
+                .advisories
+                .as_ref()
+                .map(|advisories| advisories.entity.clone())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The feeds produced for the poll, when all of them were archived.
+    pub fn results(&self) -> Option<GtfsAmtrakResults> {
+        Some(GtfsAmtrakResults {
+            trip_updates: self.trip_updates.clone()?,
+            vehicle_positions: self.vehicle_positions.clone()?,
+            alerts: self.alerts.clone()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Archive {
+    root: PathBuf,
+}
+
+/// Subdirectories named with a number, like partitions. Missing directories have none.
+fn numbered_subdirectories(path: &Path) -> Result<Vec<(u32, PathBuf)>, ArchiveError> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut subdirectories = vec![];
+
+    for entry in entries {
+        let entry = entry?;
+
+        if let Some(number) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+            .filter(|_| entry.path().is_dir())
+        {
+            subdirectories.push((number, entry.path()));
+        }
+    }
+
+    Ok(subdirectories)
+}
+
+impl Archive {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Archive { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory of the hour containing `timestamp_ms`.
+    fn partition(&self, timestamp_ms: i64) -> Result<PathBuf, ArchiveError> {
+        let datetime = DateTime::from_timestamp_millis(timestamp_ms)
+            .ok_or(ArchiveError::InvalidTimestamp(timestamp_ms))?;
+
+        Ok(self.root.join(datetime.format("%Y/%m/%d/%H").to_string()))
+    }
+
+    fn file_path(&self, timestamp_ms: i64, kind: &str) -> Result<PathBuf, ArchiveError> {
+        Ok(self
+            .partition(timestamp_ms)?
+            .join(format!("{}-{}.gz", timestamp_ms, kind)))
+    }
+
+    fn write_file(
+        &self,
+        timestamp_ms: i64,
+        kind: &str,
+        contents: &[u8],
+    ) -> Result<(), ArchiveError> {
+        let path = self.file_path(timestamp_ms, kind)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Readers never see a partially written file
+        let temporary_path = path.with_extension("gz.tmp");
+
+        let mut encoder =
+            GzEncoder::new(fs::File::create(&temporary_path)?, Compression::default());
+        encoder.write_all(contents)?;
+        encoder.finish()?;
+
+        fs::rename(&temporary_path, &path)?;
+
+        Ok(())
+    }
+
+    fn read_file(&self, timestamp_ms: i64, kind: &str) -> Result<Option<Vec<u8>>, ArchiveError> {
+        let file = match fs::File::open(self.file_path(timestamp_ms, kind)?) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut contents = vec![];
+        GzDecoder::new(file).read_to_end(&mut contents)?;
+
+        Ok(Some(contents))
+    }
+
+    fn read_text(&self, timestamp_ms: i64, kind: &str) -> Result<Option<String>, ArchiveError> {
+        match self.read_file(timestamp_ms, kind)? {
+            Some(contents) => Ok(Some(String::from_utf8(contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_feed(
+        &self,
+        timestamp_ms: i64,
+        kind: &str,
+    ) -> Result<Option<FeedMessage>, ArchiveError> {
+        match self.read_file(timestamp_ms, kind)? {
+            Some(contents) => Ok(Some(FeedMessage::decode(contents.as_slice())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Archives the raw payloads of a poll at `timestamp_ms`, unix milliseconds, and the feeds produced from
+    /// them if any.
+    ///
+    /// A snapshot is never overwritten, so its files all come from the same poll.
+    pub fn write_snapshot(
+        &self,
+        timestamp_ms: i64,
+        payloads: &AmtrakPayloads,
+        results: Option<&GtfsAmtrakResults>,
+    ) -> Result<(), ArchiveError> {
+        if self.file_path(timestamp_ms, TRACK_A_TRAIN)?.exists() {
+            return Err(ArchiveError::SnapshotExists(timestamp_ms));
+        }
+
+        self.write_file(
+            timestamp_ms,
+            TRACK_A_TRAIN,
+            payloads.track_a_train.as_bytes(),
+        )?;
+
+        if let Some(asm) = &payloads.asm {
+            self.write_file(timestamp_ms, ASM, asm.as_bytes())?;
+        }
+
+        let advisories = FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity: payloads.advisories.clone(),
+        };
+
+        self.write_file(timestamp_ms, ADVISORIES, &advisories.encode_to_vec())?;
+
+        if let Some(results) = results {
+            self.write_results(timestamp_ms, results)?;
+        }
+
+        Ok(())
+    }
+
+    /// Archives the feeds produced for a poll, at its time in milliseconds. Only called by
+    /// [`Archive::write_snapshot`], which makes sure the snapshot is new.
+    fn write_results(
+        &self,
+        timestamp_ms: i64,
+        results: &GtfsAmtrakResults,
+    ) -> Result<(), ArchiveError> {
+        self.write_file(
+            timestamp_ms,
+            TRIP_UPDATES,
+            &results.trip_updates.encode_to_vec(),
+        )?;
+        self.write_file(
+            timestamp_ms,
+            VEHICLE_POSITIONS,
+            &results.vehicle_positions.encode_to_vec(),
+        )?;
+        self.write_file(timestamp_ms, ALERTS, &results.alerts.encode_to_vec())?;
+
+        Ok(())
+    }
+
+    /// Reads every file of a snapshot, by its time in milliseconds.
+    pub fn read_snapshot(&self, timestamp_ms: i64) -> Result<Snapshot, ArchiveError> {
+        let track_a_train = self.read_text(timestamp_ms, TRACK_A_TRAIN)?;
+
+        Ok(Snapshot {
+            timestamp_ms,
+            track_a_train_geojson: track_a_train
+                .as_deref()
+                .and_then(|track_a_train| decrypt_track_a_train(track_a_train).ok()),
+            track_a_train,
+            asm: self.read_text(timestamp_ms, ASM)?,
+            advisories: self.read_feed(timestamp_ms, ADVISORIES)?,
+            trip_updates: self.read_feed(timestamp_ms, TRIP_UPDATES)?,
+            vehicle_positions: self.read_feed(timestamp_ms, VEHICLE_POSITIONS)?,
+            alerts: self.read_feed(timestamp_ms, ALERTS)?,
+        })
+    }
+
+    /// Times of the snapshots within `range`, oldest first. Both are unix milliseconds.
+    pub fn snapshot_times(&self, range: Range<i64>) -> Result<Vec<i64>, ArchiveError> {
+        let mut timestamps = BTreeSet::new();
+
+        for (hour_start, directory) in self.hour_partitions()? {
+            // Skip hours entirely outside of the range
+            if hour_start + 3_600_000 <= range.start || hour_start >= range.end {
+                continue;
+            }
+
+            for entry in fs::read_dir(directory)? {
+                let name = entry?.file_name();
+
+                let Some(timestamp_ms) = name
+                    .to_str()
+                    .filter(|name| name.ends_with(".gz"))
+                    .and_then(|name| name.split('-').next())
+                    .and_then(|timestamp_ms| timestamp_ms.parse::<i64>().ok())
+                else {
+                    continue;
+                };
+
+                if range.contains(&timestamp_ms) {
+                    timestamps.insert(timestamp_ms);
+                }
+            }
+        }
+
+        Ok(timestamps.into_iter().collect())
+    }
+
+    /// Every hour directory of the archive, with the unix time in milliseconds the hour starts at.
+    fn hour_partitions(&self) -> Result<Vec<(i64, PathBuf)>, ArchiveError> {
+        let mut partitions = vec![];
+
+        for (year, year_path) in numbered_subdirectories(&self.root)? {
+            for (month, month_path) in numbered_subdirectories(&year_path)? {
+                for (day, day_path) in numbered_subdirectories(&month_path)? {
+                    for (hour, hour_path) in numbered_subdirectories(&day_path)? {
+                        let hour_start = NaiveDate::from_ymd_opt(year as i32, month, day)
+                            .and_then(|date| date.and_hms_opt(hour, 0, 0));
+
+                        if let Some(hour_start) = hour_start {
+                            partitions.push((hour_start.and_utc().timestamp_millis(), hour_path));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(partitions)
+    }
+
+    /// Iterates the snapshots within `range`, unix milliseconds, oldest first.
+    pub fn snapshots(
+        &self,
+        range: Range<i64>,
+    ) -> Result<impl Iterator<Item = Result<Snapshot, ArchiveError>> + '_, ArchiveError> {
+        Ok(self
+            .snapshot_times(range)?
+            .into_iter()
+            .map(|timestamp_ms| self.read_snapshot(timestamp_ms)))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::*;
+
+    /// An empty archive in a new temporary directory.
+    pub fn temporary_archive(name: &str) -> Archive {
+        let root =
+            std::env::temp_dir().join(format!("amtrak-gtfs-rt-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&root);
+
+        Archive::new(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_helpers::temporary_archive;
+    use super::*;
+
+    // 2026-01-19 17:00:00 UTC
+    const HOUR: i64 = 1_768_842_000_000;
+    const SECOND: i64 = 1000;
+
+    fn results(entity_id: &str) -> GtfsAmtrakResults {
+        let feed = FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity: vec![gtfs_realtime::FeedEntity {
+                id: entity_id.to_string(),
+                ..Default::default()
+            }],
+        };
+
+        GtfsAmtrakResults {
+            trip_updates: feed.clone(),
+            vehicle_positions: feed.clone(),
+            alerts: feed,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let archive = temporary_archive("round-trip");
+
+        let payloads = AmtrakPayloads {
+            track_a_train: "not encrypted".to_string(),
+            asm: Some("{}".to_string()),
+            advisories: results("advisory").alerts.entity,
+        };
+
+        archive
+            .write_snapshot(HOUR + 30 * SECOND, &payloads, Some(&results("20260119-11")))
+            .unwrap();
+
+        assert!(
+            archive
+                .root()
+                .join("2026/01/19/17/1768842030000-asm.json.gz")
+                .exists()
+        );
+
+        let snapshot = archive.read_snapshot(HOUR + 30 * SECOND).unwrap();
+
+        assert_eq!(snapshot.timestamp_ms, HOUR + 30 * SECOND);
+        assert_eq!(snapshot.track_a_train.as_deref(), Some("not encrypted"));
+        // Couldn't be decrypted
+        assert_eq!(snapshot.track_a_train_geojson, None);
+        assert_eq!(
+            fs::read_dir(archive.root().join("2026/01/19/17"))
+                .unwrap()
+                .count(),
+            6
+        );
+        assert_eq!(snapshot.payloads().unwrap().asm, payloads.asm);
+        assert_eq!(snapshot.payloads().unwrap().advisories[0].id, "advisory");
+        assert_eq!(
+            snapshot.results().unwrap().trip_updates.entity[0].id,
+            "20260119-11"
+        );
+
+        // Nothing archived at that time
+        let snapshot = archive.read_snapshot(HOUR).unwrap();
+        assert!(snapshot.payloads().is_none());
+        assert!(snapshot.results().is_none());
+
+        fs::remove_dir_all(archive.root()).unwrap();
+    }
+
+    #[test]
+    fn time_ranges() {
+        let archive = temporary_archive("time-ranges");
+
+        let payloads = AmtrakPayloads::default();
+
+        // Across three hours and a day boundary
+        let times = [
+            HOUR - 10 * SECOND,
+            HOUR + 30 * SECOND,
+            HOUR + 3599 * SECOND,
+            HOUR + 7 * 3600 * SECOND,
+        ];
+
+        for time in times {
+            archive.write_snapshot(time, &payloads, None).unwrap();
+        }
+
+        assert_eq!(archive.snapshot_times(0..i64::MAX).unwrap(), times.to_vec());
+        assert_eq!(
+            archive
+                .snapshot_times(HOUR - 3600 * SECOND..HOUR + 8 * 3600 * SECOND)
+                .unwrap(),
+            times.to_vec()
+        );
+        assert_eq!(
+            archive
+                .snapshot_times(HOUR + 30 * SECOND..HOUR + 3599 * SECOND)
+                .unwrap(),
+            vec![HOUR + 30 * SECOND]
+        );
+        assert!(archive.snapshot_times(HOUR..HOUR).unwrap().is_empty());
+
+        let snapshots = archive
+            .snapshots(HOUR..HOUR + 3600 * SECOND)
+            .unwrap()
+            .collect::<Result<Vec<Snapshot>, ArchiveError>>()
+            .unwrap();
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[1].timestamp_ms, HOUR + 3599 * SECOND);
+        assert_eq!(snapshots[1].track_a_train.as_deref(), Some(""));
+
+        fs::remove_dir_all(archive.root()).unwrap();
+    }
+
+    #[test]
+    fn polls_in_the_same_second() {
+        let archive = temporary_archive("same-second");
+
+        let with_asm = AmtrakPayloads {
+            track_a_train: "first".to_string(),
+            asm: Some("{}".to_string()),
+            ..Default::default()
+        };
+
+        let without_asm = AmtrakPayloads {
+            track_a_train: "second".to_string(),
+            ..Default::default()
+        };
+
+        archive.write_snapshot(HOUR, &with_asm, None).unwrap();
+        archive
+            .write_snapshot(HOUR + 500, &without_asm, None)
+            .unwrap();
+
+        assert_eq!(
+            archive.snapshot_times(HOUR..HOUR + SECOND).unwrap(),
+            vec![HOUR, HOUR + 500]
+        );
+
+        // The second poll doesn't get the first poll's ASM response
+        let snapshot = archive.read_snapshot(HOUR + 500).unwrap();
+        assert_eq!(snapshot.track_a_train.as_deref(), Some("second"));
+        assert_eq!(snapshot.asm, None);
+
+        // Snapshots aren't overwritten
+        assert!(matches!(
+            archive.write_snapshot(HOUR, &without_asm, None),
+            Err(ArchiveError::SnapshotExists(HOUR))
+        ));
+        assert_eq!(
+            archive
+                .read_snapshot(HOUR)
+                .unwrap()
+                .track_a_train
+                .as_deref(),
+            Some("first")
+        );
+
+        fs::remove_dir_all(archive.root()).unwrap();
+    }
+}
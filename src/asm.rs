@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::alert_classifier::classify_alert;
 use gtfs_realtime::Alert as GtfsRtAlert;
 use serde::Deserialize;
 use serde::Serialize;
@@ -7,7 +8,7 @@ use serde::Serialize;
 // New ASM schema
 pub type Welcome = Vec<WelcomeElement>;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct WelcomeElement {
     train_id: String,
     railroad: Railroad,
@@ -28,13 +29,13 @@ pub struct WelcomeElement {
     alerts: Option<Vec<Alert>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Alert {
     record_time: i64,
     text: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Location {
     latitude: f64,
     longitude: f64,
@@ -42,7 +43,7 @@ pub struct Location {
     speed: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Railroad {
     Amtrak,
@@ -50,7 +51,7 @@ pub enum Railroad {
     ViaRail,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Stop {
     code: String,
     miles: i64,
@@ -61,7 +62,7 @@ pub struct Stop {
     arrive: Option<Arrive>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Arrive {
     variance: i64,
     times_compared: TimesCompared,
@@ -69,7 +70,7 @@ pub struct Arrive {
     arrive_type: Type,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Type {
     #[serde(rename = "ACTUAL")]
     Actual,
@@ -77,7 +78,7 @@ pub enum Type {
     Estimated,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum TimesCompared {
     Departure,
@@ -89,6 +90,27 @@ pub enum TimesCompared {
     TerminalArrival,
 }
 
+impl WelcomeElement {
+    pub fn number(&self) -> i64 {
+        self.number
+    }
+
+    /// Local date the train departed (or is scheduled to depart) its origin.
+    pub fn origin_date(&self) -> Option<chrono::NaiveDate> {
+        chrono::NaiveDate::parse_from_str(&self.origin_date, "%Y-%m-%d").ok()
+    }
+
+    /// True when every stop of the train is canceled, ASM's confirmation that the whole trip isn't running.
+    pub fn is_fully_canceled(&self) -> bool {
+        !self.stops.is_empty() && self.stops.iter().all(|stop| stop.canceled)
+    }
+
+    /// Scheduled departure from the first stop, unix seconds.
+    pub fn scheduled_origin_departure(&self) -> Option<i64> {
+        self.stops.first().and_then(|stop| stop.sched_depart)
+    }
+}
+
 // Backwards-compatible aliases for existing code
 pub type AsmRoot = Welcome;
 pub type AsmAlert = Alert;
@@ -127,7 +149,6 @@ pub fn asm_alert_to_gtfs_rt(
         return None;
     }
 
-    let mut alert = gtfs_realtime::Alert::default();
     let mut description = gtfs_realtime::TranslatedString::default();
 
     let text = asm_alerts
@@ -143,9 +164,15 @@ pub fn asm_alert_to_gtfs_rt(
             language: Some("en".to_string()),
         });
 
-    Some(GtfsRtAlert {
+    let classification = classify_alert(None, "", &text);
+
+    let mut alert = GtfsRtAlert {
         description_text: Some(description),
         informed_entity: vec![informed_entity],
         ..Default::default()
-    })
+    };
+
+    classification.apply(&mut alert);
+
+    Some(alert)
 }
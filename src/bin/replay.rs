@@ -0,0 +1,139 @@
+//! Replays an archive through the converter and reports how the feeds differ from the archived ones.
+//!
+//! Usage: `replay <archive directory> <gtfs zip> [--from <unix time>] [--to <unix time>] [--engine]`
+//!
+//! Exits with an error when a snapshot couldn't be replayed or produced different feeds.
+
+use amtrak_gtfs_rt::archive::Archive;
+use amtrak_gtfs_rt::engine::AmtrakFeedEngine;
+use amtrak_gtfs_rt::replay::{ReplayedSnapshot, replay, replay_with_engine};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: replay <archive directory> <gtfs zip> [--from <unix time>] [--to <unix time>] [--engine]";
+
+struct Arguments {
+    archive: String,
+    gtfs: String,
+    from: i64,
+    to: i64,
+    engine: bool,
+}
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut paths = vec![];
+    let mut from = 0;
+    let mut to = i64::MAX;
+    let mut engine = false;
+
+    let mut arguments = std::env::args().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--from" | "--to" => {
+                let time = arguments
+                    .next()
+                    .and_then(|time| time.parse::<i64>().ok())
+                    .ok_or(format!("{} needs a unix time", argument))?;
+
+                if argument == "--from" {
+                    from = time;
+                } else {
+                    to = time;
+                }
+            }
+            "--engine" => engine = true,
+            _ => paths.push(argument),
+        }
+    }
+
+    let [archive, gtfs]: [String; 2] = paths.try_into().map_err(|_| USAGE.to_string())?;
+
+    Ok(Arguments {
+        archive,
+        gtfs,
+        from,
+        to,
+        engine,
+    })
+}
+
+fn main() -> ExitCode {
+    let arguments = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let gtfs = match gtfs_structures::Gtfs::from_path(&arguments.gtfs) {
+        Ok(gtfs) => Arc::new(gtfs),
+        Err(e) => {
+            eprintln!("Error reading GTFS {}, {}", arguments.gtfs, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let archive = Archive::new(&arguments.archive);
+    let range = arguments.from.saturating_mul(1000)..arguments.to.saturating_mul(1000);
+
+    let mut engine = AmtrakFeedEngine::new(gtfs.clone());
+
+    let replayed: Box<dyn Iterator<Item = Result<ReplayedSnapshot, _>>> = if arguments.engine {
+        match replay_with_engine(&mut engine, &archive, range) {
+            Ok(replayed) => Box::new(replayed),
+            Err(e) => {
+                eprintln!("Error reading archive, {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        match replay(&gtfs, &archive, range, engine.config.repair_strategy) {
+            Ok(replayed) => Box::new(replayed),
+            Err(e) => {
+                eprintln!("Error reading archive, {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    };
+
+    let mut snapshots = 0;
+    let mut failures = 0;
+
+    for replayed in replayed {
+        snapshots += 1;
+
+        match replayed {
+            Ok(replayed) => match replayed.diff {
+                Some(diff) if !diff.is_empty() => {
+                    failures += 1;
+                    println!("{}: {}", replayed.timestamp_ms, diff);
+                }
+                Some(_) => println!("{}: identical", replayed.timestamp_ms),
+                None => println!(
+                    "{}: {} trip updates, {} vehicles, {} alerts",
+                    replayed.timestamp_ms,
+                    replayed.results.trip_updates.entity.len(),
+                    replayed.results.vehicle_positions.entity.len(),
+                    replayed.results.alerts.entity.len()
+                ),
+            },
+            Err(e) => {
+                failures += 1;
+                eprintln!("Error replaying snapshot, {}", e);
+            }
+        }
+    }
+
+    println!(
+        "{} snapshots replayed, {} differ or failed",
+        snapshots, failures
+    );
+
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
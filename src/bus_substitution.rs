@@ -0,0 +1,149 @@
+//! Stations served by bus instead of the train.
+//!
+//! Track-A-Train lists Thruway connections and bus bridges in the station list of the train, flagged with `bus`.
+//! Those stops aren't served by the rail trip, so they are removed from its trip update (or SKIPPED when the
+//! scheduled rail trip calls there), and published as a NEW trip `{entity id}-bus` so riders can see that
+//! the segment is run by bus.
+
+use crate::AmtrakArrivalJson;
+use gtfs_realtime::FeedEntity;
+use gtfs_realtime::trip_update::StopTimeUpdate;
+
+/// Splits stop time updates, in the same order as `stops`, into the rail trip's updates and the bus stops.
+///
+/// Bus stops with a `stop_sequence` belong to the scheduled rail trip, and stay in it as SKIPPED.
+pub(crate) fn split_bus_stops(
+    updates: Vec<StopTimeUpdate>,
+    stops: &[AmtrakArrivalJson],
+) -> (Vec<StopTimeUpdate>, Vec<StopTimeUpdate>) {
+    let mut rail = vec![];
+    let mut bus = vec![];
+
+    for (update, stop) in updates.into_iter().zip(stops) {
+        if !stop.bus {
+            rail.push(update);
+            continue;
+        }
+
+        if update.stop_sequence.is_some() {
+            rail.push(StopTimeUpdate {
+                stop_sequence: update.stop_sequence,
+                stop_id: update.stop_id.clone(),
+                schedule_relationship: Some(
+                    gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship::Skipped
+                        as i32,
+                ),
+                ..Default::default()
+            });
+        }
+
+        bus.push(StopTimeUpdate {
+            // Sequences of the rail trip don't apply to the bus trip
+            stop_sequence: None,
+            ..update
+        });
+    }
+
+    (rail, bus)
+}
+
+/// The NEW trip serving the bus stops of a train, identified as `{entity_id}-bus`.
+pub(crate) fn bus_replacement_entity(
+    entity_id: &str,
+    train_num: Option<String>,
+    route_id: Option<String>,
+    start_date: &str,
+    timestamp: Option<u64>,
+    bus_stops: Vec<StopTimeUpdate>,
+) -> FeedEntity {
+    let id = format!("{}-bus", entity_id);
+
+    FeedEntity {
+        id: id.clone(),
+        is_deleted: Some(false),
+        trip_update: Some(gtfs_realtime::TripUpdate {
+            trip: gtfs_realtime::TripDescriptor {
+                trip_id: Some(id),
+                route_id,
+                direction_id: None,
+                start_time: None,
+                start_date: Some(start_date.to_string()),
+                modified_trip: None,
+                schedule_relationship: Some(
+                    gtfs_realtime::trip_descriptor::ScheduleRelationship::New as i32,
+                ),
+            },
+            vehicle: None,
+            stop_time_update: bus_stops,
+            timestamp,
+            delay: None,
+            trip_properties: Some(gtfs_realtime::trip_update::TripProperties {
+                trip_id: None,
+                start_date: Some(start_date.to_string()),
+                start_time: None,
+                shape_id: None,
+                trip_headsign: None,
+                trip_short_name: train_num,
+            }),
+        }),
+        vehicle: None,
+        alert: None,
+        stop: None,
+        shape: None,
+        trip_modifications: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(bus: &[bool]) -> Vec<AmtrakArrivalJson> {
+        bus.iter()
+            .enumerate()
+            .map(|(i, bus)| {
+                let mut station = crate::test_helpers::station(&format!("S{}", i), None, None, "");
+                station["bus"] = (*bus).into();
+                serde_json::from_value(station).unwrap()
+            })
+            .collect()
+    }
+
+    fn update(stop_id: &str, stop_sequence: Option<u32>) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            stop_sequence,
+            arrival: Some(gtfs_realtime::trip_update::StopTimeEvent {
+                time: Some(1768845600),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bus_stops_leave_the_rail_trip() {
+        let (rail, bus) = split_bus_stops(
+            vec![
+                update("S0", Some(10)),
+                update("S1", Some(20)),
+                update("S2", None),
+            ],
+            &stops(&[false, true, true]),
+        );
+
+        // S1 is scheduled by rail, S2 is a Thruway connection
+        assert_eq!(rail.len(), 2);
+        assert_eq!(rail[1].stop_id.as_deref(), Some("S1"));
+        assert_eq!(rail[1].schedule_relationship, Some(1));
+        assert!(rail[1].arrival.is_none());
+
+        assert_eq!(
+            bus.iter()
+                .map(|x| (x.stop_id.as_deref().unwrap(), x.stop_sequence))
+                .collect::<Vec<(&str, Option<u32>)>>(),
+            vec![("S1", None), ("S2", None)]
+        );
+        assert_eq!(bus[0].arrival.as_ref().unwrap().time, Some(1768845600));
+    }
+}
@@ -0,0 +1,211 @@
+//! Whole trip cancellations.
+//!
+//! Track-A-Train keeps listing some canceled trains with every stop marked `Canceled`, those become
+//! CANCELED trips in [`crate::feature_to_gtfs_unified`]. Other canceled trains simply never show up.
+//! For those, a scheduled trip is only reported as canceled once its departure time has passed
+//! and ASM confirms that every stop is canceled, so a train that is merely missing from Track-A-Train isn't canceled by mistake.
+//! The cancellation is dropped once the trip would have reached its terminal, ASM keeps old trains around for days.
+
+use crate::AmtrakArrivalJson;
+use crate::asm::AsmRoot;
+use crate::schedule::scheduled_trip_for_train;
+use gtfs_realtime::FeedEntity;
+use gtfs_realtime::trip_descriptor::ScheduleRelationship;
+use gtfs_structures::Gtfs;
+use std::collections::HashSet;
+
+/// Whether every stop in a Track-A-Train station list is canceled.
+pub(crate) fn all_stops_canceled(stops: &[AmtrakArrivalJson]) -> bool {
+    !stops.is_empty() && stops.iter().all(|stop| stop.schcmnt == "Canceled")
+}
+
+/// Builds CANCELED trip updates for trains ASM reports as fully canceled which are absent from Track-A-Train,
+/// between their scheduled departure and their scheduled arrival at the terminal.
+///
+/// `present_entity_ids` are the `{yyyymmdd}-{train number}` entity ids produced from Track-A-Train.
+/// `now` is unix seconds.
+pub fn canceled_trips_from_asm(
+    gtfs: &Gtfs,
+    asm_root: &AsmRoot,
+    present_entity_ids: &HashSet<String>,
+    now: i64,
+) -> Vec<FeedEntity> {
+    let mut entities = vec![];
+
+    for train in asm_root {
+        if !train.is_fully_canceled() {
+            continue;
+        }
+
+        let origin_date = match train.origin_date() {
+            Some(origin_date) => origin_date,
+            None => continue,
+        };
+
+        let departure = match train.scheduled_origin_departure() {
+            Some(departure) if departure <= now => departure,
+            _ => continue,
+        };
+
+        let train_number = train.number().to_string();
+        let start_date = origin_date.format("%Y%m%d").to_string();
+        let id = format!("{}-{}", start_date, train_number);
+
+        if present_entity_ids.contains(&id) {
+            continue;
+        }
+
+        let trip = match scheduled_trip_for_train(gtfs, &train_number, origin_date) {
+            Some(trip) => trip,
+            None => continue,
+        };
+
+        if now > departure + scheduled_duration(trip) {
+            continue;
+        }
+
+        entities.push(FeedEntity {
+            id,
+            is_deleted: Some(false),
+            trip_update: Some(gtfs_realtime::TripUpdate {
+                trip: gtfs_realtime::TripDescriptor {
+                    trip_id: Some(trip.id.clone()),
+                    route_id: Some(trip.route_id.clone()),
+                    start_date: Some(start_date),
+                    schedule_relationship: Some(ScheduleRelationship::Canceled as i32),
+                    ..Default::default()
+                },
+                timestamp: Some(now as u64),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    entities.sort_by(|a, b| a.id.cmp(&b.id));
+
+    entities
+}
+
+/// Seconds from the first departure to the last arrival of a scheduled trip.
+fn scheduled_duration(trip: &gtfs_structures::Trip) -> i64 {
+    let first = trip
+        .stop_times
+        .first()
+        .and_then(|stop_time| stop_time.departure_time.or(stop_time.arrival_time));
+    let last = trip
+        .stop_times
+        .last()
+        .and_then(|stop_time| stop_time.arrival_time.or(stop_time.departure_time));
+
+    match (first, last) {
+        (Some(first), Some(last)) => last as i64 - first as i64,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::test_helpers::january_gtfs;
+
+    // 2026-01-19 06:00 UTC
+    const DEPARTURE: i64 = 1768802400;
+
+    fn asm_train(number: i64, origin_date: &str, canceled: [bool; 2]) -> serde_json::Value {
+        serde_json::json!({
+            "train_id": format!("{}-{}", number, origin_date),
+            "railroad": "AMTRAK",
+            "origin_date": origin_date,
+            "number": number,
+            "all_numbers": [number],
+            "name": "Coast Starlight",
+            "origin": "SEA",
+            "destination": "LAX",
+            "partial_train": false,
+            "last_updated": DEPARTURE,
+            "current_timezone": "America/Los_Angeles",
+            "threshold": 0,
+            "disruption": true,
+            "total_miles": 1377,
+            "location": null,
+            "stops": [
+                {"code": "SEA", "miles": 0, "sched_depart": DEPARTURE, "depart": null, "canceled": canceled[0], "sched_arrive": null, "arrive": null},
+                {"code": "LAX", "miles": 1377, "sched_depart": null, "depart": null, "canceled": canceled[1], "sched_arrive": DEPARTURE + 126000, "arrive": null}
+            ],
+            "alerts": null
+        })
+    }
+
+    fn asm_root(trains: Vec<serde_json::Value>) -> AsmRoot {
+        serde_json::from_value(serde_json::Value::Array(trains)).unwrap()
+    }
+
+    #[test]
+    fn confirmed_missing_train_is_canceled() {
+        let gtfs = january_gtfs();
+        let root = asm_root(vec![asm_train(11, "2026-01-19", [true, true])]);
+
+        let canceled = canceled_trips_from_asm(&gtfs, &root, &HashSet::new(), DEPARTURE + 60);
+
+        assert_eq!(canceled.len(), 1);
+        assert_eq!(canceled[0].id, "20260119-11");
+
+        let trip = &canceled[0].trip_update.as_ref().unwrap().trip;
+        assert_eq!(trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(trip.route_id.as_deref(), Some("94"));
+        assert_eq!(
+            trip.schedule_relationship,
+            Some(ScheduleRelationship::Canceled as i32)
+        );
+    }
+
+    #[test]
+    fn not_canceled_without_confirmation() {
+        let gtfs = january_gtfs();
+
+        // Only partially canceled
+        let partial = asm_root(vec![asm_train(11, "2026-01-19", [false, true])]);
+        assert!(
+            canceled_trips_from_asm(&gtfs, &partial, &HashSet::new(), DEPARTURE + 60).is_empty()
+        );
+
+        let full = asm_root(vec![asm_train(11, "2026-01-19", [true, true])]);
+
+        // Before the scheduled departure
+        assert!(canceled_trips_from_asm(&gtfs, &full, &HashSet::new(), DEPARTURE - 60).is_empty());
+
+        // Still on Track-A-Train, which reports the cancellation itself
+        let present = HashSet::from(["20260119-11".to_string()]);
+        assert!(canceled_trips_from_asm(&gtfs, &full, &present, DEPARTURE + 60).is_empty());
+
+        // Not scheduled that day
+        let removed_day = asm_root(vec![asm_train(11, "2026-01-20", [true, true])]);
+        assert!(
+            canceled_trips_from_asm(&gtfs, &removed_day, &HashSet::new(), DEPARTURE + 86460)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn old_cancellations_expire() {
+        let gtfs = january_gtfs();
+        let full = asm_root(vec![asm_train(11, "2026-01-19", [true, true])]);
+
+        // SEA 9:52 to LAX 21:00 the next day
+        let duration = 162000 - 35520;
+
+        assert_eq!(
+            canceled_trips_from_asm(&gtfs, &full, &HashSet::new(), DEPARTURE + duration).len(),
+            1
+        );
+        assert!(
+            canceled_trips_from_asm(&gtfs, &full, &HashSet::new(), DEPARTURE + duration + 60)
+                .is_empty()
+        );
+        assert!(
+            canceled_trips_from_asm(&gtfs, &full, &HashSet::new(), DEPARTURE + 3 * 86400)
+                .is_empty()
+        );
+    }
+}
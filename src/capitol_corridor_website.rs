@@ -0,0 +1,35 @@
+use crate::advisory_source::{AdvisorySource, CardSelectors, parse_card_advisories};
+use gtfs_realtime::FeedEntity;
+
+pub struct CapitolCorridor;
+
+const SELECTORS: CardSelectors = CardSelectors {
+    card: ".service-alert",
+    title: ".service-alert__title",
+    body: ".service-alert__content",
+    section: Some(".service-alert__category"),
+};
+
+impl AdvisorySource for CapitolCorridor {
+    fn id_prefix(&self) -> &'static str {
+        "CAP_CORR"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Capitol Corridor"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://www.capitolcorridor.org/service-alerts/"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_card_advisories(
+            html,
+            &SELECTORS,
+            self.id_prefix(),
+            "Service Alerts",
+            route_id,
+        )
+    }
+}
@@ -0,0 +1,29 @@
+use crate::advisory_source::{AdvisorySource, CardSelectors, parse_card_advisories};
+use gtfs_realtime::FeedEntity;
+
+pub struct Cascades;
+
+const SELECTORS: CardSelectors = CardSelectors {
+    card: ".view-alerts .views-row",
+    title: ".alert-title",
+    body: ".views-field-body .field-content",
+    section: Some(".views-field-field-alert-type .field-content"),
+};
+
+impl AdvisorySource for Cascades {
+    fn id_prefix(&self) -> &'static str {
+        "CASCADES"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Amtrak Cascades"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://amtrakcascades.com/alerts"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_card_advisories(html, &SELECTORS, self.id_prefix(), "Alerts", route_id)
+    }
+}
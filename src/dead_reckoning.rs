@@ -0,0 +1,344 @@
+//! Extrapolation of vehicle positions between Track-A-Train updates.
+//!
+//! Track-A-Train positions only change every few minutes. Between two updates, a train is moved along the
+//! shape of its trip at its last known speed, for at most a maximum horizon, and never past the next station
+//! it's scheduled to stop at, since it will dwell there. Shapes and the stations along them are measured
+//! once, and kept in a [`ShapeCache`].
+
+use crate::shapes::{
+    cumulative_distances, point_at_measured_distance, project_onto_measured_polyline, shape_points,
+};
+use crate::trip_modifications::SHAPE_DIVERGENCE_THRESHOLD_M;
+use gtfs_structures::{Gtfs, Trip};
+use std::collections::HashMap;
+
+/// Default for how far ahead of the last update a position is extrapolated, seconds.
+pub const DEFAULT_MAX_HORIZON_SECS: i64 = 180;
+
+/// A position moved along the shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extrapolation {
+    /// `(latitude, longitude)`
+    pub point: (f64, f64),
+    /// Distance along the shape, meters
+    pub odometer_m: f64,
+    /// Time the train was moved for, seconds
+    pub elapsed_secs: i64,
+    /// The station the train was held at, if it reached one
+    pub held_at_stop: Option<String>,
+}
+
+/// A GTFS shape with its distances, and where stations are along it.
+#[derive(Debug, Clone, Default)]
+pub struct MeasuredShape {
+    points: Vec<(f64, f64)>,
+    /// Distance from the start of the shape at each point, meters
+    distances: Vec<f64>,
+    /// Distance along the shape of each station projected so far, `None` for stations without coordinates
+    stop_distances: HashMap<String, Option<f64>>,
+}
+
+impl MeasuredShape {
+    fn new(points: Vec<(f64, f64)>) -> Self {
+        MeasuredShape {
+            distances: cumulative_distances(&points),
+            points,
+            stop_distances: HashMap::new(),
+        }
+    }
+
+    fn stop_distance(&mut self, gtfs: &Gtfs, stop: &gtfs_structures::Stop) -> Option<f64> {
+        if let Some(distance) = self.stop_distances.get(&stop.id) {
+            return *distance;
+        }
+
+        let stop = gtfs
+            .stops
+            .get(&stop.id)
+            .map(|stop| stop.as_ref())
+            .unwrap_or(stop);
+
+        let distance = stop.latitude.zip(stop.longitude).and_then(|point| {
+            project_onto_measured_polyline(&self.points, &self.distances, point)
+                .map(|projection| projection.distance_along_m)
+        });
+
+        self.stop_distances.insert(stop.id.clone(), distance);
+
+        distance
+    }
+}
+
+/// Shapes prepared for extrapolation, by shape id. Every shape and station is only measured once.
+#[derive(Debug, Clone, Default)]
+pub struct ShapeCache {
+    /// `None` for shapes that can't be used, e.g. missing or with fewer than two points
+    shapes: HashMap<String, Option<MeasuredShape>>,
+}
+
+impl ShapeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The measured shape of a trip, with every station of the trip projected onto it.
+    pub fn trip_shape(&mut self, gtfs: &Gtfs, trip: &Trip) -> Option<&mut MeasuredShape> {
+        let shape_id = trip.shape_id.as_ref()?;
+
+        let shape = self
+            .shapes
+            .entry(shape_id.clone())
+            .or_insert_with(|| {
+                shape_points(gtfs, shape_id)
+                    .filter(|points| points.len() >= 2)
+                    .map(MeasuredShape::new)
+            })
+            .as_mut()?;
+
+        for stop_time in &trip.stop_times {
+            shape.stop_distance(gtfs, &stop_time.stop);
+        }
+
+        Some(shape)
+    }
+}
+
+/// Moves a train reported at `point` at `position_time` along its trip's shape, up to `now`.
+///
+/// `speed` is in meters per second. Stations in `skipped_stop_ids` aren't stopped at.
+/// Returns `None` when the train isn't moving, or isn't on the shape of its trip.
+#[allow(clippy::too_many_arguments)]
+pub fn extrapolate_along_trip(
+    shapes: &mut ShapeCache,
+    gtfs: &Gtfs,
+    trip: &Trip,
+    point: (f64, f64),
+    speed: f32,
+    position_time: i64,
+    now: i64,
+    max_horizon_secs: i64,
+    skipped_stop_ids: &[String],
+) -> Option<Extrapolation> {
+    let elapsed_secs = (now - position_time).min(max_horizon_secs);
+
+    if elapsed_secs <= 0 || !speed.is_finite() || speed <= 0.0 {
+        return None;
+    }
+
+    let shape = shapes.trip_shape(gtfs, trip)?;
+
+    let snapped = project_onto_measured_polyline(&shape.points, &shape.distances, point)
+        .filter(|snapped| snapped.distance_from_shape_m <= SHAPE_DIVERGENCE_THRESHOLD_M)?;
+
+    // Next station ahead of the train on the shape
+    let next_stop = trip
+        .stop_times
+        .iter()
+        .filter(|stop_time| !skipped_stop_ids.contains(&stop_time.stop.id))
+        .filter_map(|stop_time| {
+            let distance = shape
+                .stop_distances
+                .get(&stop_time.stop.id)
+                .copied()
+                .flatten()?;
+
+            Some((stop_time.stop.id.clone(), distance))
+        })
+        .find(|(_, distance)| *distance > snapped.distance_along_m);
+
+    let travelled = snapped.distance_along_m + speed as f64 * elapsed_secs as f64;
+    let shape_length_m = *shape.distances.last()?;
+
+    let (odometer_m, held_at_stop) = match next_stop {
+        Some((stop_id, distance)) if distance <= travelled => (distance, Some(stop_id)),
+        _ => (travelled.min(shape_length_m), None),
+    };
+
+    Some(Extrapolation {
+        point: point_at_measured_distance(&shape.points, &shape.distances, odometer_m)?,
+        odometer_m,
+        elapsed_secs,
+        held_at_stop,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{haversine_m, point_at_distance, project_onto_polyline};
+    use crate::test_helpers::coast_starlight_gtfs;
+
+    const NOW: i64 = 1768842000;
+
+    fn portland_distance(gtfs: &Gtfs) -> f64 {
+        let points = shape_points(gtfs, "SH94").unwrap();
+
+        project_onto_polyline(&points, (45.529, -122.677))
+            .unwrap()
+            .distance_along_m
+    }
+
+    fn point_before_portland(gtfs: &Gtfs, meters: f64) -> (f64, f64) {
+        let points = shape_points(gtfs, "SH94").unwrap();
+
+        point_at_distance(&points, portland_distance(gtfs) - meters).unwrap()
+    }
+
+    #[test]
+    fn moves_along_the_shape() {
+        let gtfs = coast_starlight_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+        let mut shapes = ShapeCache::new();
+
+        let start = point_before_portland(&gtfs, 20_000.0);
+
+        let extrapolation = extrapolate_along_trip(
+            &mut shapes,
+            &gtfs,
+            trip,
+            start,
+            30.0,
+            NOW - 60,
+            NOW,
+            180,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(extrapolation.elapsed_secs, 60);
+        assert_eq!(extrapolation.held_at_stop, None);
+        assert!((extrapolation.odometer_m - (portland_distance(&gtfs) - 18_200.0)).abs() < 1.0);
+        assert!((haversine_m(start, extrapolation.point) - 1800.0).abs() < 10.0);
+
+        // Capped by the horizon
+        let extrapolation = extrapolate_along_trip(
+            &mut shapes,
+            &gtfs,
+            trip,
+            start,
+            30.0,
+            NOW - 3600,
+            NOW,
+            180,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(extrapolation.elapsed_secs, 180);
+        assert!((extrapolation.odometer_m - (portland_distance(&gtfs) - 14_600.0)).abs() < 1.0);
+    }
+
+    #[test]
+    fn stops_at_stations() {
+        let gtfs = coast_starlight_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+        let mut shapes = ShapeCache::new();
+
+        let start = point_before_portland(&gtfs, 1000.0);
+
+        let extrapolation = extrapolate_along_trip(
+            &mut shapes,
+            &gtfs,
+            trip,
+            start,
+            30.0,
+            NOW - 120,
+            NOW,
+            180,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(extrapolation.held_at_stop.as_deref(), Some("PDX"));
+        assert!((extrapolation.odometer_m - portland_distance(&gtfs)).abs() < 1.0);
+
+        // Skipped stations don't stop the train
+        let extrapolation = extrapolate_along_trip(
+            &mut shapes,
+            &gtfs,
+            trip,
+            start,
+            30.0,
+            NOW - 120,
+            NOW,
+            180,
+            &["PDX".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(extrapolation.held_at_stop, None);
+        assert!(extrapolation.odometer_m > portland_distance(&gtfs) + 2000.0);
+    }
+
+    #[test]
+    fn nothing_to_extrapolate() {
+        let gtfs = coast_starlight_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+        let mut shapes = ShapeCache::new();
+
+        let start = point_before_portland(&gtfs, 20_000.0);
+
+        assert_eq!(
+            extrapolate_along_trip(
+                &mut shapes,
+                &gtfs,
+                trip,
+                start,
+                0.0,
+                NOW - 60,
+                NOW,
+                180,
+                &[]
+            ),
+            None
+        );
+        assert_eq!(
+            extrapolate_along_trip(&mut shapes, &gtfs, trip, start, 30.0, NOW, NOW, 180, &[]),
+            None
+        );
+        assert_eq!(
+            extrapolate_along_trip(
+                &mut shapes,
+                &gtfs,
+                trip,
+                (40.0, -100.0),
+                30.0,
+                NOW - 60,
+                NOW,
+                180,
+                &[]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn shapes_are_measured_once() {
+        let gtfs = coast_starlight_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+        let mut shapes = ShapeCache::new();
+
+        let start = point_before_portland(&gtfs, 20_000.0);
+
+        for _ in 0..3 {
+            extrapolate_along_trip(
+                &mut shapes,
+                &gtfs,
+                trip,
+                start,
+                30.0,
+                NOW - 60,
+                NOW,
+                180,
+                &[],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(shapes.shapes.len(), 1);
+
+        let shape = shapes.trip_shape(&gtfs, trip).unwrap();
+        assert_eq!(shape.stop_distances.len(), trip.stop_times.len());
+        assert!((shape.stop_distances["PDX"].unwrap() - portland_distance(&gtfs)).abs() < 1.0);
+    }
+}
@@ -0,0 +1,260 @@
+//! Propagation of a train's delay to the stations Track-A-Train has no estimate for.
+//!
+//! A late train keeps its delay from one station to the next, however many stations are missing estimates,
+//! but it can make up time where the schedule is padded: it won't dwell longer than needed at a station it
+//! reaches late, and it won't leave before its scheduled departure. Dwell times come from the GTFS stop
+//! times when the trip is matched, since Track-A-Train often only lists a departure at stations with recovery
+//! time built in.
+
+use crate::station_times::StationTimes;
+use gtfs_structures::Trip;
+
+/// Shortest dwell a train needs at a station it reaches late, unless its scheduled dwell is shorter.
+pub const MIN_DWELL_SECS: i64 = 60;
+
+/// Realtime times of one station after propagation.
+/// Delays are only set for times propagated from an earlier station.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct PropagatedTimes {
+    pub arrival: Option<i64>,
+    pub arrival_delay: Option<i64>,
+    pub departure: Option<i64>,
+    pub departure_delay: Option<i64>,
+}
+
+/// Scheduled dwell in seconds at each station code, from the trip's stop times.
+pub(crate) fn scheduled_dwells(trip: Option<&Trip>, codes: &[&str]) -> Vec<Option<i64>> {
+    let Some(trip) = trip else {
+        return vec![None; codes.len()];
+    };
+
+    crate::schedule::stop_sequences_for_codes(trip, codes)
+        .into_iter()
+        .map(|stop_sequence| {
+            let stop_time = trip
+                .stop_times
+                .iter()
+                .find(|stop_time| Some(stop_time.stop_sequence) == stop_sequence)?;
+
+            Some(stop_time.departure_time? as i64 - stop_time.arrival_time? as i64)
+        })
+        .collect()
+}
+
+/// The later of two optional times.
+fn not_before(time: Option<i64>, previous: Option<i64>) -> Option<i64> {
+    match (time, previous) {
+        (Some(time), Some(previous)) => Some(time.max(previous)),
+        (time, _) => time,
+    }
+}
+
+/// Fills in the realtime times missing from `times`, `dwells` being in the same order.
+pub(crate) fn propagate_delays(
+    times: &[StationTimes],
+    dwells: &[Option<i64>],
+) -> Vec<PropagatedTimes> {
+    // Delay of the last realtime time, carried to the next stations
+    let mut delay: Option<i64> = None;
+    let mut previous: Option<i64> = None;
+
+    times
+        .iter()
+        .enumerate()
+        .map(|(i, times)| {
+            let dwell = dwells.get(i).copied().flatten().or(
+                match (times.scheduled_arrival, times.scheduled_departure) {
+                    (Some(arrival), Some(departure)) => Some(departure - arrival),
+                    _ => None,
+                },
+            );
+
+            let scheduled_arrival =
+                times
+                    .scheduled_arrival
+                    .or(match (times.scheduled_departure, dwell) {
+                        (Some(departure), Some(dwell)) => Some(departure - dwell),
+                        _ => None,
+                    });
+
+            let (arrival, arrival_delay) = match (times.arrival, delay, scheduled_arrival) {
+                (Some(arrival), _, _) => (Some(arrival), None),
+                (None, Some(delay), Some(scheduled_arrival)) => {
+                    (Some(scheduled_arrival + delay), Some(delay))
+                }
+                _ => (None, None),
+            };
+
+            // Never arrive before leaving the previous station
+            let arrival = not_before(arrival, previous);
+            let arrival_delay = match (arrival_delay, arrival, scheduled_arrival) {
+                (Some(_), Some(arrival), Some(scheduled_arrival)) => {
+                    Some(arrival - scheduled_arrival)
+                }
+                _ => None,
+            };
+
+            if let Some(arrival) = arrival {
+                previous = Some(arrival);
+
+                if let Some(scheduled_arrival) = scheduled_arrival {
+                    delay = Some(arrival - scheduled_arrival);
+                }
+            }
+
+            let (departure, departure_delay) = match (times.departure, times.scheduled_departure) {
+                (Some(departure), _) => (Some(departure), None),
+                (None, Some(scheduled_departure)) => {
+                    let departure = match arrival {
+                        // A late train makes up the dwell time it doesn't need, but never leaves early
+                        Some(arrival) => Some(scheduled_departure.max(
+                            arrival + dwell.unwrap_or(MIN_DWELL_SECS).clamp(0, MIN_DWELL_SECS),
+                        )),
+                        None => delay.map(|delay| scheduled_departure + delay),
+                    };
+
+                    (
+                        departure,
+                        departure.map(|departure| departure - scheduled_departure),
+                    )
+                }
+                (None, None) => (None, None),
+            };
+
+            let departure = not_before(departure, previous);
+
+            if let Some(departure) = departure {
+                previous = Some(departure);
+
+                if let Some(scheduled_departure) = times.scheduled_departure {
+                    delay = Some(departure - scheduled_departure);
+                }
+            }
+
+            PropagatedTimes {
+                arrival,
+                arrival_delay,
+                departure,
+                departure_delay: match (departure_delay, departure, times.scheduled_departure) {
+                    (Some(_), Some(departure), Some(scheduled_departure)) => {
+                        Some(departure - scheduled_departure)
+                    }
+                    _ => None,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60;
+
+    fn times(
+        scheduled_arrival: Option<i64>,
+        scheduled_departure: Option<i64>,
+        arrival: Option<i64>,
+        departure: Option<i64>,
+    ) -> StationTimes {
+        StationTimes {
+            scheduled_arrival: scheduled_arrival.map(|x| x * MINUTE),
+            scheduled_departure: scheduled_departure.map(|x| x * MINUTE),
+            arrival: arrival.map(|x| x * MINUTE),
+            departure: departure.map(|x| x * MINUTE),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delay_carries_across_missing_stations() {
+        let stations = [
+            times(None, Some(0), None, Some(30)),
+            times(Some(60), Some(62), None, None),
+            times(Some(120), Some(122), None, None),
+            times(Some(180), None, None, None),
+        ];
+
+        let propagated = propagate_delays(&stations, &[None; 4]);
+
+        assert_eq!(propagated[0].departure, Some(30 * MINUTE));
+        assert_eq!(propagated[0].departure_delay, None);
+
+        // 2 minute dwells are shortened to 1, gaining a minute at each station
+        assert_eq!(propagated[1].arrival, Some(90 * MINUTE));
+        assert_eq!(propagated[1].arrival_delay, Some(30 * MINUTE));
+        assert_eq!(propagated[1].departure, Some(91 * MINUTE));
+        assert_eq!(propagated[2].arrival, Some(149 * MINUTE));
+        assert_eq!(propagated[2].departure_delay, Some(28 * MINUTE));
+        assert_eq!(propagated[3].arrival, Some(208 * MINUTE));
+        assert_eq!(propagated[3].departure, None);
+    }
+
+    #[test]
+    fn recovery_time_from_the_schedule() {
+        // Track-A-Train only lists the departure of the middle station
+        let stations = [
+            times(None, Some(0), None, Some(20)),
+            times(None, Some(100), None, None),
+            times(Some(160), None, None, None),
+        ];
+
+        // The GTFS has 30 minutes of recovery time before that departure
+        let propagated = propagate_delays(&stations, &[None, Some(30 * MINUTE), None]);
+
+        assert_eq!(propagated[1].arrival, Some(90 * MINUTE));
+        // Ready to leave after a minute, but waits for the scheduled departure
+        assert_eq!(propagated[1].departure, Some(100 * MINUTE));
+        assert_eq!(propagated[1].departure_delay, Some(0));
+        assert_eq!(propagated[2].arrival, Some(160 * MINUTE));
+
+        // Without the schedule, there's nothing to recover with
+        let propagated = propagate_delays(&stations, &[None; 3]);
+
+        assert_eq!(propagated[1].arrival, None);
+        assert_eq!(propagated[1].departure, Some(120 * MINUTE));
+        assert_eq!(propagated[2].arrival, Some(180 * MINUTE));
+    }
+
+    #[test]
+    fn never_before_the_previous_departure() {
+        let stations = [
+            times(None, Some(0), None, Some(50)),
+            // Stale estimate, from before the train left late
+            times(Some(30), Some(32), Some(30), None),
+            times(Some(60), None, None, None),
+        ];
+
+        let propagated = propagate_delays(&stations, &[None; 3]);
+
+        assert_eq!(propagated[1].arrival, Some(50 * MINUTE));
+        assert_eq!(propagated[1].departure, Some(51 * MINUTE));
+        assert_eq!(propagated[2].arrival, Some(79 * MINUTE));
+
+        // Trains running early still don't leave early
+        let stations = [
+            times(None, Some(0), None, Some(0)),
+            times(Some(30), Some(40), Some(25), None),
+        ];
+
+        let propagated = propagate_delays(&stations, &[None; 2]);
+        assert_eq!(propagated[1].departure, Some(40 * MINUTE));
+    }
+
+    #[test]
+    fn dwells_from_the_trip() {
+        let mut trip = crate::schedule::test_helpers::trip("T11", "JAN", "94", "11");
+        trip.stop_times = crate::schedule::test_helpers::stop_times(&[
+            ("SEA", 35520, 35520),
+            ("PDX", 48600, 49500),
+            ("LAX", 162000, 162000),
+        ]);
+
+        assert_eq!(
+            scheduled_dwells(Some(&trip), &["SEA", "PDX", "XYZ", "LAX"]),
+            vec![Some(0), Some(900), None, Some(0)]
+        );
+        assert_eq!(scheduled_dwells(None, &["SEA"]), vec![None]);
+    }
+}
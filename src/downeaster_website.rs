@@ -0,0 +1,29 @@
+use crate::advisory_source::{AdvisorySource, CardSelectors, parse_card_advisories};
+use gtfs_realtime::FeedEntity;
+
+pub struct Downeaster;
+
+const SELECTORS: CardSelectors = CardSelectors {
+    card: "article.alert",
+    title: ".alert__title",
+    body: ".alert__content",
+    section: Some(".alert__label"),
+};
+
+impl AdvisorySource for Downeaster {
+    fn id_prefix(&self) -> &'static str {
+        "DOWNEAST"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Downeaster"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://www.amtrakdowneaster.com/alerts"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_card_advisories(html, &SELECTORS, self.id_prefix(), "Alerts", route_id)
+    }
+}
@@ -0,0 +1,827 @@
+//! Feed generation that remembers trains across polls.
+//!
+//! Track-A-Train regularly drops a train for a poll or two, and forgets actual times of stations the train
+//! already served. [`AmtrakFeedEngine`] keeps every train it published for a grace period after it goes
+//! missing, with the entities derived from it like its bus replacement. Derived entities that are gone
+//! while their train is still reported are dropped right away. The engine also keeps actual times once
+//! observed, and keeps the last good position of every vehicle. Vehicles that haven't reported for a while
+//! are stale: they keep their last position and timestamp, but lose their speed. Optionally, positions are
+//! extrapolated along the shape of the trip between updates, see [`crate::dead_reckoning`]. Replacement
+//! shapes of modified trips are redrawn through the train once it was reported off its GTFS shape for
+//! several polls, see [`crate::trip_modifications`].
+//!
+//! ```no_run
+//! # async fn run(gtfs: gtfs_structures::Gtfs) {
+//! use amtrak_gtfs_rt::engine::AmtrakFeedEngine;
+//!
+//! let client = reqwest::Client::new();
+//! let mut engine = AmtrakFeedEngine::new(std::sync::Arc::new(gtfs));
+//!
+//! loop {
+//!     if let Ok(payloads) = amtrak_gtfs_rt::fetch_amtrak_payloads(engine.gtfs(), &client).await {
+//!         let feeds = engine.update(&payloads);
+//!         let stale_vehicles = &engine.last_results().stale_vehicles;
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::dead_reckoning::{ShapeCache, extrapolate_along_trip};
+use crate::monotonicity::{Repair, RepairStrategy, enforce_feed_monotonicity};
+use crate::motion::MotionSmoother;
+use crate::prediction::ArrivalPredictor;
+use crate::trip_modifications::{
+    MIN_DIVERGENT_POSITIONS, scheduled_trip, trip_modification_entities,
+};
+use crate::{
+    AmtrakPayloads, GtfsAmtrakResults, make_gtfs_header, parent_entity_id,
+    process_amtrak_payloads_joined, split_unified_feed,
+};
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship::Skipped;
+use gtfs_realtime::trip_update::{StopTimeEvent, StopTimeUpdate};
+use gtfs_realtime::{FeedEntity, FeedMessage, Position};
+use gtfs_structures::Gtfs;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// How long an entity missing from Track-A-Train stays in the feed, seconds.
+    pub grace_period_secs: i64,
+    /// Vehicles whose position is older than this are stale, seconds.
+    pub stale_after_secs: i64,
+    /// Repair of stop times that go back in time, `None` to publish them as is.
+    pub repair_strategy: Option<RepairStrategy>,
+    /// Smooth speeds and headings across polls, see [`MotionSmoother`].
+    pub smooth_motion: bool,
+    /// Move trains along their shape between Track-A-Train updates, for at most this many seconds.
+    /// `None` publishes positions as reported.
+    pub dead_reckoning_horizon_secs: Option<i64>,
+    /// Predict arrivals from history and blend them with Amtrak's estimates, see [`ArrivalPredictor`].
+    pub predictor: Option<Arc<ArrivalPredictor>>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            grace_period_secs: 10 * 60,
+            stale_after_secs: 5 * 60,
+            repair_strategy: Some(RepairStrategy::Clamp),
+            smooth_motion: true,
+            dead_reckoning_horizon_secs: None,
+            predictor: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EntityState {
+    entity: FeedEntity,
+    /// Last poll the entity was in, unix seconds
+    last_seen: i64,
+    /// Last position with valid coordinates, and its timestamp
+    last_good_position: Option<(Position, Option<u64>)>,
+    /// Last reported positions, oldest first, at most [`MIN_DIVERGENT_POSITIONS`]
+    recent_positions: Vec<(f64, f64)>,
+}
+
+/// What the engine did to the feeds of an update, see [`AmtrakFeedEngine::last_results`].
+#[derive(Clone, Debug, Default)]
+pub struct EngineResults {
+    /// Entity ids of vehicles whose position is stale
+    pub stale_vehicles: HashSet<String>,
+    /// Entity ids of vehicles whose position was extrapolated. Their timestamp is the time the position
+    /// was extrapolated to.
+    pub extrapolated_vehicles: HashSet<String>,
+    /// Stop times repaired with the configured `repair_strategy`
+    pub repairs: Vec<Repair>,
+    /// Why the poll couldn't be processed, its trains are then treated as missing
+    pub poll_error: Option<String>,
+}
+
+pub struct AmtrakFeedEngine {
+    gtfs: Arc<Gtfs>,
+    pub config: EngineConfig,
+    entities: HashMap<String, EntityState>,
+    smoother: MotionSmoother,
+    shapes: ShapeCache,
+    results: EngineResults,
+}
+
+/// Stops are matched across polls by stop sequence, else by stop id.
+fn stop_key(stop_time_update: &StopTimeUpdate) -> (Option<u32>, Option<String>) {
+    match stop_time_update.stop_sequence {
+        Some(stop_sequence) => (Some(stop_sequence), None),
+        None => (None, stop_time_update.stop_id.clone()),
+    }
+}
+
+/// Actual times are marked with an uncertainty of 0.
+fn is_actual(event: &Option<StopTimeEvent>) -> bool {
+    event
+        .as_ref()
+        .is_some_and(|event| event.uncertainty == Some(0))
+}
+
+fn has_valid_coordinates(position: &Position) -> bool {
+    position.latitude.is_finite()
+        && position.longitude.is_finite()
+        && (position.latitude != 0.0 || position.longitude != 0.0)
+        && position.latitude.abs() <= 90.0
+        && position.longitude.abs() <= 180.0
+}
+
+/// Copies actual times of `previous` that `entity` lost, e.g. after Track-A-Train reset a station.
+fn preserve_actuals(entity: &mut FeedEntity, previous: &FeedEntity) {
+    let (Some(trip_update), Some(previous)) =
+        (entity.trip_update.as_mut(), previous.trip_update.as_ref())
+    else {
+        return;
+    };
+
+    let previous_stops = previous
+        .stop_time_update
+        .iter()
+        .map(|stop_time_update| (stop_key(stop_time_update), stop_time_update))
+        .collect::<HashMap<_, _>>();
+
+    for stop_time_update in trip_update.stop_time_update.iter_mut() {
+        let Some(previous) = previous_stops.get(&stop_key(stop_time_update)) else {
+            continue;
+        };
+
+        if is_actual(&previous.arrival) && !is_actual(&stop_time_update.arrival) {
+            stop_time_update.arrival = previous.arrival;
+        }
+
+        if is_actual(&previous.departure) && !is_actual(&stop_time_update.departure) {
+            stop_time_update.departure = previous.departure;
+        }
+    }
+}
+
+impl AmtrakFeedEngine {
+    pub fn new(gtfs: Arc<Gtfs>) -> Self {
+        Self::with_config(gtfs, EngineConfig::default())
+    }
+
+    pub fn with_config(gtfs: Arc<Gtfs>, config: EngineConfig) -> Self {
+        AmtrakFeedEngine {
+            gtfs,
+            config,
+            entities: HashMap::new(),
+            smoother: MotionSmoother::default(),
+            shapes: ShapeCache::new(),
+            results: EngineResults::default(),
+        }
+    }
+
+    pub fn gtfs(&self) -> &Gtfs {
+        &self.gtfs
+    }
+
+    /// Whether the vehicle was stale in the last update.
+    pub fn is_stale(&self, entity_id: &str) -> bool {
+        self.results.stale_vehicles.contains(entity_id)
+    }
+
+    /// Whether the vehicle's position was extrapolated in the last update.
+    pub fn is_extrapolated(&self, entity_id: &str) -> bool {
+        self.results.extrapolated_vehicles.contains(entity_id)
+    }
+
+    /// Stale and extrapolated vehicles, repairs and errors of the last update.
+    pub fn last_results(&self) -> &EngineResults {
+        &self.results
+    }
+
+    /// Processes one poll, see [`crate::fetch_amtrak_payloads`].
+    pub fn update(&mut self, payloads: &AmtrakPayloads) -> GtfsAmtrakResults {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        self.update_at(payloads, now)
+    }
+
+    /// [`AmtrakFeedEngine::update`] at a given time, unix seconds.
+    pub fn update_at(&mut self, payloads: &AmtrakPayloads, now: i64) -> GtfsAmtrakResults {
+        // A poll that can't be decoded is treated like every train going missing, so the grace period applies.
+        // Stop times are repaired below, once merged with the state.
+        let entities = match process_amtrak_payloads_joined(
+            &self.gtfs,
+            &payloads.track_a_train,
+            payloads.asm.as_deref(),
+            now,
+            None,
+        ) {
+            Ok(joined) => {
+                self.results.poll_error = None;
+                joined.unified_feed.entity
+            }
+            Err(e) => {
+                self.results.poll_error = Some(e.to_string());
+                vec![]
+            }
+        };
+
+        self.ingest(entities, now);
+
+        let mut unified_feed = FeedMessage {
+            header: make_gtfs_header(),
+            entity: self.published_entities(now),
+        };
+
+        self.redraw_detours(&mut unified_feed.entity);
+
+        self.results.extrapolated_vehicles.clear();
+
+        if let Some(max_horizon_secs) = self.config.dead_reckoning_horizon_secs {
+            self.extrapolate(&mut unified_feed.entity, now, max_horizon_secs);
+        }
+
+        if let Some(predictor) = &self.config.predictor {
+            predictor.apply(&mut unified_feed);
+        }
+
+        self.results.repairs = match self.config.repair_strategy {
+            Some(strategy) => enforce_feed_monotonicity(&mut unified_feed, strategy),
+            None => vec![],
+        };
+
+        if self.config.smooth_motion {
+            self.smoother.apply(&mut unified_feed);
+        }
+
+        split_unified_feed(unified_feed, payloads.advisories.clone())
+    }
+
+    /// Merges the entities of a poll into the state, and forgets entities past their grace period.
+    fn ingest(&mut self, entities: Vec<FeedEntity>, now: i64) {
+        let polled = entities
+            .iter()
+            .map(|entity| entity.id.clone())
+            .collect::<HashSet<String>>();
+
+        for mut entity in entities {
+            let previous = self.entities.remove(&entity.id);
+
+            if let Some(previous) = &previous {
+                preserve_actuals(&mut entity, &previous.entity);
+            }
+
+            let (mut last_good_position, mut recent_positions) = previous
+                .map(|previous| (previous.last_good_position, previous.recent_positions))
+                .unwrap_or_default();
+
+            if let Some(vehicle) = entity.vehicle.as_mut() {
+                match vehicle.position.filter(has_valid_coordinates) {
+                    Some(position) => {
+                        // Track-A-Train repeats positions between reports
+                        let is_new_report = last_good_position
+                            .is_none_or(|(_, timestamp)| timestamp != vehicle.timestamp);
+
+                        if is_new_report {
+                            recent_positions
+                                .push((position.latitude as f64, position.longitude as f64));
+
+                            if recent_positions.len() > MIN_DIVERGENT_POSITIONS {
+                                recent_positions.remove(0);
+                            }
+                        }
+
+                        last_good_position = Some((position, vehicle.timestamp));
+                    }
+                    // Carry the last good position forward, with the time it was seen at
+                    None => {
+                        if let Some((position, timestamp)) = last_good_position {
+                            vehicle.position = Some(position);
+                            vehicle.timestamp = timestamp;
+                        }
+                    }
+                }
+            }
+
+            self.entities.insert(
+                entity.id.clone(),
+                EntityState {
+                    entity,
+                    last_seen: now,
+                    last_good_position,
+                    recent_positions,
+                },
+            );
+        }
+
+        let grace_period_secs = self.config.grace_period_secs;
+
+        // The grace period is for trains missing from the poll, not for e.g. a bus replacement that ended
+        self.entities.retain(|id, state| {
+            let withdrawn = !polled.contains(id)
+                && parent_entity_id(id).is_some_and(|parent| polled.contains(parent));
+
+            !withdrawn && now - state.last_seen <= grace_period_secs
+        });
+    }
+
+    /// Redraws the replacement shapes of modified trips through the recent positions of their train, which
+    /// a single poll doesn't know about.
+    fn redraw_detours(&self, entities: &mut Vec<FeedEntity>) {
+        let ids = entities
+            .iter()
+            .map(|entity| entity.id.clone())
+            .collect::<HashSet<String>>();
+
+        let mut redrawn = vec![];
+
+        for entity in entities.iter() {
+            let Some(state) = self.entities.get(&entity.id) else {
+                continue;
+            };
+
+            if state.recent_positions.len() < MIN_DIVERGENT_POSITIONS
+                || !ids.contains(&format!("{}-modifications", entity.id))
+            {
+                continue;
+            }
+
+            let (Some(trip_update), Some(scheduled)) =
+                (&entity.trip_update, scheduled_trip(entity))
+            else {
+                continue;
+            };
+
+            let (Some(trip), Some(start_date)) = (
+                scheduled
+                    .trip_id
+                    .as_ref()
+                    .and_then(|trip_id| self.gtfs.trips.get(trip_id)),
+                scheduled.start_date.as_deref(),
+            ) else {
+                continue;
+            };
+
+            redrawn.push((
+                entity.id.clone(),
+                trip_modification_entities(
+                    &self.gtfs,
+                    trip,
+                    &entity.id,
+                    start_date,
+                    &trip_update.stop_time_update,
+                    &state.recent_positions,
+                    trip_update.timestamp,
+                ),
+            ));
+        }
+
+        if redrawn.is_empty() {
+            return;
+        }
+
+        for (id, replacement) in redrawn {
+            let derived = [format!("{}-modifications", id), format!("{}-shape", id)];
+
+            entities.retain(|entity| !derived.contains(&entity.id));
+            entities.extend(replacement);
+        }
+
+        entities.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+
+    /// Moves every vehicle of a scheduled trip along its shape, see [`extrapolate_along_trip`].
+    fn extrapolate(&mut self, entities: &mut [FeedEntity], now: i64, max_horizon_secs: i64) {
+        for entity in entities.iter_mut() {
+            let skipped_stop_ids = entity
+                .trip_update
+                .iter()
+                .flat_map(|trip_update| trip_update.stop_time_update.iter())
+                .filter(|stop_time_update| {
+                    stop_time_update.schedule_relationship == Some(Skipped as i32)
+                })
+                .filter_map(|stop_time_update| stop_time_update.stop_id.clone())
+                .collect::<Vec<String>>();
+
+            let Some(vehicle) = entity.vehicle.as_mut() else {
+                continue;
+            };
+
+            let Some(trip) = vehicle
+                .trip
+                .as_ref()
+                .and_then(|trip| trip.trip_id.as_ref())
+                .and_then(|trip_id| self.gtfs.trips.get(trip_id))
+            else {
+                continue;
+            };
+
+            let (Some(position), Some(timestamp)) = (vehicle.position.as_mut(), vehicle.timestamp)
+            else {
+                continue;
+            };
+
+            // Stale vehicles have no speed, so they stay where they were last seen
+            let Some(extrapolation) = position.speed.and_then(|speed| {
+                extrapolate_along_trip(
+                    &mut self.shapes,
+                    &self.gtfs,
+                    trip,
+                    (position.latitude as f64, position.longitude as f64),
+                    speed,
+                    timestamp as i64,
+                    now,
+                    max_horizon_secs,
+                    &skipped_stop_ids,
+                )
+            }) else {
+                continue;
+            };
+
+            position.latitude = extrapolation.point.0 as f32;
+            position.longitude = extrapolation.point.1 as f32;
+            position.odometer = Some(extrapolation.odometer_m);
+
+            // The position is where the train should be by now, not where it was reported
+            vehicle.timestamp = Some((timestamp as i64 + extrapolation.elapsed_secs) as u64);
+
+            self.results.extrapolated_vehicles.insert(entity.id.clone());
+        }
+    }
+
+    /// Entities to publish, marking stale vehicles.
+    fn published_entities(&mut self, now: i64) -> Vec<FeedEntity> {
+        self.results.stale_vehicles.clear();
+
+        let mut ids = self.entities.keys().cloned().collect::<Vec<String>>();
+        ids.sort();
+
+        ids.into_iter()
+            .filter_map(|id| {
+                let state = self.entities.get(&id)?;
+                let mut entity = state.entity.clone();
+
+                if let Some(vehicle) = entity.vehicle.as_mut() {
+                    let position_age = vehicle
+                        .timestamp
+                        .map(|timestamp| now - timestamp as i64)
+                        .unwrap_or(now - state.last_seen);
+
+                    if state.last_seen < now || position_age > self.config.stale_after_secs {
+                        // The train isn't moving as far as anyone knows
+                        if let Some(position) = vehicle.position.as_mut() {
+                            position.speed = None;
+                        }
+
+                        self.results.stale_vehicles.insert(id);
+                    }
+                }
+
+                Some(entity)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{NOW, event, stop, train_entity, with_vehicle};
+
+    /// Train 11 reporting its arrival at PDX.
+    fn entity(arrival: Option<StopTimeEvent>, latitude: f32, timestamp: i64) -> FeedEntity {
+        let pdx = StopTimeUpdate {
+            stop_sequence: Some(20),
+            ..stop("PDX", arrival, None)
+        };
+
+        with_vehicle(
+            train_entity("20260119", "11", vec![pdx]),
+            latitude,
+            timestamp as u64,
+        )
+    }
+
+    fn engine() -> AmtrakFeedEngine {
+        AmtrakFeedEngine::with_config(
+            Arc::new(Gtfs::default()),
+            EngineConfig {
+                smooth_motion: false,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn published(engine: &mut AmtrakFeedEngine, now: i64) -> Vec<FeedEntity> {
+        engine.published_entities(now)
+    }
+
+    #[test]
+    fn grace_period_and_stale_vehicles() {
+        let mut engine = engine();
+
+        engine.ingest(vec![entity(None, 45.0, NOW)], NOW);
+        assert_eq!(published(&mut engine, NOW).len(), 1);
+        assert!(!engine.is_stale("20260119-11"));
+
+        // Missing from the next poll, still published but stale
+        engine.ingest(vec![], NOW + 60);
+        let entities = published(&mut engine, NOW + 60);
+
+        assert_eq!(entities.len(), 1);
+        assert!(engine.is_stale("20260119-11"));
+        let position = entities[0].vehicle.as_ref().unwrap().position.unwrap();
+        assert_eq!(position.latitude, 45.0);
+        assert_eq!(position.speed, None);
+
+        // Gone after the grace period
+        engine.ingest(vec![], NOW + 11 * 60);
+        assert!(published(&mut engine, NOW + 11 * 60).is_empty());
+    }
+
+    #[test]
+    fn derived_entities_follow_their_train() {
+        let mut engine = engine();
+
+        let derived = |suffix: &str| FeedEntity {
+            id: format!("20260119-11{}", suffix),
+            ..Default::default()
+        };
+
+        engine.ingest(
+            vec![
+                entity(None, 45.0, NOW),
+                derived("-bus"),
+                derived("-modifications"),
+                derived("-shape"),
+            ],
+            NOW,
+        );
+        assert_eq!(published(&mut engine, NOW).len(), 4);
+
+        // The train is missing, everything stays for the grace period
+        engine.ingest(vec![], NOW + 60);
+        assert_eq!(published(&mut engine, NOW + 60).len(), 4);
+
+        // The train is back without its bus replacement
+        engine.ingest(
+            vec![
+                entity(None, 45.0, NOW + 120),
+                derived("-modifications"),
+                derived("-shape"),
+            ],
+            NOW + 120,
+        );
+
+        let ids = published(&mut engine, NOW + 120)
+            .into_iter()
+            .map(|entity| entity.id)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            ids,
+            vec![
+                "20260119-11",
+                "20260119-11-modifications",
+                "20260119-11-shape"
+            ]
+        );
+    }
+
+    #[test]
+    fn position_not_updated_upstream_is_stale() {
+        let mut engine = engine();
+
+        engine.ingest(vec![entity(None, 45.0, NOW - 10 * 60)], NOW);
+        published(&mut engine, NOW);
+
+        assert!(engine.is_stale("20260119-11"));
+    }
+
+    #[test]
+    fn actuals_and_positions_are_kept() {
+        let mut engine = engine();
+
+        engine.ingest(vec![entity(event(NOW - 600, 0, true), 45.0, NOW)], NOW);
+
+        // Track-A-Train forgot the arrival, and sent no coordinates
+        let mut update = entity(event(NOW + 600, 0, false), 0.0, NOW + 30);
+        update.vehicle.as_mut().unwrap().position = Some(Position::default());
+
+        engine.ingest(vec![update], NOW + 30);
+
+        let entities = published(&mut engine, NOW + 30);
+        let arrival = entities[0].trip_update.as_ref().unwrap().stop_time_update[0].arrival;
+
+        assert_eq!(arrival, event(NOW - 600, 0, true));
+
+        let vehicle = entities[0].vehicle.as_ref().unwrap();
+        assert_eq!(vehicle.position.unwrap().latitude, 45.0);
+        assert_eq!(vehicle.timestamp, Some(NOW as u64));
+    }
+
+    #[test]
+    fn undecodable_polls_keep_trains() {
+        let mut engine = engine();
+
+        // Departing PDX before arriving
+        let mut train = entity(event(NOW, 0, false), 45.0, NOW);
+        train.trip_update.as_mut().unwrap().stop_time_update[0].departure =
+            event(NOW - 60, 0, false);
+
+        engine.ingest(vec![train], NOW);
+
+        let undecodable = AmtrakPayloads {
+            track_a_train: "not encrypted".to_string(),
+            ..Default::default()
+        };
+
+        let feeds = engine.update_at(&undecodable, NOW + 30);
+
+        assert_eq!(feeds.vehicle_positions.entity.len(), 1);
+        assert_eq!(feeds.trip_updates.entity.len(), 1);
+
+        let results = engine.last_results();
+        assert!(results.stale_vehicles.contains("20260119-11"));
+        assert!(results.poll_error.is_some());
+
+        // The state isn't repaired, so the repair is reported on every update
+        assert_eq!(results.repairs.len(), 1);
+        assert_eq!(
+            feeds.trip_updates.entity[0]
+                .trip_update
+                .as_ref()
+                .unwrap()
+                .stop_time_update[0]
+                .departure
+                .and_then(|event| event.time),
+            Some(NOW)
+        );
+
+        engine.config.repair_strategy = None;
+        engine.update_at(&undecodable, NOW + 60);
+        assert!(engine.last_results().repairs.is_empty());
+    }
+
+    #[test]
+    fn dead_reckoning() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+
+        let mut engine = AmtrakFeedEngine::with_config(
+            Arc::new(gtfs),
+            EngineConfig {
+                smooth_motion: false,
+                dead_reckoning_horizon_secs: Some(180),
+                ..Default::default()
+            },
+        );
+
+        let mut moving = entity(None, 46.0, NOW - 60);
+        moving.vehicle.as_mut().unwrap().trip = Some(gtfs_realtime::TripDescriptor {
+            trip_id: Some("T11".to_string()),
+            ..Default::default()
+        });
+        let position = moving.vehicle.as_mut().unwrap().position.as_mut().unwrap();
+        position.longitude = -122.7406;
+
+        engine.ingest(vec![moving], NOW);
+
+        let mut entities = published(&mut engine, NOW);
+        engine.extrapolate(&mut entities, NOW, 180);
+
+        assert!(engine.is_extrapolated("20260119-11"));
+
+        let position = entities[0].vehicle.as_ref().unwrap().position.unwrap();
+        // 60 seconds at 20 m/s towards Portland, to the south
+        assert!(position.latitude < 46.0 && position.latitude > 45.98);
+        assert!(position.odometer.is_some());
+        // Reported a minute ago, moved to now
+        assert_eq!(
+            entities[0].vehicle.as_ref().unwrap().timestamp,
+            Some(NOW as u64)
+        );
+
+        // The engine's state keeps the reported position
+        let entities = published(&mut engine, NOW);
+        assert_eq!(
+            entities[0]
+                .vehicle
+                .as_ref()
+                .unwrap()
+                .position
+                .unwrap()
+                .latitude,
+            46.0
+        );
+
+        // Stale vehicles aren't moved
+        engine.ingest(vec![], NOW + 30);
+        let mut entities = published(&mut engine, NOW + 30);
+        engine.results.extrapolated_vehicles.clear();
+        engine.extrapolate(&mut entities, NOW + 30, 180);
+
+        assert!(!engine.is_extrapolated("20260119-11"));
+    }
+
+    #[test]
+    fn detours_need_several_positions_off_the_shape() {
+        let gtfs = Arc::new(crate::test_helpers::coast_starlight_gtfs());
+        let mut engine = AmtrakFeedEngine::with_config(
+            gtfs.clone(),
+            EngineConfig {
+                smooth_motion: false,
+                ..Default::default()
+            },
+        );
+
+        let scheduled = gtfs_realtime::TripDescriptor {
+            trip_id: Some("T11".to_string()),
+            start_date: Some("20260119".to_string()),
+            ..Default::default()
+        };
+
+        let skipped_sac = vec![
+            StopTimeUpdate {
+                stop_sequence: Some(30),
+                stop_id: Some("SAC".to_string()),
+                schedule_relationship: Some(Skipped as i32),
+                ..Default::default()
+            },
+            StopTimeUpdate {
+                stop_id: Some("EUG".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        // Detoured from SAC to EUG, between SEA and PDX but far east of the shape
+        let poll = |latitude: f32, timestamp: i64| {
+            let mut train = entity(None, latitude, timestamp);
+
+            let trip_update = train.trip_update.as_mut().unwrap();
+            trip_update.stop_time_update = skipped_sac.clone();
+            trip_update.trip = scheduled.clone();
+            crate::trip_modifications::link_modified_trip(&mut trip_update.trip, &train.id);
+
+            let vehicle = train.vehicle.as_mut().unwrap();
+            vehicle.trip = Some(scheduled.clone());
+            vehicle.position.as_mut().unwrap().longitude = -121.0;
+
+            let modifications = trip_modification_entities(
+                &gtfs,
+                gtfs.trips.get("T11").unwrap(),
+                &train.id,
+                "20260119",
+                &skipped_sac,
+                &[],
+                None,
+            );
+
+            std::iter::once(train)
+                .chain(modifications)
+                .collect::<Vec<FeedEntity>>()
+        };
+
+        let shape = |entities: &[FeedEntity]| {
+            entities
+                .iter()
+                .find(|entity| entity.id == "20260119-11-shape")
+                .and_then(|entity| entity.shape.clone())
+                .and_then(|shape| shape.encoded_polyline)
+        };
+
+        let scheduled_shape = shape(&poll(46.9, NOW));
+        let mut path = vec![];
+
+        for (i, latitude) in [46.9_f32, 46.5, 46.1].into_iter().enumerate() {
+            let now = NOW + 60 * i as i64;
+            path.push((latitude as f64, -121.0));
+
+            engine.ingest(poll(latitude, now), now);
+
+            let mut entities = published(&mut engine, now);
+            engine.redraw_detours(&mut entities);
+
+            assert_eq!(entities.len(), 3);
+
+            if path.len() < MIN_DIVERGENT_POSITIONS {
+                assert_eq!(shape(&entities), scheduled_shape);
+            } else {
+                let detour = crate::trip_modifications::replacement_shape(
+                    &gtfs,
+                    gtfs.trips.get("T11").unwrap(),
+                    &skipped_sac,
+                    true,
+                    &path,
+                )
+                .unwrap();
+
+                assert_eq!(detour[1..4], path);
+                assert_eq!(
+                    shape(&entities),
+                    Some(crate::shapes::encode_polyline(&detour))
+                );
+            }
+        }
+    }
+}
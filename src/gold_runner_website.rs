@@ -0,0 +1,31 @@
+use crate::advisory_source::{AdvisorySource, parse_heading_advisories};
+use gtfs_realtime::FeedEntity;
+
+/// The San Joaquins, renamed Gold Runner in 2025.
+pub struct GoldRunner;
+
+impl AdvisorySource for GoldRunner {
+    fn id_prefix(&self) -> &'static str {
+        "GOLD_RUN"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Gold Runner"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://amtrakgoldrunner.com/service-alerts/"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_heading_advisories(
+            html,
+            ".entry-content",
+            Some("h2"),
+            "h3",
+            self.id_prefix(),
+            "Service Alerts",
+            route_id,
+        )
+    }
+}
@@ -0,0 +1,30 @@
+use crate::advisory_source::{AdvisorySource, parse_heading_advisories};
+use gtfs_realtime::FeedEntity;
+
+pub struct Hiawatha;
+
+impl AdvisorySource for Hiawatha {
+    fn id_prefix(&self) -> &'static str {
+        "HIAWATHA"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Hiawatha"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://www.hiawathaservice.com/service-alerts/"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_heading_advisories(
+            html,
+            ".entry-content",
+            None,
+            "h3",
+            self.id_prefix(),
+            "Service Alerts",
+            route_id,
+        )
+    }
+}
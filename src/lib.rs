@@ -13,6 +13,7 @@
 //!use prost::Message;
 //!use gtfs_structures::Gtfs;
 //!use amtrak_gtfs_rt::fetch_amtrak_gtfs_rt;
+//!use amtrak_gtfs_rt::monotonicity::RepairStrategy;
 
 //!#[tokio::main]
 //!async fn main() {
@@ -21,7 +22,9 @@
 //!    .unwrap();
 //!
 //!    let client = reqwest::Client::new();
-//!    let amtrak_gtfs_rt = fetch_amtrak_gtfs_rt(&gtfs, &client).await.unwrap();
+//!    let amtrak_gtfs_rt = fetch_amtrak_gtfs_rt(&gtfs, &client, Some(RepairStrategy::Clamp))
+//!        .await
+//!        .unwrap();
 //!
 //!    //extract the binary data
 //!    let vehicle_data = amtrak_gtfs_rt.vehicle_positions.encode_to_vec();
@@ -35,15 +38,42 @@
 //! Thus, we've included a function `filter_capital_corridor()` which takes in any `FeedMessage` and removes CC vehicles and trips.
 
 use asm::asm_alert_to_gtfs_rt;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Weekday};
+use chrono::{Datelike, NaiveDate, Weekday};
 use geojson::FeatureCollection;
 use gtfs_realtime::FeedEntity;
 use gtfs_realtime::FeedMessage;
 use gtfs_structures::Gtfs;
 use scraper::{Html, Selector};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
 use std::time::SystemTime;
+pub mod alert_classifier;
+pub mod alert_ids;
+pub mod alert_tracker;
+pub mod archive;
 pub mod asm;
+pub mod bus_substitution;
+pub mod cancellations;
+pub mod dead_reckoning;
+pub mod delay_propagation;
+pub mod engine;
+pub mod map_matching;
+pub mod monotonicity;
+pub mod motion;
+pub mod on_time_performance;
+pub mod prediction;
+pub mod realized_trips;
+pub mod replay;
+pub mod schedule;
+pub mod segment_times;
+pub mod service_date;
+pub mod shapes;
+pub mod short_turns;
+pub mod station_times;
+pub mod stop_times;
+pub mod timestamps;
+pub mod timezones;
+pub mod trip_modifications;
 
 pub const DEFAULT_PROXIES: &[&str] = &[
     "http://45.59.186.60:80",
@@ -71,41 +101,65 @@ pub const DEFAULT_PROXIES: &[&str] = &[
     "http://162.255.110.107:8080",
 ];
 
+/// Entities derived from a train's entity `{id}`, e.g. its bus replacement `{id}-bus`.
+const DERIVED_ENTITY_SUFFIXES: [&str; 4] = ["-bus", "-short-turn", "-modifications", "-shape"];
+
+/// The entity id of the train a derived entity belongs to.
+pub(crate) fn parent_entity_id(id: &str) -> Option<&str> {
+    DERIVED_ENTITY_SUFFIXES
+        .iter()
+        .find_map(|suffix| id.strip_suffix(suffix))
+}
+
 //Written by Kyler Chin - Catenary Transit Initiatives.
 pub fn filter_capital_corridor(input: FeedMessage) -> FeedMessage {
     let cc_route_id = "84";
 
-    FeedMessage {
-        entity: input
-            .entity
-            .into_iter()
-            .filter(|item| {
-                if item.vehicle.is_some() {
-                    let vehicle = item.vehicle.as_ref().unwrap();
-                    if vehicle.trip.is_some() {
-                        let trip = vehicle.trip.as_ref().unwrap();
-                        if trip.route_id.is_some()
-                            && trip.route_id.as_ref().unwrap().as_str() == cc_route_id
-                        {
-                            return false;
-                        }
-                    }
+    let is_capital_corridor = |item: &FeedEntity| {
+        if item.vehicle.is_some() {
+            let vehicle = item.vehicle.as_ref().unwrap();
+            if vehicle.trip.is_some() {
+                let trip = vehicle.trip.as_ref().unwrap();
+                if trip.route_id.is_some()
+                    && trip.route_id.as_ref().unwrap().as_str() == cc_route_id
+                {
+                    return true;
                 }
+            }
+        }
 
-                if item.trip_update.is_some() {
-                    let trip_update = item.trip_update.as_ref().unwrap();
-                    let trip = &trip_update.trip;
+        if item.trip_update.is_some() {
+            let trip_update = item.trip_update.as_ref().unwrap();
+            let trip = &trip_update.trip;
 
-                    if trip.route_id.is_some() {
-                        let route_id = trip.route_id.as_ref().unwrap();
+            if trip.route_id.is_some() {
+                let route_id = trip.route_id.as_ref().unwrap();
 
-                        if route_id == cc_route_id {
-                            return false;
-                        }
-                    }
+                if route_id == cc_route_id {
+                    return true;
                 }
+            }
+        }
+
+        false
+    };
 
-                true
+    // Modifications, shapes and alerts derived from a CC train don't carry its route
+    let cc_entity_ids = input
+        .entity
+        .iter()
+        .filter(|item| is_capital_corridor(item))
+        .map(|item| item.id.clone())
+        .collect::<HashSet<String>>();
+
+    FeedMessage {
+        entity: input
+            .entity
+            .into_iter()
+            .filter(|item| {
+                !cc_entity_ids.contains(&item.id)
+                    && !parent_entity_id(&item.id)
+                        .is_some_and(|parent| cc_entity_ids.contains(parent))
             })
             .collect::<Vec<FeedEntity>>(),
         header: input.header,
@@ -122,6 +176,8 @@ pub struct GtfsAmtrakResults {
 #[derive(Clone, Debug)]
 pub struct GtfsAmtrakResultsJoined {
     pub unified_feed: FeedMessage,
+    /// Stop times repaired in the unified feed, see [`monotonicity::enforce_feed_monotonicity`]
+    pub repairs: Vec<monotonicity::Repair>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -184,43 +240,46 @@ fn feature_to_amtrak_arrival_structs(feature: &geojson::Feature) -> Vec<AmtrakAr
     amtrak_arrival_jsons
 }
 
+/// Speed in meters per second, `None` unless Track-A-Train gave a plausible one.
 fn get_speed(feature: &geojson::Feature) -> Option<f32> {
-    match feature.properties.as_ref().unwrap().get("Velocity") {
-        Some(speed_text) => match speed_text {
-            serde_json::value::Value::String(x) => {
-                Some(x.as_str().parse::<f32>().unwrap() * 0.44704)
-            }
-            _ => None,
-        },
-        _ => None,
-    }
+    motion::parse_speed(feature.properties.as_ref()?.get("Velocity")?)
 }
 
 fn get_bearing(feature: &geojson::Feature) -> Option<f32> {
-    match feature.properties.as_ref().unwrap().get("Heading") {
-        Some(bearing_text) => match bearing_text {
-            serde_json::value::Value::String(x) => match x.as_str() {
-                "N" => Some(0.001),
-                "NE" => Some(45.0),
-                "E" => Some(90.0),
-                "SE" => Some(135.0),
-                "S" => Some(180.0),
-                "SW" => Some(225.0),
-                "W" => Some(270.0),
-                "NW" => Some(315.0),
-                _ => None,
-            },
-            _ => None,
-        },
+    match feature.properties.as_ref()?.get("Heading")? {
+        serde_json::value::Value::String(x) => motion::compass_to_bearing(x),
         _ => None,
     }
 }
 
+/// Maps every `route_long_name` in the schedule to its `route_id`.
+pub fn long_name_to_route_id(gtfs: &Gtfs) -> HashMap<String, String> {
+    HashMap::from_iter(gtfs.routes.values().filter_map(|route| {
+        route
+            .long_name
+            .as_ref()
+            .map(|long_name| (long_name.clone(), route.id.clone()))
+    }))
+}
+
+/// Looks up the route id of an Amtrak route name, as used by Track-A-Train and Amtrak.com.
+/// Gold Runner is published in a separate GTFS, where the route id is always `GR`.
+pub fn route_name_to_route_id(
+    long_name_to_route_id: &HashMap<String, String>,
+    route_name: &str,
+) -> Option<String> {
+    match route_name {
+        "Gold Runner" => Some("GR".to_string()),
+        _ => long_name_to_route_id.get(route_name).cloned(),
+    }
+}
+
+/// Converts one Track-A-Train feature into its unified entity, followed by any alerts generated for the trip.
 fn feature_to_gtfs_unified(
     gtfs: &Gtfs,
     feature: &geojson::Feature,
     asm_lookup_table: Option<&HashMap<(NaiveDate, String), Vec<asm::AsmAlert>>>,
-) -> FeedEntity {
+) -> Vec<FeedEntity> {
     let geometry = feature.geometry.as_ref().unwrap();
     let point: Option<geojson::PointType> = match geometry.value.clone() {
         geojson::Value::Point {
@@ -229,12 +288,7 @@ fn feature_to_gtfs_unified(
         _ => None,
     };
 
-    let long_name_to_route_id_hashmap: HashMap<String, String> = HashMap::from_iter(
-        gtfs.routes
-            .iter()
-            .filter(|(_, route)| route.long_name.is_some())
-            .map(|(_string, route)| (route.long_name.as_ref().unwrap().clone(), route.id.clone())),
-    );
+    let long_name_to_route_id_hashmap = long_name_to_route_id(gtfs);
 
     let mut trip_name_to_id_hashmap: HashMap<String, Vec<String>> = HashMap::new();
 
@@ -272,7 +326,7 @@ fn feature_to_gtfs_unified(
         _ => None,
     };
 
-    let origin_tz = match feature.properties.as_ref().unwrap().get("OriginTZ") {
+    let origin_tz_code = match feature.properties.as_ref().unwrap().get("OriginTZ") {
         Some(a) => match a {
             serde_json::value::Value::String(x) if x.len() == 1 => Some(x.chars().next().unwrap()),
             _ => None,
@@ -290,129 +344,111 @@ fn feature_to_gtfs_unified(
     }
     .unwrap();
 
+    // Service dates are in the agency's timezone, without it no train can be placed on one
+    let agency_tz = match timezones::agency_timezone(gtfs) {
+        Some(Ok(agency_tz)) => agency_tz,
+        Some(Err(e)) => {
+            log_once(
+                "agency timezone".to_string(),
+                format_args!("Agency {}, skipping trains", e),
+            );
+            return vec![];
+        }
+        None => {
+            log_once(
+                "agency timezone".to_string(),
+                format_args!("No agency timezone in the schedule, skipping trains"),
+            );
+            return vec![];
+        }
+    };
+
     let features_list = feature_to_amtrak_arrival_structs(feature);
 
-    let arrivals: Vec<gtfs_realtime::trip_update::StopTimeUpdate> =
-    features_list
+    let station_timezones = features_list
         .iter()
-        .enumerate()
-        .map(|(i, feature)| gtfs_realtime::trip_update::StopTimeUpdate {
-            stop_sequence: None,
-            stop_id: Some(feature.code.clone()),
-            arrival: match &feature.postarr {
-                Some(postarr) => Some(gtfs_realtime::trip_update::StopTimeEvent {
-                    delay: None,
-                    time: time_and_tz_to_unix(postarr, feature.tz),
-                    uncertainty: None,
-                    scheduled_time: None,
-                }),
-                None => match &feature.estarr {
-                    Some(estarr) => Some(gtfs_realtime::trip_update::StopTimeEvent {
-                        delay: None,
-                        time: time_and_tz_to_unix(estarr, feature.tz),
-                        uncertainty: None,
-                        scheduled_time: None,
-                    }),
-                    //There is no provided arrival time, interpolate it from the previous stop
-                    None => match i {
-                            0 => None,
-                            _ => {
-                                let previous = features_list.get(i - 1);
-
-                                match previous {
-                                    Some(previous) => {
-                                        let previous_departure_time = match &previous.postdep {
-                                            Some(postdep) => Some(time_and_tz_to_unix(postdep, feature.tz)),
-                                            None => previous.estdep.as_ref().map(|estdep| time_and_tz_to_unix(estdep, feature.tz)),
-                                        };
-
-                                        match previous_departure_time {
-                                            None => None,
-                                            Some(previous_departure_time) => {
-                                                match &previous.schdep {
-                                                    Some(previous_schdep) => {
-                                                        let prev_sch_dep = time_and_tz_to_unix(previous_schdep, feature.tz);
-                                                        let delay = match (previous_departure_time,prev_sch_dep) {
-                                                            (Some(a), Some(b)) => Some(a - b),
-                                                            _ => None
-                                                        };
-
-                                                        match delay {
-                                                            Some( delay) => {
-                                                                match &feature.scharr {
-                                                                    Some(scharr) => {
-                                                                        let arrival_time = time_and_tz_to_unix(scharr, feature.tz);
-
-                                                                        match arrival_time {
-                                                                            Some(arrival_time) => {
-                                                                                let arrival_time = arrival_time + delay;
-        
-                                                                        Some(gtfs_realtime::trip_update::StopTimeEvent {
-                                                                            delay: Some(delay.try_into().unwrap()),
-                                                                            time: Some(arrival_time),
-                                                                            uncertainty: None,
-                                                                            scheduled_time: None,
-                                                                        })
-                                                                            },
-                                                                            None => None
-                                                                        }
-        
-                                                                        
-                                                                    },
-                                                                    None => None,
-                                                                }
-                                                            },
-                                                            None => None
-                                                        }
-                                                    },
-                                                    None => None
-                                                }
-                                            }
-                                        }
-                                    },
-                                    None => None,
-                                }
-                        },
-                }
-            }},
-            departure: match &feature.postdep {
-                Some(postdep) => Some(gtfs_realtime::trip_update::StopTimeEvent {
-                    delay: None,
-                    time: time_and_tz_to_unix(postdep, feature.tz),
-                    uncertainty: None,
-                    scheduled_time: None,
-                }),
-                None => feature.estdep.as_ref().map(|estdep| gtfs_realtime::trip_update::StopTimeEvent {
-                    delay: None,
-                    time: time_and_tz_to_unix(estdep, feature.tz),
-                    uncertainty: None,
-                    scheduled_time: None,
-                })},
-            departure_occupancy_status: None,
-            schedule_relationship: match feature.schcmnt.as_str() {
-                "Canceled" => Some(1),
-                _ => None
-            },
-            stop_time_properties: None,
+        .map(|station| {
+            timezones::station_timezone(gtfs, &station.code, station.tz)
+                .map_err(|e| {
+                    log_once(
+                        format!("station timezone {}", station.code),
+                        format_args!("Timezone of station {}: {}", station.code, e),
+                    )
+                })
+                .ok()
         })
-        .collect::<Vec<gtfs_realtime::trip_update::StopTimeUpdate>>();
+        .collect::<Vec<Option<chrono_tz::Tz>>>();
+
+    // The origin is usually the first station, otherwise only the zone letter is known
+    let origin_tz = match station_timezones.first().copied().flatten() {
+        Some(timezone) => Some(timezone),
+        None => timezones::tz_from_code(origin_tz_code)
+            .map_err(|e| {
+                log_once(
+                    format!("origin timezone {}", origin_tz_code),
+                    format_args!("Origin {}, leaving its departure unresolved", e),
+                )
+            })
+            .ok(),
+    };
 
-    let origin_local_time = origin_departure(&origin_time_string, origin_tz);
+    let (station_times, timestamp_errors) = station_times::resolve_station_times(
+        &features_list,
+        &station_timezones,
+        timestamp.map(|timestamp| timestamp as i64),
+    );
 
-    let mut starting_service_date_new_york = origin_local_time
-        .with_timezone(&chrono_tz::America::New_York)
-        .date_naive();
+    for (code, e) in timestamp_errors {
+        log_once(
+            format!("station time {}", code),
+            format_args!("Station time at {} left out: {}", code, e),
+        );
+    }
+
+    let origin_departure = origin_tz.and_then(|origin_tz| {
+        origin_departure(&origin_time_string, origin_tz)
+            .map_err(|e| {
+                log_once(
+                    format!(
+                        "origin departure {}",
+                        trip_name.as_deref().unwrap_or_default()
+                    ),
+                    format_args!("Origin departure of train {:?}: {}", trip_name, e),
+                )
+            })
+            .ok()
+    });
+
+    let first_scheduled =
+        features_list
+            .iter()
+            .zip(station_times.iter())
+            .find_map(|(station, times)| {
+                times
+                    .scheduled_departure
+                    .or(times.scheduled_arrival)
+                    .map(|time| (station.code.as_str(), time))
+            });
 
-    // Check if the computed date is off by ~24 hours by comparing scheduled vs realtime
+    // Without the origin's departure, the first scheduled time stands in for it, else the train's last report
+    let origin_local_time = origin_departure.unwrap_or_else(|| {
+        let guess = first_scheduled
+            .map(|(_, time)| time)
+            .or(timestamp.map(|timestamp| timestamp as i64))
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+        chrono::DateTime::from_timestamp(guess, 0)
+            .unwrap_or_default()
+            .with_timezone(&origin_tz.unwrap_or(agency_tz))
+    });
+
+    // Check if the computed date is off by whole days by comparing scheduled vs realtime
     // for the first stop with both values available
-    let date_offset = detect_date_offset_from_delay(&features_list);
-    if date_offset != 0 {
-        starting_service_date_new_york =
-            starting_service_date_new_york + chrono::Duration::days(date_offset as i64);
-    }
+    let date_offset = detect_date_offset_from_delay(&station_times);
 
-    let starting_yyyy_mm_dd_in_new_york =
-        starting_service_date_new_york.format("%Y%m%d").to_string();
+    // Used to match the trip, then refined with the matched trip's schedule
+    let starting_service_date =
+        service_date::service_date(gtfs, None, &origin_local_time, agency_tz, date_offset);
 
     let origin_weekday = origin_local_time.weekday();
 
@@ -484,10 +520,8 @@ fn feature_to_gtfs_unified(
                                                 let calendar =
                                                     gtfs.calendar.get(&trip.service_id).unwrap();
 
-                                                starting_service_date_new_york
-                                                    >= calendar.start_date
-                                                    && starting_service_date_new_york
-                                                        <= calendar.end_date
+                                                starting_service_date >= calendar.start_date
+                                                    && starting_service_date <= calendar.end_date
                                             })
                                             .collect::<Vec<&&String>>();
 
@@ -507,31 +541,156 @@ fn feature_to_gtfs_unified(
         },
     };
 
-    let id = match &train_num {
-        Some(train_num) => Some(format!("{}-{}", starting_yyyy_mm_dd_in_new_york, train_num)),
-        None => None,
+    let starting_service_date = match trip_id.as_ref().and_then(|trip_id| gtfs.trips.get(trip_id)) {
+        Some(trip) => {
+            service_date::service_date(gtfs, Some(trip), &origin_local_time, agency_tz, date_offset)
+        }
+        None => starting_service_date,
     };
 
-    let route_id: Option<String> = match route_name {
-        Some(route_name) => match route_name.as_str() {
-            "Gold Runner" => Some("GR".to_string()),
-            _ => long_name_to_route_id_hashmap
-                .get(&route_name.clone())
-                .cloned(),
-        },
+    // Without the origin's departure, the trip must be scheduled at the first station at the time
+    // Track-A-Train gives. A scheduled train that can't be matched only gets its position published,
+    // rather than being passed off as a new trip.
+    let trip_unconfirmed = origin_departure.is_none()
+        && match trip_id.as_ref().and_then(|trip_id| gtfs.trips.get(trip_id)) {
+            Some(trip) => !first_scheduled.is_some_and(|(code, time)| {
+                schedule::trip_scheduled_at(trip, starting_service_date, agency_tz, code, time)
+            }),
+            None => train_num
+                .as_ref()
+                .is_some_and(|train_num| trip_name_to_id_hashmap.contains_key(train_num)),
+        };
+
+    let starting_yyyy_mm_dd = starting_service_date.format("%Y%m%d").to_string();
+
+    let id = match &train_num {
+        Some(train_num) => Some(format!("{}-{}", starting_yyyy_mm_dd, train_num)),
         None => None,
     };
 
+    let route_id: Option<String> = route_name
+        .as_deref()
+        .and_then(|route_name| route_name_to_route_id(&long_name_to_route_id_hashmap, route_name));
+
     let bearing: Option<f32> = get_bearing(feature);
 
+    // Trains that can't be matched to the schedule (extra sections, specials) are NEW trips,
+    // identified by their entity id and described by their own stop list.
+    // A scheduled train with every stop canceled is a CANCELED trip.
+    let schedule_relationship = match &trip_id {
+        None => Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::New as i32),
+        Some(_) if cancellations::all_stops_canceled(&features_list) => {
+            Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::Canceled as i32)
+        }
+        Some(_) => None,
+    };
+
+    let is_new_trip = trip_id.is_none();
+
+    let trip_properties = match is_new_trip {
+        true => Some(gtfs_realtime::trip_update::TripProperties {
+            trip_id: None,
+            start_date: Some(starting_yyyy_mm_dd.clone()),
+            start_time: None,
+            shape_id: None,
+            trip_headsign: None,
+            trip_short_name: train_num.clone(),
+        }),
+        false => None,
+    };
+
+    let trip_id = match is_new_trip {
+        true => id.clone(),
+        false => trip_id,
+    };
+
+    let is_canceled = schedule_relationship
+        == Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::Canceled as i32);
+
+    // Actual times are marked with an uncertainty of 0, as GTFS-rt recommends.
+    // The scheduled time lets consumers compute delays without the static schedule.
+    let event = |time: Option<i64>, delay: Option<i64>, actual: bool, scheduled: Option<i64>| {
+        time.map(|time| gtfs_realtime::trip_update::StopTimeEvent {
+            delay: delay.and_then(|delay| i32::try_from(delay).ok()),
+            time: Some(time),
+            uncertainty: actual.then_some(0),
+            scheduled_time: scheduled,
+        })
+    };
+
+    let scheduled_trip = trip_id
+        .as_ref()
+        .filter(|_| !is_new_trip)
+        .and_then(|trip_id| gtfs.trips.get(trip_id));
+
+    let codes = features_list
+        .iter()
+        .map(|feature| feature.code.as_str())
+        .collect::<Vec<&str>>();
+
+    // Stations without an estimate get the delay carried from earlier stations
+    let propagated_times = delay_propagation::propagate_delays(
+        &station_times,
+        &delay_propagation::scheduled_dwells(scheduled_trip, &codes),
+    );
+
+    // No stop time updates are needed for a canceled trip
+    let mut arrivals: Vec<gtfs_realtime::trip_update::StopTimeUpdate> = match is_canceled {
+        true => vec![],
+        false => features_list
+            .iter()
+            .zip(propagated_times.iter().zip(station_times.iter()))
+            .map(
+                |(feature, (times, station_times))| gtfs_realtime::trip_update::StopTimeUpdate {
+                    stop_sequence: None,
+                    stop_id: Some(feature.code.clone()),
+                    arrival: event(
+                        times.arrival,
+                        times.arrival_delay,
+                        station_times.actual_arrival,
+                        station_times.scheduled_arrival,
+                    ),
+                    departure: event(
+                        times.departure,
+                        times.departure_delay,
+                        station_times.actual_departure,
+                        station_times.scheduled_departure,
+                    ),
+                    departure_occupancy_status: None,
+                    schedule_relationship: match feature.schcmnt.as_str() {
+                        "Canceled" => Some(1),
+                        _ => None,
+                    },
+                    stop_time_properties: None,
+                },
+            )
+            .collect(),
+    };
+
+    // Stop sequences disambiguate skipped stations for consumers, and are only known for scheduled trips
+    if let Some(trip) = scheduled_trip {
+        let stop_sequences = schedule::stop_sequences_for_codes(trip, &codes);
+
+        for (arrival, stop_sequence) in arrivals.iter_mut().zip(stop_sequences) {
+            arrival.stop_sequence = stop_sequence;
+        }
+    }
+
+    let (arrivals, bus_stops) = bus_substitution::split_bus_stops(arrivals, &features_list);
+
+    let short_turn = match is_canceled {
+        true => None,
+        false => short_turns::detect_short_turn(&features_list),
+    };
+
     let trip_desc = gtfs_realtime::TripDescriptor {
         trip_id: trip_id.clone(),
         route_id: route_id.clone(),
         direction_id: None,
         start_time: None,
-        start_date: Some(starting_yyyy_mm_dd_in_new_york.clone()),
+        start_date: Some(starting_yyyy_mm_dd.clone()),
         modified_trip: None,
-        schedule_relationship: None,
+        schedule_relationship,
     };
 
     let informed_entity = gtfs_realtime::EntitySelector {
@@ -547,7 +706,7 @@ fn feature_to_gtfs_unified(
         Some(train_num) => match asm_lookup_table {
             Some(asm_lookup_table) => {
                 match asm_lookup_table.get(&(origin_local_time.date_naive(), train_num.clone())) {
-                    Some(alerts) => asm_alert_to_gtfs_rt(informed_entity, alerts),
+                    Some(alerts) => asm_alert_to_gtfs_rt(informed_entity.clone(), alerts),
                     None => None,
                 }
             }
@@ -556,41 +715,116 @@ fn feature_to_gtfs_unified(
         None => None,
     };
 
-    FeedEntity {
+    let id = id.unwrap();
+
+    // A single poll has a single position, so detours through the train are left to the engine
+    let modification_entities = match is_new_trip || is_canceled {
+        true => vec![],
+        false => trip_id
+            .as_ref()
+            .and_then(|trip_id| gtfs.trips.get(trip_id))
+            .map(|trip| {
+                trip_modifications::trip_modification_entities(
+                    gtfs,
+                    trip,
+                    &id,
+                    &starting_yyyy_mm_dd,
+                    &arrivals,
+                    &[],
+                    timestamp,
+                )
+            })
+            .unwrap_or_default(),
+    };
+
+    let bus_entity = match bus_stops.is_empty() {
+        true => None,
+        false => Some(bus_substitution::bus_replacement_entity(
+            &id,
+            train_num.clone(),
+            route_id.clone(),
+            &starting_yyyy_mm_dd,
+            timestamp,
+            bus_stops,
+        )),
+    };
+
+    let short_turn_alert = match (&short_turn, &train_num) {
+        (Some(short_turn), Some(train_num)) => Some(short_turns::short_turn_alert(
+            gtfs,
+            short_turn,
+            train_num,
+            &id,
+            informed_entity,
+        )),
+        _ => None,
+    };
+
+    let mut modified_trip_desc = trip_desc.clone();
+
+    if !modification_entities.is_empty() {
+        trip_modifications::link_modified_trip(&mut modified_trip_desc, &id);
+    }
+
+    let vehicle = gtfs_realtime::VehiclePosition {
+        stop_id: None,
+        current_status: None,
+        timestamp,
+        congestion_level: None,
+        occupancy_status: None,
+        occupancy_percentage: None,
+        multi_carriage_details: vec![],
+        current_stop_sequence: None,
+        vehicle: None,
+        trip: Some(trip_desc.clone()),
+        position: Some(gtfs_realtime::Position {
+            speed,
+            odometer: None,
+            bearing,
+            latitude: point[1] as f32,
+            longitude: point[0] as f32,
+        }),
+    };
+
+    if trip_unconfirmed {
+        return vec![FeedEntity {
+            alert: None,
+            id,
+            is_deleted: Some(false),
+            trip_modifications: None,
+            stop: None,
+            shape: None,
+            trip_update: None,
+            vehicle: Some(gtfs_realtime::VehiclePosition {
+                trip: None,
+                ..vehicle
+            }),
+        }];
+    }
+
+    let entity = FeedEntity {
         alert,
-        id: id.unwrap(),
+        id,
         is_deleted: Some(false),
         trip_modifications: None,
         stop: None,
         shape: None,
         trip_update: Some(gtfs_realtime::TripUpdate {
             vehicle: None,
-            trip: trip_desc.clone(),
+            trip: modified_trip_desc,
             timestamp,
             delay: None,
             stop_time_update: arrivals,
-            trip_properties: None,
-        }),
-        vehicle: Some(gtfs_realtime::VehiclePosition {
-            stop_id: None,
-            current_status: None,
-            timestamp,
-            congestion_level: None,
-            occupancy_status: None,
-            occupancy_percentage: None,
-            multi_carriage_details: vec![],
-            current_stop_sequence: None,
-            vehicle: None,
-            trip: Some(trip_desc.clone()),
-            position: Some(gtfs_realtime::Position {
-                speed,
-                odometer: None,
-                bearing,
-                latitude: point[1] as f32,
-                longitude: point[0] as f32,
-            }),
+            trip_properties,
         }),
-    }
+        vehicle: Some(vehicle),
+    };
+
+    std::iter::once(entity)
+        .chain(bus_entity)
+        .chain(modification_entities)
+        .chain(short_turn_alert)
+        .collect()
 }
 
 pub fn make_gtfs_header() -> gtfs_realtime::FeedHeader {
@@ -607,248 +841,490 @@ pub fn make_gtfs_header() -> gtfs_realtime::FeedHeader {
     }
 }
 
-fn tz_char_to_tz(tz: char) -> Option<chrono_tz::Tz> {
-    match tz {
-        'E' => Some(chrono_tz::America::New_York),
-        'M' => Some(chrono_tz::America::Denver),
-        'P' => Some(chrono_tz::America::Los_Angeles),
-        'C' => Some(chrono_tz::America::Chicago),
-        'A' => Some(chrono_tz::America::Phoenix),
-        //NEVER SUPPOSED TO HAPPEN
-        _ => Some(chrono_tz::America::New_York),
+/// Detects if the starting date is off by whole days by analyzing delays.
+/// Looks at the first stop with both scheduled and realtime times available.
+/// Returns the number of days the date should be shifted by, e.g. -1 for a delay of about +24 hours.
+fn detect_date_offset_from_delay(station_times: &[station_times::StationTimes]) -> i64 {
+    for times in station_times {
+        // Get scheduled time (prefer arrival, fall back to departure)
+        let scheduled_time = times.scheduled_arrival.or(times.scheduled_departure);
+
+        // Get realtime/actual time (prefer arrival, fall back to departure)
+        let realtime_time = times.arrival.or(times.departure);
+
+        if let (Some(sched), Some(rt)) = (scheduled_time, realtime_time) {
+            // Found a usable pair
+            return service_date::day_offset_from_delay(rt - sched);
+        }
     }
+
+    // No usable data found
+    0
 }
 
-//for arrivals and departures, does not parse PM or AM.
-fn time_and_tz_to_unix(timestamp_text: &String, tz: char) -> Option<i64> {
-    //  println!("{}, {}", timestamp_text, tz);
-    // tz: String like "P", "C", "M", or "E"
-    //time: "12/11/2023 17:36:00"
-    let naive_dt = NaiveDateTime::parse_from_str(timestamp_text, "%m/%d/%Y %H:%M:%S").unwrap();
+//for origin departure conversion to local time representation
+//time is formatted 8/8/2025 9:51:00 AM
+pub fn origin_departure(
+    timestamp_text: &str,
+    tz: chrono_tz::Tz,
+) -> Result<chrono::DateTime<chrono_tz::Tz>, timestamps::TimestampError> {
+    timestamps::parse_amtrak_datetime(timestamp_text, tz)
+}
 
-    let local_time_representation = tz_char_to_tz(tz)
-        .unwrap()
-        .from_local_datetime(&naive_dt)
-        .latest();
+/// Problems already logged, see [`log_once`].
+static LOGGED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
 
-    match local_time_representation {
-        None => None,
-        Some(local_time_representation) => {
-            local_time_representation.timestamp().try_into().unwrap()
-        }
+/// Logs a problem the first time `key` is seen. Track-A-Train repeats the same bad data every poll.
+fn log_once(key: String, message: std::fmt::Arguments) {
+    let mut logged = LOGGED.lock().unwrap_or_else(|e| e.into_inner());
+
+    if logged.insert(key) {
+        eprintln!("{}", message);
     }
 }
 
-/// Detects if the starting date is off by approximately 24 hours by analyzing delays.
-/// Looks at the first stop with both scheduled and realtime times available.
-/// Returns: -1 if date should be shifted back 1 day, +1 if forward 1 day, 0 if no adjustment needed.
-fn detect_date_offset_from_delay(features_list: &[AmtrakArrivalJson]) -> i32 {
-    const TWENTY_TWO_HOURS_SECS: i64 = 22 * 3600;
-    const TWENTY_SIX_HOURS_SECS: i64 = 26 * 3600;
-
-    for feature in features_list {
-        // Get scheduled time (prefer arrival, fall back to departure)
-        let scheduled_time = feature
-            .scharr
-            .as_ref()
-            .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            .or_else(|| {
-                feature
-                    .schdep
-                    .as_ref()
-                    .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            });
+//time is formatted 11/18/2023 4:58:09 PM
+pub fn process_timestamp_text(timestamp_text: &str) -> Option<i64> {
+    match timestamps::parse_amtrak_datetime(timestamp_text, chrono_tz::America::New_York) {
+        Ok(datetime) => Some(datetime.timestamp()),
+        Err(e) => {
+            println!("Error parsing timestamp: {}", e);
+            None
+        }
+    }
+}
 
-        // Get realtime/actual time (prefer actual, fall back to estimated)
-        let realtime_time = feature
-            .postarr
-            .as_ref()
-            .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            .or_else(|| {
-                feature
-                    .postdep
-                    .as_ref()
-                    .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            })
-            .or_else(|| {
-                feature
-                    .estarr
-                    .as_ref()
-                    .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            })
-            .or_else(|| {
-                feature
-                    .estdep
-                    .as_ref()
-                    .and_then(|t| time_and_tz_to_unix(t, feature.tz))
-            });
+/// Raw responses of the upstream services for one poll, see [`fetch_amtrak_payloads`].
+#[derive(Clone, Debug, Default)]
+pub struct AmtrakPayloads {
+    /// Encrypted Track-A-Train response
+    pub track_a_train: String,
+    /// ASM map response, `None` when it couldn't be fetched
+    pub asm: Option<String>,
+    /// Corridor travel advisories, already converted to alerts
+    pub advisories: Vec<FeedEntity>,
+}
 
-        if let (Some(sched), Some(rt)) = (scheduled_time, realtime_time) {
-            let delay = rt - sched;
+const TRACK_A_TRAIN_URL: &str =
+    "https://maps.amtrak.com/services/MapDataService/trains/getTrainsData";
+const ASM_URL: &str = "https://asm-backend.transitdocs.com/map";
 
-            // If delay is close to +24 hours, the start date should be shifted back 1 day
-            if delay > TWENTY_TWO_HOURS_SECS && delay < TWENTY_SIX_HOURS_SECS {
-                return -1;
+async fn fetch_asm(client: &reqwest::Client) -> Option<String> {
+    match client.get(ASM_URL).send().await {
+        Ok(raw_asm_data) => match raw_asm_data.text().await {
+            Ok(asm_root) => {
+                println!("ASM data successfully downloaded");
+                Some(asm_root)
             }
-            // If delay is close to -24 hours, the start date should be shifted forward 1 day
-            if delay < -TWENTY_TWO_HOURS_SECS && delay > -TWENTY_SIX_HOURS_SECS {
-                return 1;
+            Err(e) => {
+                eprintln!("Error reading ASM data, proceeding without alerts, {:?}", e);
+                None
             }
-            // Found a usable pair, delay is reasonable
-            return 0;
+        },
+        Err(_) => {
+            eprintln!("Error fetching ASM data, proceeding without alerts");
+            None
         }
     }
+}
 
-    // No usable data found
-    0
+/// Downloads everything a poll needs, without processing it.
+pub async fn fetch_amtrak_payloads(
+    gtfs: &Gtfs,
+    client: &reqwest::Client,
+) -> Result<AmtrakPayloads, Box<dyn std::error::Error + Sync + Send>> {
+    let track_a_train = client.get(TRACK_A_TRAIN_URL).send().await?.text().await?;
+
+    Ok(AmtrakPayloads {
+        track_a_train,
+        asm: fetch_asm(client).await,
+        advisories: fetch_all_advisories(&default_advisory_sources(), client, gtfs).await,
+    })
 }
 
-//for origin departure conversion to local time representation
-pub fn origin_departure(timestamp_text: &str, tz: char) -> chrono::DateTime<chrono_tz::Tz> {
-    let naive_dt = NaiveDateTime::parse_from_str(timestamp_text, "%m/%d/%Y %l:%M:%S %p").unwrap();
+/// Splits the unified feed into trip updates, vehicle positions and alerts, adding the advisories to the alerts.
+pub fn split_unified_feed(
+    unified_feed: FeedMessage,
+    mut advisories: Vec<FeedEntity>,
+) -> GtfsAmtrakResults {
+    let mut vehicles: Vec<gtfs_realtime::FeedEntity> = vec![];
+    let mut trips: Vec<gtfs_realtime::FeedEntity> = vec![];
+    let mut alerts: Vec<FeedEntity> = vec![];
 
-    tz_char_to_tz(tz)
-        .unwrap()
-        .from_local_datetime(&naive_dt)
-        .latest()
-        .unwrap()
-}
+    for feed_entity in unified_feed.entity {
+        if feed_entity.vehicle.is_some() {
+            vehicles.push(feed_entity.clone());
+        }
 
-//time is formatted 11/18/2023 4:58:09 PM
-pub fn process_timestamp_text(timestamp_text: &str) -> Option<i64> {
-    let naive_dt = NaiveDateTime::parse_from_str(timestamp_text, "%m/%d/%Y %l:%M:%S %p");
+        // Trip modifications and their shapes are published alongside the trip updates they modify
+        if feed_entity.trip_update.is_some()
+            || feed_entity.trip_modifications.is_some()
+            || feed_entity.shape.is_some()
+        {
+            trips.push(feed_entity.clone());
+        }
 
-    if naive_dt.is_err() {
-        println!("Error parsing timestamp: {}", timestamp_text);
-        return None;
+        if feed_entity.alert.is_some() {
+            alerts.push(feed_entity.clone());
+        }
     }
 
-    let naive_dt = naive_dt.unwrap();
-
-    let eastern_time = chrono_tz::America::New_York
-        .from_local_datetime(&naive_dt)
-        .latest()
-        .unwrap();
+    alerts.append(&mut advisories);
 
-    eastern_time.timestamp().try_into().unwrap()
+    GtfsAmtrakResults {
+        trip_updates: FeedMessage {
+            entity: trips,
+            header: unified_feed.header.clone(),
+        },
+        vehicle_positions: FeedMessage {
+            entity: vehicles,
+            header: unified_feed.header.clone(),
+        },
+        alerts: FeedMessage {
+            header: gtfs_realtime::FeedHeader {
+                feed_version: Some(alert_ids::alerts_feed_version(&alerts)),
+                ..unified_feed.header.clone()
+            },
+            entity: alerts,
+        },
+    }
 }
 
+/// Fetches and converts one poll. `repair_strategy` repairs stop times that go back in time, `None` publishes
+/// them as is.
 pub async fn fetch_amtrak_gtfs_rt(
     gtfs: &Gtfs,
     client: &reqwest::Client,
+    repair_strategy: Option<monotonicity::RepairStrategy>,
 ) -> Result<GtfsAmtrakResults, Box<dyn std::error::Error + Sync + Send>> {
-    let joined_res = fetch_amtrak_gtfs_rt_joined(gtfs, client).await;
+    let joined_res = fetch_amtrak_gtfs_rt_joined(gtfs, client, repair_strategy).await?;
 
-    let mut vehicles: Vec<gtfs_realtime::FeedEntity> = vec![];
-    let mut trips: Vec<gtfs_realtime::FeedEntity> = vec![];
-    let mut alerts: Vec<FeedEntity> = vec![];
+    let advisories = fetch_all_advisories(&default_advisory_sources(), client, gtfs).await;
 
-    match joined_res {
-        Ok(joined_res) => {
-            for feed_entity in joined_res.unified_feed.entity {
-                vehicles.push(feed_entity.clone());
-                trips.push(feed_entity.clone());
+    Ok(split_unified_feed(joined_res.unified_feed, advisories))
+}
 
-                if feed_entity.alert.is_some() {
-                    alerts.push(feed_entity.clone());
-                }
-            }
+/// Decrypts a Track-A-Train response to GeoJSON.
+pub fn decrypt_track_a_train(
+    track_a_train: &str,
+) -> Result<String, Box<dyn std::error::Error + Sync + Send>> {
+    // The response ends with an 88 character encrypted key, amtk panics on anything shorter
+    if track_a_train.len() < 88 || !track_a_train.is_char_boundary(track_a_train.len() - 88) {
+        return Err("Track-A-Train response is too short to be encrypted data".into());
+    }
 
-            match fetch_pacific_surfliner_advisories(client, gtfs).await {
-                Ok(mut surfliner_alerts) => {
-                    alerts.append(&mut surfliner_alerts);
-                }
+    Ok(amtk::decrypt(track_a_train)?)
+}
+
+/// Converts a Track-A-Train response, and optionally an ASM response, to the unified feed.
+/// `now` is in unix seconds. `repair_strategy` repairs stop times that go back in time, `None` publishes
+/// them as is.
+pub fn process_amtrak_payloads_joined(
+    gtfs: &Gtfs,
+    track_a_train: &str,
+    asm: Option<&str>,
+    now: i64,
+    repair_strategy: Option<monotonicity::RepairStrategy>,
+) -> Result<GtfsAmtrakResultsJoined, Box<dyn std::error::Error + Sync + Send>> {
+    let decrypted_string = decrypt_track_a_train(track_a_train)?;
+
+    process_track_a_train_geojson_joined(gtfs, &decrypted_string, asm, now, repair_strategy)
+}
+
+/// [`process_amtrak_payloads_joined`] on an already decrypted Track-A-Train response.
+pub fn process_track_a_train_geojson_joined(
+    gtfs: &Gtfs,
+    track_a_train_geojson: &str,
+    asm: Option<&str>,
+    now: i64,
+    repair_strategy: Option<monotonicity::RepairStrategy>,
+) -> Result<GtfsAmtrakResultsJoined, Box<dyn std::error::Error + Sync + Send>> {
+    let geojson: geojson::GeoJson = track_a_train_geojson.parse::<geojson::GeoJson>()?;
+    let features_collection: FeatureCollection = FeatureCollection::try_from(geojson)?;
+
+    let asm_root: Option<asm::AsmRoot> =
+        asm.and_then(
+            |asm_root| match serde_json::from_str::<asm::AsmRoot>(asm_root) {
+                Ok(asm_root) => Some(asm_root),
                 Err(e) => {
-                    eprintln!("Error fetching Pacific Surfliner alerts: {}", e);
+                    eprintln!("Error parsing ASM data, proceeding without alerts, {:?}", e);
+                    None
                 }
-            }
+            },
+        );
 
-            Ok(GtfsAmtrakResults {
-                trip_updates: FeedMessage {
-                    entity: trips,
-                    header: joined_res.unified_feed.header.clone(),
-                },
-                vehicle_positions: FeedMessage {
-                    entity: vehicles,
-                    header: joined_res.unified_feed.header.clone(),
-                },
-                alerts: FeedMessage {
-                    entity: alerts,
-                    header: joined_res.unified_feed.header.clone(),
-                },
-            })
-        }
-        Err(x) => Err(x),
+    let lookup_table: Option<HashMap<(NaiveDate, String), Vec<asm::AsmAlert>>> = asm_root
+        .as_ref()
+        .map(|asm_root| asm::make_lookup_table_from_asm_root(asm_root.clone()));
+
+    let mut entities = features_collection
+        .features
+        .iter()
+        .flat_map(|feature: &geojson::Feature| {
+            feature_to_gtfs_unified(gtfs, feature, lookup_table.as_ref())
+        })
+        .collect::<Vec<FeedEntity>>();
+
+    // Trains that never showed up on Track-A-Train, but are confirmed canceled by ASM
+    if let Some(asm_root) = &asm_root {
+        let present_entity_ids = entities
+            .iter()
+            .map(|entity| entity.id.clone())
+            .collect::<HashSet<String>>();
+
+        entities.append(&mut cancellations::canceled_trips_from_asm(
+            gtfs,
+            asm_root,
+            &present_entity_ids,
+            now,
+        ));
     }
+
+    let mut unified_feed = FeedMessage {
+        entity: entities,
+        header: make_gtfs_header(),
+    };
+
+    let repairs = match repair_strategy {
+        Some(strategy) => monotonicity::enforce_feed_monotonicity(&mut unified_feed, strategy),
+        None => vec![],
+    };
+
+    Ok(GtfsAmtrakResultsJoined {
+        unified_feed,
+        repairs,
+    })
 }
 
 pub async fn fetch_amtrak_gtfs_rt_joined(
     gtfs: &Gtfs,
     client: &reqwest::Client,
+    repair_strategy: Option<monotonicity::RepairStrategy>,
 ) -> Result<GtfsAmtrakResultsJoined, Box<dyn std::error::Error + Sync + Send>> {
-    let raw_data = client
-        .get("https://maps.amtrak.com/services/MapDataService/trains/getTrainsData")
-        .send()
-        .await;
+    let raw_data_text = client.get(TRACK_A_TRAIN_URL).send().await?.text().await?;
 
-    let raw_asm_data = client
-        .get("https://asm-backend.transitdocs.com/map")
-        .send()
-        .await;
+    let raw_asm_data = fetch_asm(client).await;
 
-    match raw_data {
-        Ok(raw_data) => {
-            //println!("Raw data successfully downloaded");
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    process_amtrak_payloads_joined(
+        gtfs,
+        &raw_data_text,
+        raw_asm_data.as_deref(),
+        now,
+        repair_strategy,
+    )
+}
 
-            let raw_data_text = raw_data.text().await?;
+pub mod advisory_source;
+pub mod amtrak_service_alerts;
+pub mod capitol_corridor_website;
+pub mod cascades_website;
+pub mod downeaster_website;
+pub mod gold_runner_website;
+pub mod hiawatha_website;
+pub mod pacific_surfliner_website;
+use advisory_source::{default_advisory_sources, fetch_all_advisories};
+#[cfg(test)]
+use pacific_surfliner_website::fetch_pacific_surfliner_advisories;
 
-            let decrypted_string = amtk::decrypt(raw_data_text.as_str())?;
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use gtfs_realtime::trip_update::{StopTimeEvent, StopTimeUpdate};
+    use gtfs_realtime::{
+        FeedEntity, FeedMessage, Position, TripDescriptor, TripUpdate, VehiclePosition,
+    };
 
-            let geojson: geojson::GeoJson = decrypted_string.parse::<geojson::GeoJson>()?;
-            let features_collection: FeatureCollection = FeatureCollection::try_from(geojson)?;
+    /// A Track-A-Train feature. Stations are `(code, scharr, schdep, schcmnt)`, times in `%m/%d/%Y %H:%M:%S` Pacific time.
+    pub fn feature(
+        train_num: &str,
+        route_name: &str,
+        orig_sch_dep: &str,
+        stations: &[serde_json::Value],
+    ) -> geojson::Feature {
+        let mut properties = serde_json::Map::new();
+
+        properties.insert("TrainNum".to_string(), train_num.into());
+        properties.insert("RouteName".to_string(), route_name.into());
+        properties.insert("OriginTZ".to_string(), "P".into());
+        properties.insert("OrigSchDep".to_string(), orig_sch_dep.into());
+        properties.insert("updated_at".to_string(), "1/19/2026 9:00:00 AM".into());
+        properties.insert("Velocity".to_string(), "40.5".into());
+        properties.insert("Heading".to_string(), "S".into());
+
+        for (i, station) in stations.iter().enumerate() {
+            properties.insert(format!("Station{}", i), station.to_string().into());
+        }
 
-            let lookup_table: Option<HashMap<(NaiveDate, String), Vec<asm::AsmAlert>>> =
-                match raw_asm_data {
-                    Ok(raw_asm_data) => {
-                        let asm_root = raw_asm_data.text().await?;
+        geojson::Feature {
+            bbox: None,
+            geometry: Some(geojson::Geometry::new(geojson::GeometryValue::new_point([
+                -122.83, 45.0,
+            ]))),
+            id: None,
+            properties: Some(properties),
+            foreign_members: None,
+        }
+    }
 
-                        println!("ASM data successfully downloaded");
+    pub fn station(
+        code: &str,
+        scharr: Option<&str>,
+        schdep: Option<&str>,
+        schcmnt: &str,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "code": code,
+            "tz": "P",
+            "bus": false,
+            "scharr": scharr,
+            "schdep": schdep,
+            "schcmnt": schcmnt,
+            "autoarr": false,
+            "autodep": false
+        })
+    }
 
-                        let asm_root_json = serde_json::from_str::<asm::AsmRoot>(&asm_root);
+    /// Train 11 (Coast Starlight, route 94) scheduled daily in January 2026
+    pub fn coast_starlight_gtfs() -> gtfs_structures::Gtfs {
+        let mut gtfs = crate::schedule::test_helpers::january_gtfs();
 
-                        match asm_root_json {
-                            Ok(asm_root) => Some(asm::make_lookup_table_from_asm_root(asm_root)),
-                            Err(e) => {
-                                eprintln!("Error parsing ASM data, proceeding without alerts, {:?}", e);
-                                None
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        eprintln!("Error fetching ASM data, proceeding without alerts");
-                        None
-                    }
-                };
-
-            Ok(GtfsAmtrakResultsJoined {
-                unified_feed: FeedMessage {
-                    entity: features_collection
-                        .features
-                        .iter()
-                        .map(|feature: &geojson::Feature| {
-                            feature_to_gtfs_unified(&gtfs, feature, lookup_table.as_ref())
-                        })
-                        .collect::<Vec<FeedEntity>>(),
-                    header: make_gtfs_header(),
+        gtfs.routes.insert(
+            "94".to_string(),
+            gtfs_structures::Route {
+                id: "94".to_string(),
+                long_name: Some("Coast Starlight".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let stations = [
+            ("SEA", 47.598, -122.330),
+            ("PDX", 45.529, -122.677),
+            ("EUG", 44.055, -123.094),
+            ("SAC", 38.584, -121.500),
+            ("LAX", 34.056, -118.236),
+        ];
+
+        for (code, latitude, longitude) in stations {
+            gtfs.stops.insert(
+                code.to_string(),
+                std::sync::Arc::new(gtfs_structures::Stop {
+                    id: code.to_string(),
+                    latitude: Some(latitude),
+                    longitude: Some(longitude),
+                    ..Default::default()
+                }),
+            );
+        }
+
+        // SEA, a point north of PDX, then every station but EUG, which train 11 doesn't call at
+        let shape = [
+            (47.598, -122.330),
+            (46.8, -122.8),
+            (45.529, -122.677),
+            (44.055, -123.094),
+            (38.584, -121.500),
+            (34.056, -118.236),
+        ];
+
+        gtfs.shapes.insert(
+            "SH94".to_string(),
+            shape
+                .iter()
+                .enumerate()
+                .map(|(i, (latitude, longitude))| gtfs_structures::Shape {
+                    id: "SH94".to_string(),
+                    latitude: *latitude,
+                    longitude: *longitude,
+                    sequence: i,
+                    dist_traveled: None,
+                })
+                .collect(),
+        );
+
+        let mut t11 = gtfs.trips.get("T11").unwrap().clone();
+        t11.shape_id = Some("SH94".to_string());
+        gtfs.trips.insert("T11".to_string(), t11);
+
+        gtfs
+    }
+
+    /// 9:00 Pacific on January 19, 2026
+    pub const NOW: i64 = 1768842000;
+
+    /// A stop time event `delay_secs` after `scheduled_time`. Actual times have an uncertainty of 0.
+    pub fn event(scheduled_time: i64, delay_secs: i64, actual: bool) -> Option<StopTimeEvent> {
+        Some(StopTimeEvent {
+            time: Some(scheduled_time + delay_secs),
+            scheduled_time: Some(scheduled_time),
+            uncertainty: actual.then_some(0),
+            ..Default::default()
+        })
+    }
+
+    pub fn stop(
+        stop_id: &str,
+        arrival: Option<StopTimeEvent>,
+        departure: Option<StopTimeEvent>,
+    ) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            arrival,
+            departure,
+            ..Default::default()
+        }
+    }
+
+    /// The trip update of a Coast Starlight train, trip `T{train}` on route 94.
+    pub fn train_entity(service_date: &str, train: &str, stops: Vec<StopTimeUpdate>) -> FeedEntity {
+        FeedEntity {
+            id: format!("{}-{}", service_date, train),
+            trip_update: Some(TripUpdate {
+                trip: TripDescriptor {
+                    trip_id: Some(format!("T{}", train)),
+                    route_id: Some("94".to_string()),
+                    start_date: Some(service_date.to_string()),
+                    ..Default::default()
                 },
-            })
+                stop_time_update: stops,
+                ..Default::default()
+            }),
+            ..Default::default()
         }
-        Err(err) => Err(Box::new(err)),
     }
-}
 
-pub mod pacific_surfliner_website;
-use pacific_surfliner_website::fetch_pacific_surfliner_advisories;
+    /// Adds the vehicle of the trip, moving at 20 m/s.
+    pub fn with_vehicle(entity: FeedEntity, latitude: f32, timestamp: u64) -> FeedEntity {
+        FeedEntity {
+            vehicle: Some(VehiclePosition {
+                trip: entity
+                    .trip_update
+                    .as_ref()
+                    .map(|update| update.trip.clone()),
+                timestamp: Some(timestamp),
+                position: Some(Position {
+                    latitude,
+                    longitude: -122.0,
+                    speed: Some(20.0),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..entity
+        }
+    }
+
+    pub fn feed(entity: Vec<FeedEntity>) -> FeedMessage {
+        FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity,
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -871,7 +1347,7 @@ mod tests {
 
         println!("amtrak download finished");
 
-        let amtrak_results = fetch_amtrak_gtfs_rt_joined(&gtfs, &client).await;
+        let amtrak_results = fetch_amtrak_gtfs_rt_joined(&gtfs, &client, None).await;
 
         assert!(amtrak_results.is_ok());
 
@@ -890,9 +1366,17 @@ mod tests {
         let geojson: geojson::GeoJson = decrypted_string.parse::<geojson::GeoJson>().unwrap();
         let features_collection: FeatureCollection = FeatureCollection::try_from(geojson).unwrap();
 
+        // Every train on Track-A-Train has a vehicle, ASM confirmed cancellations don't
         assert_eq!(
             features_collection.features.len(),
-            amtrak_results.as_ref().unwrap().unified_feed.entity.len()
+            amtrak_results
+                .as_ref()
+                .unwrap()
+                .unified_feed
+                .entity
+                .iter()
+                .filter(|entity| entity.vehicle.is_some())
+                .count()
         );
 
         // println!("{:?}", amtrak_results.unwrap());
@@ -911,7 +1395,10 @@ mod tests {
             .await
             .unwrap();
 
-        let amtrak_results = fetch_amtrak_gtfs_rt(&gtfs, &client).await.unwrap();
+        let amtrak_results =
+            fetch_amtrak_gtfs_rt(&gtfs, &client, Some(monotonicity::RepairStrategy::Clamp))
+                .await
+                .unwrap();
 
         println!(
             "Trip updates: {} vehicles: {} alerts: {}",
@@ -947,7 +1434,8 @@ mod tests {
 
     #[test]
     fn test_origin() {
-        let origin_departure_calc = origin_departure("8/8/2025 9:51:00 AM", 'P');
+        let origin_departure_calc =
+            origin_departure("8/8/2025 9:51:00 AM", chrono_tz::America::Los_Angeles).unwrap();
 
         println!("{:#?}", origin_departure_calc);
 
@@ -1015,4 +1503,392 @@ mod tests {
             }
         }
     }
+
+    fn three_stop_train(train_num: &str, schcmnt: [&str; 3]) -> geojson::Feature {
+        test_helpers::feature(
+            train_num,
+            "Coast Starlight",
+            "1/19/2026 9:52:00 AM",
+            &[
+                test_helpers::station("SEA", None, Some("01/19/2026 09:52:00"), schcmnt[0]),
+                test_helpers::station(
+                    "PDX",
+                    Some("01/19/2026 13:30:00"),
+                    Some("01/19/2026 13:45:00"),
+                    schcmnt[1],
+                ),
+                test_helpers::station("SAC", Some("01/20/2026 06:00:00"), None, schcmnt[2]),
+            ],
+        )
+    }
+
+    #[test]
+    fn fully_canceled_train_is_canceled_trip() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let entities = feature_to_gtfs_unified(
+            &gtfs,
+            &three_stop_train("11", ["Canceled", "Canceled", "Canceled"]),
+            None,
+        );
+
+        // A canceled trip isn't also short-turned
+        assert_eq!(entities.len(), 1);
+        let entity = entities[0].clone();
+
+        let trip_update = entity.trip_update.unwrap();
+
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(
+            trip_update.trip.schedule_relationship,
+            Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::Canceled as i32)
+        );
+        assert!(trip_update.stop_time_update.is_empty());
+
+        // Partially canceled trains still run
+        let entity =
+            feature_to_gtfs_unified(&gtfs, &three_stop_train("11", ["", "", "Canceled"]), None)
+                .remove(0);
+
+        assert_eq!(entity.trip_update.unwrap().trip.schedule_relationship, None);
+    }
+
+    #[test]
+    fn unscheduled_train_is_new_trip() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let entity =
+            feature_to_gtfs_unified(&gtfs, &three_stop_train("1011", ["", "", ""]), None).remove(0);
+
+        assert_eq!(entity.id, "20260119-1011");
+
+        let trip_update = entity.trip_update.unwrap();
+
+        assert_eq!(
+            trip_update.trip.schedule_relationship,
+            Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::New as i32)
+        );
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("20260119-1011"));
+        assert_eq!(trip_update.trip.route_id.as_deref(), Some("94"));
+        assert_eq!(
+            trip_update
+                .trip_properties
+                .unwrap()
+                .trip_short_name
+                .as_deref(),
+            Some("1011")
+        );
+        assert_eq!(
+            trip_update
+                .stop_time_update
+                .iter()
+                .map(|x| x.stop_id.clone().unwrap())
+                .collect::<Vec<String>>(),
+            vec!["SEA", "PDX", "SAC"]
+        );
+    }
+
+    #[test]
+    fn unreadable_origin_keeps_the_vehicle() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let unreadable_origin = |feature: &mut geojson::Feature| {
+            feature
+                .properties
+                .as_mut()
+                .unwrap()
+                .insert("OrigSchDep".to_string(), "soon".into());
+        };
+
+        // The first scheduled time matches the scheduled trip
+        let mut feature = three_stop_train("11", ["", "", ""]);
+        unreadable_origin(&mut feature);
+
+        let entity = feature_to_gtfs_unified(&gtfs, &feature, None).remove(0);
+
+        assert_eq!(entity.id, "20260119-11");
+        assert!(entity.vehicle.is_some());
+
+        let trip_update = entity.trip_update.unwrap();
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(trip_update.trip.schedule_relationship, None);
+        assert_eq!(trip_update.stop_time_update.len(), 3);
+
+        // A scheduled train that can't be matched is never a new trip, only its position is known
+        for scheduled_departure in [Some("01/19/2026 10:30:00"), None] {
+            let mut feature = test_helpers::feature(
+                "11",
+                "Coast Starlight",
+                "soon",
+                &[test_helpers::station("SEA", None, scheduled_departure, "")],
+            );
+            unreadable_origin(&mut feature);
+
+            let entities = feature_to_gtfs_unified(&gtfs, &feature, None);
+
+            assert_eq!(entities.len(), 1);
+            assert_eq!(entities[0].id, "20260119-11");
+            assert!(entities[0].trip_update.is_none());
+            assert_eq!(entities[0].vehicle.as_ref().unwrap().trip, None);
+            assert!(entities[0].vehicle.as_ref().unwrap().position.is_some());
+        }
+
+        // Neither the schedule nor the zone letter know the origin, the train isn't scheduled
+        let mut origin = test_helpers::station("XYZ", None, Some("01/19/2026 09:52:00"), "");
+        origin["tz"] = "Q".into();
+
+        let mut feature =
+            test_helpers::feature("1011", "Coast Starlight", "1/19/2026 9:52:00 AM", &[origin]);
+        feature
+            .properties
+            .as_mut()
+            .unwrap()
+            .insert("OriginTZ".to_string(), "Q".into());
+
+        let entities = feature_to_gtfs_unified(&gtfs, &feature, None);
+
+        assert_eq!(entities[0].id, "20260119-1011");
+        assert!(entities[0].vehicle.is_some());
+        assert_eq!(
+            entities[0]
+                .trip_update
+                .as_ref()
+                .unwrap()
+                .trip
+                .schedule_relationship,
+            Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::New as i32)
+        );
+
+        // Without the agency's timezone no train can be placed on a service date
+        let mut gtfs = gtfs;
+        gtfs.agencies.clear();
+        assert!(
+            feature_to_gtfs_unified(&gtfs, &three_stop_train("11", ["", "", ""]), None).is_empty()
+        );
+    }
+
+    #[test]
+    fn short_turned_train_skips_stations_and_links_alert() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let entities =
+            feature_to_gtfs_unified(&gtfs, &three_stop_train("11", ["Canceled", "", ""]), None);
+
+        assert_eq!(
+            entities
+                .iter()
+                .map(|entity| entity.id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["20260119-11", "20260119-11-short-turn"]
+        );
+
+        // Skipped stations without a replacement stay on the unmodified trip
+        let trip_update = entities[0].trip_update.as_ref().unwrap();
+
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(trip_update.trip.route_id.as_deref(), Some("94"));
+        assert_eq!(trip_update.trip.modified_trip, None);
+        assert_eq!(trip_update.trip.schedule_relationship, None);
+        assert_eq!(
+            trip_update
+                .stop_time_update
+                .iter()
+                .map(|x| (x.stop_sequence, x.schedule_relationship))
+                .collect::<Vec<(Option<u32>, Option<i32>)>>(),
+            vec![(Some(10), Some(1)), (Some(20), None), (Some(30), None)]
+        );
+
+        let alert_entity = &entities[1];
+        let alert = alert_entity.alert.as_ref().unwrap();
+
+        assert_eq!(alert_entity.id, "20260119-11-short-turn");
+        assert!(alert_entity.trip_update.is_none() && alert_entity.vehicle.is_none());
+        assert_eq!(
+            alert.informed_entity[0]
+                .trip
+                .as_ref()
+                .unwrap()
+                .trip_id
+                .as_deref(),
+            Some("T11")
+        );
+        assert_eq!(alert.informed_entity[1].stop_id.as_deref(), Some("SEA"));
+    }
+
+    #[test]
+    fn capital_corridor_is_filtered_with_its_derived_entities() {
+        let mut cc_train = test_helpers::train_entity("20260119", "531", vec![]);
+        cc_train.trip_update.as_mut().unwrap().trip.route_id = Some("84".to_string());
+
+        let mut cc_train = test_helpers::with_vehicle(cc_train, 38.5, test_helpers::NOW as u64);
+
+        // The trip update of a modified trip has no route, only the vehicle does
+        let trip = &mut cc_train.trip_update.as_mut().unwrap().trip;
+        trip_modifications::link_modified_trip(trip, "20260119-531");
+
+        let derived = |id: &str| FeedEntity {
+            id: id.to_string(),
+            ..Default::default()
+        };
+
+        let feed = test_helpers::feed(vec![
+            cc_train,
+            derived("20260119-531-modifications"),
+            derived("20260119-531-shape"),
+            derived("20260119-531-short-turn"),
+            test_helpers::train_entity("20260119", "11", vec![]),
+            derived("20260119-11-bus"),
+        ]);
+
+        let ids = filter_capital_corridor(feed)
+            .entity
+            .into_iter()
+            .map(|entity| entity.id)
+            .collect::<Vec<String>>();
+
+        assert_eq!(ids, vec!["20260119-11", "20260119-11-bus"]);
+    }
+
+    #[test]
+    fn stateless_conversion_checks_stop_times() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        // Track-A-Train has the train leave PDX before it got there
+        let mut pdx = test_helpers::station(
+            "PDX",
+            Some("01/19/2026 13:30:00"),
+            Some("01/19/2026 13:45:00"),
+            "",
+        );
+        pdx["postarr"] = "01/19/2026 13:50:00".into();
+        pdx["postdep"] = "01/19/2026 13:40:00".into();
+
+        let geojson = geojson::GeoJson::FeatureCollection(geojson::FeatureCollection {
+            bbox: None,
+            features: vec![test_helpers::feature(
+                "11",
+                "Coast Starlight",
+                "1/19/2026 9:52:00 AM",
+                &[
+                    test_helpers::station("SEA", None, Some("01/19/2026 09:52:00"), ""),
+                    pdx,
+                ],
+            )],
+            foreign_members: None,
+        })
+        .to_string();
+
+        for strategy in [
+            Some(monotonicity::RepairStrategy::Flag),
+            Some(monotonicity::RepairStrategy::Clamp),
+            None,
+        ] {
+            let joined = process_track_a_train_geojson_joined(
+                &gtfs,
+                &geojson,
+                None,
+                test_helpers::NOW,
+                strategy,
+            )
+            .unwrap();
+
+            // Station times are already kept in order, 13:50 Pacific, so there is nothing left to repair
+            let pdx = &joined.unified_feed.entity[0]
+                .trip_update
+                .as_ref()
+                .unwrap()
+                .stop_time_update[1];
+
+            assert_eq!(pdx.departure.and_then(|event| event.time), Some(1768859400));
+            assert!(joined.repairs.is_empty());
+        }
+    }
+
+    #[test]
+    fn unknown_station_keeps_the_trip() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let feature = test_helpers::feature(
+            "11",
+            "Coast Starlight",
+            "1/19/2026 9:52:00 AM",
+            &[
+                test_helpers::station("SEA", None, Some("01/19/2026 09:52:00"), ""),
+                test_helpers::station("XYZ", None, Some("01/19/2026 11:00:00"), ""),
+                test_helpers::station("PDX", Some("01/19/2026 13:30:00"), None, ""),
+            ],
+        );
+
+        let entities = feature_to_gtfs_unified(&gtfs, &feature, None);
+
+        assert_eq!(entities.len(), 1);
+
+        let trip_update = entities[0].trip_update.as_ref().unwrap();
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(trip_update.trip.modified_trip, None);
+        assert_eq!(trip_update.stop_time_update[1].stop_sequence, None);
+    }
+
+    #[test]
+    fn bus_stops_become_a_bus_trip() {
+        let gtfs = test_helpers::coast_starlight_gtfs();
+
+        let mut sac = test_helpers::station("SAC", Some("01/20/2026 06:00:00"), None, "");
+        sac["bus"] = true.into();
+
+        let feature = test_helpers::feature(
+            "11",
+            "Coast Starlight",
+            "1/19/2026 9:52:00 AM",
+            &[
+                test_helpers::station("SEA", None, Some("01/19/2026 09:52:00"), ""),
+                test_helpers::station(
+                    "PDX",
+                    Some("01/19/2026 13:30:00"),
+                    Some("01/19/2026 13:45:00"),
+                    "",
+                ),
+                sac,
+            ],
+        );
+
+        let entities = feature_to_gtfs_unified(&gtfs, &feature, None);
+
+        // SAC is skipped by the rail trip, which isn't modified
+        assert_eq!(entities.len(), 2);
+
+        let rail = entities[0].trip_update.as_ref().unwrap();
+        assert_eq!(rail.stop_time_update[2].schedule_relationship, Some(1));
+        assert_eq!(rail.trip.trip_id.as_deref(), Some("T11"));
+        assert_eq!(rail.trip.modified_trip, None);
+
+        let bus = entities[1].trip_update.as_ref().unwrap();
+        assert_eq!(entities[1].id, "20260119-11-bus");
+        assert!(entities[1].vehicle.is_none());
+        assert_eq!(
+            bus.trip.schedule_relationship,
+            Some(gtfs_realtime::trip_descriptor::ScheduleRelationship::New as i32)
+        );
+        assert_eq!(bus.stop_time_update.len(), 1);
+        assert_eq!(bus.stop_time_update[0].stop_id.as_deref(), Some("SAC"));
+
+        let results = split_unified_feed(
+            FeedMessage {
+                header: make_gtfs_header(),
+                entity: entities,
+            },
+            vec![],
+        );
+
+        let trip_update_ids = results
+            .trip_updates
+            .entity
+            .iter()
+            .map(|entity| entity.id.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(trip_update_ids, vec!["20260119-11", "20260119-11-bus"]);
+        assert_eq!(results.vehicle_positions.entity.len(), 1);
+        assert!(results.alerts.entity.is_empty());
+    }
 }
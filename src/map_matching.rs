@@ -0,0 +1,180 @@
+//! Snapping of vehicle positions to the shape of their trip.
+//!
+//! Track-A-Train positions are raw GPS points, which can sit next to the track or jump around in stations.
+//! Projecting them onto the matched trip's GTFS shape gives the distance travelled along the shape, published
+//! as the `odometer`, and a point on the track. Positions too far from the shape are off route, so they're
+//! left untouched.
+
+use crate::shapes::{cumulative_distances, project_onto_polyline, shape_points};
+use crate::trip_modifications::SHAPE_DIVERGENCE_THRESHOLD_M;
+use gtfs_realtime::FeedMessage;
+use gtfs_structures::{Gtfs, Trip};
+use std::collections::HashMap;
+
+/// A vehicle position projected onto its trip's shape. Points are `(latitude, longitude)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnappedPosition {
+    /// Closest point on the shape
+    pub point: (f64, f64),
+    /// Distance along the shape to the snapped point, meters
+    pub odometer_m: f64,
+    /// Length of the whole shape, meters
+    pub shape_length_m: f64,
+    /// Distance between the reported position and the shape, meters
+    pub distance_from_shape_m: f64,
+}
+
+impl SnappedPosition {
+    /// Fraction of the shape travelled, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        match self.shape_length_m > 0.0 {
+            true => (self.odometer_m / self.shape_length_m).clamp(0.0, 1.0),
+            false => 0.0,
+        }
+    }
+
+    /// The reported position is too far from the shape to be on it, e.g. a detour or a bad GPS fix.
+    pub fn is_off_route(&self) -> bool {
+        self.distance_from_shape_m > SHAPE_DIVERGENCE_THRESHOLD_M
+    }
+}
+
+/// Projects a position onto the shape of a trip, `None` when the trip has no usable shape.
+pub fn snap_to_trip(gtfs: &Gtfs, trip: &Trip, point: (f64, f64)) -> Option<SnappedPosition> {
+    let points = shape_points(gtfs, trip.shape_id.as_ref()?)?;
+
+    let projection = project_onto_polyline(&points, point)?;
+
+    Some(SnappedPosition {
+        point: projection.point,
+        odometer_m: projection.distance_along_m,
+        shape_length_m: *cumulative_distances(&points).last()?,
+        distance_from_shape_m: projection.distance_from_shape_m,
+    })
+}
+
+/// Snaps every vehicle position in the feed to the shape of its trip, keyed by entity id.
+///
+/// The `odometer` is filled for positions on route. With `move_to_shape`, their coordinates are also
+/// replaced by the snapped point. Off route positions are returned but not changed.
+pub fn snap_vehicle_positions(
+    gtfs: &Gtfs,
+    feed: &mut FeedMessage,
+    move_to_shape: bool,
+) -> HashMap<String, SnappedPosition> {
+    let mut snapped_positions = HashMap::new();
+
+    for entity in feed.entity.iter_mut() {
+        let Some(vehicle) = entity.vehicle.as_mut() else {
+            continue;
+        };
+
+        let Some(trip) = vehicle
+            .trip
+            .as_ref()
+            .and_then(|trip| trip.trip_id.as_ref())
+            .and_then(|trip_id| gtfs.trips.get(trip_id))
+        else {
+            continue;
+        };
+
+        let Some(position) = vehicle.position.as_mut() else {
+            continue;
+        };
+
+        let Some(snapped) = snap_to_trip(
+            gtfs,
+            trip,
+            (position.latitude as f64, position.longitude as f64),
+        ) else {
+            continue;
+        };
+
+        if !snapped.is_off_route() {
+            position.odometer = Some(snapped.odometer_m);
+
+            if move_to_shape {
+                position.latitude = snapped.point.0 as f32;
+                position.longitude = snapped.point.1 as f32;
+            }
+        }
+
+        snapped_positions.insert(entity.id.clone(), snapped);
+    }
+
+    snapped_positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::haversine_m;
+    use crate::test_helpers::coast_starlight_gtfs;
+
+    fn vehicle_feed(trip_id: &str, latitude: f32, longitude: f32) -> FeedMessage {
+        FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity: vec![gtfs_realtime::FeedEntity {
+                id: "20260119-11".to_string(),
+                vehicle: Some(gtfs_realtime::VehiclePosition {
+                    trip: Some(gtfs_realtime::TripDescriptor {
+                        trip_id: Some(trip_id.to_string()),
+                        ..Default::default()
+                    }),
+                    position: Some(gtfs_realtime::Position {
+                        latitude,
+                        longitude,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn snaps_between_portland_and_eugene() {
+        let gtfs = coast_starlight_gtfs();
+
+        let mut feed = vehicle_feed("T11", 45.0, -122.83);
+
+        let snapped = snap_vehicle_positions(&gtfs, &mut feed, true);
+        let snapped = snapped.get("20260119-11").unwrap();
+
+        assert!(!snapped.is_off_route());
+        assert!(snapped.distance_from_shape_m < 300.0);
+
+        let to_portland = haversine_m((47.598, -122.330), (46.8, -122.8))
+            + haversine_m((46.8, -122.8), (45.529, -122.677));
+        let portland_to_eugene = haversine_m((45.529, -122.677), (44.055, -123.094));
+
+        assert!(snapped.odometer_m > to_portland);
+        assert!(snapped.odometer_m < to_portland + portland_to_eugene);
+        assert!(snapped.progress() > 0.0 && snapped.progress() < 0.2);
+
+        let position = feed.entity[0].vehicle.as_ref().unwrap().position.unwrap();
+
+        assert_eq!(position.odometer, Some(snapped.odometer_m));
+        assert_eq!(position.latitude, snapped.point.0 as f32);
+        assert_eq!(position.longitude, snapped.point.1 as f32);
+    }
+
+    #[test]
+    fn off_route_and_unknown_trips() {
+        let gtfs = coast_starlight_gtfs();
+
+        let mut feed = vehicle_feed("T11", 40.0, -100.0);
+
+        let snapped = snap_vehicle_positions(&gtfs, &mut feed, true);
+
+        assert!(snapped.get("20260119-11").unwrap().is_off_route());
+
+        let position = feed.entity[0].vehicle.as_ref().unwrap().position.unwrap();
+        assert_eq!(position.odometer, None);
+        assert_eq!((position.latitude, position.longitude), (40.0, -100.0));
+
+        let mut feed = vehicle_feed("T99", 45.0, -122.83);
+        assert!(snap_vehicle_positions(&gtfs, &mut feed, true).is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+//! Repair of stop time predictions that go back in time.
+//!
+//! Track-A-Train regularly glitches into departures before arrivals, or estimates before the train left the
+//! previous station. Consumers can't make sense of those, so every time is checked against the latest time
+//! before it in the trip, ignoring skipped stations, and repaired with a [`RepairStrategy`]. Every repair is
+//! returned so it can be logged or counted.
+
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_realtime::trip_update::{StopTimeEvent, StopTimeUpdate};
+use gtfs_realtime::{FeedMessage, TripUpdate};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepairStrategy {
+    /// Moves the time to the earliest consistent time
+    #[default]
+    Clamp,
+    /// Removes the time, and marks the stop as having no data if it has no time left
+    Drop,
+    /// Leaves the time as is, only reporting it
+    Flag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopEvent {
+    Arrival,
+    Departure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// Departure earlier than the arrival at the same stop
+    DepartureBeforeArrival,
+    /// Time earlier than a time at a previous stop
+    BeforePreviousStop,
+}
+
+/// One inconsistent time, and what was done about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    pub entity_id: String,
+    /// Index of the stop in the trip update
+    pub stop_index: usize,
+    pub stop_id: Option<String>,
+    pub stop_sequence: Option<u32>,
+    pub event: StopEvent,
+    pub violation: Violation,
+    /// Predicted time, unix seconds
+    pub time: i64,
+    /// Earliest consistent time, unix seconds
+    pub minimum_time: i64,
+    pub strategy: RepairStrategy,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} stop {} ({}): {:?} {:?}, {}s early, {:?}",
+            self.entity_id,
+            self.stop_index,
+            self.stop_id.as_deref().unwrap_or("?"),
+            self.event,
+            self.violation,
+            self.minimum_time - self.time,
+            self.strategy
+        )
+    }
+}
+
+/// Stops whose times don't have to be consistent, since consumers ignore them.
+fn is_ignored(stop_time_update: &StopTimeUpdate) -> bool {
+    matches!(
+        stop_time_update.schedule_relationship,
+        Some(x) if x == ScheduleRelationship::Skipped as i32 || x == ScheduleRelationship::NoData as i32
+    )
+}
+
+/// Checks one event against `minimum`, repairing it in place. Returns the violation and the original time.
+fn check_event(
+    event: &mut Option<StopTimeEvent>,
+    minimum: Option<(i64, Violation)>,
+    strategy: RepairStrategy,
+) -> Option<(Violation, i64, i64)> {
+    let time = event.as_ref()?.time?;
+    let (minimum, violation) = minimum?;
+
+    if time >= minimum {
+        return None;
+    }
+
+    match strategy {
+        RepairStrategy::Clamp => {
+            if let Some(event) = event.as_mut() {
+                event.time = Some(minimum);
+                event.delay = event
+                    .delay
+                    .and_then(|delay| i32::try_from(delay as i64 + minimum - time).ok());
+            }
+        }
+        RepairStrategy::Drop => *event = None,
+        RepairStrategy::Flag => {}
+    }
+
+    Some((violation, time, minimum))
+}
+
+/// Makes the times of a trip update increase along the trip.
+pub fn enforce_monotonicity(
+    entity_id: &str,
+    trip_update: &mut TripUpdate,
+    strategy: RepairStrategy,
+) -> Vec<Repair> {
+    let mut repairs = vec![];
+
+    // Latest time so far in the trip
+    let mut previous: Option<i64> = None;
+
+    for (stop_index, stop_time_update) in trip_update.stop_time_update.iter_mut().enumerate() {
+        if is_ignored(stop_time_update) {
+            continue;
+        }
+
+        let arrival_violation = check_event(
+            &mut stop_time_update.arrival,
+            previous.map(|previous| (previous, Violation::BeforePreviousStop)),
+            strategy,
+        );
+
+        let arrival = stop_time_update
+            .arrival
+            .as_ref()
+            .and_then(|arrival| arrival.time);
+
+        // The stricter bound wins, ties go to the arrival at the same stop
+        let minimum = match (previous, arrival) {
+            (Some(previous), Some(arrival)) if previous > arrival => {
+                Some((previous, Violation::BeforePreviousStop))
+            }
+            (_, Some(arrival)) => Some((arrival, Violation::DepartureBeforeArrival)),
+            (Some(previous), None) => Some((previous, Violation::BeforePreviousStop)),
+            (None, None) => None,
+        };
+
+        let departure_violation = check_event(&mut stop_time_update.departure, minimum, strategy);
+
+        let dropped = strategy == RepairStrategy::Drop
+            && (arrival_violation.is_some() || departure_violation.is_some());
+
+        for (event, violation) in [
+            (StopEvent::Arrival, arrival_violation),
+            (StopEvent::Departure, departure_violation),
+        ] {
+            if let Some((violation, time, minimum_time)) = violation {
+                repairs.push(Repair {
+                    entity_id: entity_id.to_string(),
+                    stop_index,
+                    stop_id: stop_time_update.stop_id.clone(),
+                    stop_sequence: stop_time_update.stop_sequence,
+                    event,
+                    violation,
+                    time,
+                    minimum_time,
+                    strategy,
+                });
+            }
+        }
+
+        if dropped && stop_time_update.arrival.is_none() && stop_time_update.departure.is_none() {
+            stop_time_update.schedule_relationship = Some(ScheduleRelationship::NoData as i32);
+        }
+
+        // Flagged times can't lower the bound, so one glitch isn't reported again at every following stop
+        for time in [&stop_time_update.arrival, &stop_time_update.departure]
+            .into_iter()
+            .flatten()
+            .filter_map(|event| event.time)
+        {
+            previous = Some(previous.map_or(time, |previous| previous.max(time)));
+        }
+    }
+
+    repairs
+}
+
+/// [`enforce_monotonicity`] on every trip update of a feed.
+pub fn enforce_feed_monotonicity(feed: &mut FeedMessage, strategy: RepairStrategy) -> Vec<Repair> {
+    feed.entity
+        .iter_mut()
+        .flat_map(|entity| match entity.trip_update.as_mut() {
+            Some(trip_update) => enforce_monotonicity(&entity.id, trip_update, strategy),
+            None => vec![],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time: Option<i64>) -> Option<StopTimeEvent> {
+        time.map(|time| StopTimeEvent {
+            time: Some(time),
+            delay: Some(0),
+            ..Default::default()
+        })
+    }
+
+    fn stop(code: &str, arrival: Option<i64>, departure: Option<i64>) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_id: Some(code.to_string()),
+            arrival: event(arrival),
+            departure: event(departure),
+            ..Default::default()
+        }
+    }
+
+    fn trip_update() -> TripUpdate {
+        TripUpdate {
+            stop_time_update: vec![
+                stop("SEA", None, Some(1000)),
+                // Departure before arrival
+                stop("TAC", Some(2000), Some(1900)),
+                // Arrival before the previous departure
+                stop("OLW", Some(1800), Some(2100)),
+                StopTimeUpdate {
+                    schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+                    ..stop("CTL", Some(0), Some(0))
+                },
+                stop("PDX", Some(3000), Some(3100)),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn times(trip_update: &TripUpdate, i: usize) -> (Option<i64>, Option<i64>) {
+        let stop_time_update = &trip_update.stop_time_update[i];
+
+        (
+            stop_time_update.arrival.and_then(|event| event.time),
+            stop_time_update.departure.and_then(|event| event.time),
+        )
+    }
+
+    #[test]
+    fn clamp() {
+        let mut trip_update = trip_update();
+
+        let repairs = enforce_monotonicity("1", &mut trip_update, RepairStrategy::Clamp);
+
+        assert_eq!(repairs.len(), 2);
+        assert_eq!(repairs[0].stop_id.as_deref(), Some("TAC"));
+        assert_eq!(repairs[0].event, StopEvent::Departure);
+        assert_eq!(repairs[0].violation, Violation::DepartureBeforeArrival);
+        assert_eq!(repairs[1].stop_index, 2);
+        assert_eq!(repairs[1].violation, Violation::BeforePreviousStop);
+        assert_eq!(repairs[1].minimum_time, 2000);
+
+        assert_eq!(times(&trip_update, 1), (Some(2000), Some(2000)));
+        assert_eq!(
+            trip_update.stop_time_update[1].departure.unwrap().delay,
+            Some(100)
+        );
+        assert_eq!(times(&trip_update, 2), (Some(2000), Some(2100)));
+        // Skipped stops aren't touched
+        assert_eq!(times(&trip_update, 3), (Some(0), Some(0)));
+        assert_eq!(times(&trip_update, 4), (Some(3000), Some(3100)));
+    }
+
+    #[test]
+    fn drop() {
+        let mut trip_update = trip_update();
+        trip_update.stop_time_update[2].departure = None;
+
+        let repairs = enforce_monotonicity("1", &mut trip_update, RepairStrategy::Drop);
+
+        assert_eq!(repairs.len(), 2);
+        assert_eq!(times(&trip_update, 1), (Some(2000), None));
+        assert_eq!(times(&trip_update, 2), (None, None));
+        assert_eq!(
+            trip_update.stop_time_update[2].schedule_relationship,
+            Some(ScheduleRelationship::NoData as i32)
+        );
+    }
+
+    #[test]
+    fn flag() {
+        let mut feed = FeedMessage {
+            header: crate::make_gtfs_header(),
+            entity: vec![gtfs_realtime::FeedEntity {
+                id: "20260119-11".to_string(),
+                trip_update: Some(trip_update()),
+                ..Default::default()
+            }],
+        };
+
+        let repairs = enforce_feed_monotonicity(&mut feed, RepairStrategy::Flag);
+
+        assert_eq!(repairs.len(), 2);
+        assert_eq!(repairs[0].entity_id, "20260119-11");
+        assert_eq!(
+            repairs[1].to_string(),
+            "20260119-11 stop 2 (OLW): Arrival BeforePreviousStop, 200s early, Flag"
+        );
+
+        let trip_update = feed.entity[0].trip_update.as_ref().unwrap();
+        assert_eq!(times(trip_update, 1), (Some(2000), Some(1900)));
+        assert_eq!(times(trip_update, 2), (Some(1800), Some(2100)));
+    }
+}
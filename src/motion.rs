@@ -0,0 +1,332 @@
+//! Plausibility filtering and smoothing of train speeds and headings.
+//!
+//! Track-A-Train reports `Velocity` as a string in miles per hour and `Heading` as one of 8 compass points,
+//! which makes map icons point up to 22.5 degrees off, or jump between points on curves. Speeds that can't
+//! be right are dropped, and the heading is derived from consecutive positions whenever the train moved far
+//! enough between polls for the GPS noise not to matter.
+
+use crate::shapes::{bearing_deg, haversine_m};
+use gtfs_realtime::FeedMessage;
+use std::collections::HashMap;
+
+pub const MPH_TO_METERS_PER_SECOND: f32 = 0.44704;
+
+/// Faster than anything Amtrak runs, Acela tops out at 150 mph.
+pub const MAX_SPEED_MPH: f32 = 150.0;
+
+/// Minimum distance between two positions for the heading between them to be trusted.
+pub const MIN_DISTANCE_FOR_HEADING_M: f64 = 50.0;
+
+/// Parses a Track-A-Train `Velocity` to meters per second, `None` when it isn't plausible.
+pub fn parse_speed(value: &serde_json::Value) -> Option<f32> {
+    let mph = match value {
+        serde_json::Value::String(text) => text.trim().parse::<f32>().ok()?,
+        serde_json::Value::Number(number) => number.as_f64()? as f32,
+        _ => return None,
+    };
+
+    match mph.is_finite() && (0.0..=MAX_SPEED_MPH).contains(&mph) {
+        true => Some(mph * MPH_TO_METERS_PER_SECOND),
+        false => None,
+    }
+}
+
+/// Converts a compass point, like `N` or `SW`, to degrees clockwise from north.
+pub fn compass_to_bearing(text: &str) -> Option<f32> {
+    const POINTS: [&str; 16] = [
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+        "NW", "NNW",
+    ];
+
+    let text = text.trim().to_ascii_uppercase();
+
+    POINTS
+        .iter()
+        .position(|point| *point == text)
+        .map(|i| i as f32 * 22.5)
+}
+
+/// Moves `from` towards `to` by `weight`, taking the shortest way around the compass.
+fn blend_bearing(from: f32, to: f32, weight: f32) -> f32 {
+    let difference = (to - from + 540.0).rem_euclid(360.0) - 180.0;
+
+    (from + difference * weight).rem_euclid(360.0)
+}
+
+/// What was last published for a vehicle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct VehicleMotion {
+    position: (f64, f64),
+    timestamp: Option<u64>,
+    speed: Option<f32>,
+    bearing: Option<f32>,
+    /// Unix seconds of the poll `bearing` was last derived from a movement at, `None` for a compass point
+    bearing_timestamp: Option<u64>,
+}
+
+/// One poll of a vehicle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionSample {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Unix seconds of the position
+    pub timestamp: Option<u64>,
+    /// Meters per second
+    pub speed: Option<f32>,
+    /// Degrees clockwise from north
+    pub bearing: Option<f32>,
+}
+
+/// Keeps the previous poll of every vehicle to derive and smooth their speed and heading.
+#[derive(Debug, Clone)]
+pub struct MotionSmoother {
+    /// Weight of the newest poll, from 0 exclusive to 1. 1 disables smoothing.
+    pub weight: f32,
+    /// Previous polls older than this are forgotten, seconds.
+    pub max_age_secs: u64,
+    previous: HashMap<String, VehicleMotion>,
+}
+
+impl Default for MotionSmoother {
+    fn default() -> Self {
+        MotionSmoother {
+            weight: 0.5,
+            max_age_secs: 15 * 60,
+            previous: HashMap::new(),
+        }
+    }
+}
+
+impl MotionSmoother {
+    pub fn new(weight: f32, max_age_secs: u64) -> Self {
+        MotionSmoother {
+            weight: weight.clamp(f32::EPSILON, 1.0),
+            max_age_secs,
+            previous: HashMap::new(),
+        }
+    }
+
+    /// Returns the speed and bearing to publish for a vehicle.
+    pub fn update(&mut self, vehicle_id: &str, sample: MotionSample) -> (Option<f32>, Option<f32>) {
+        let position = (sample.latitude, sample.longitude);
+
+        let previous = self.previous.get(vehicle_id).copied().filter(|previous| {
+            match (previous.timestamp, sample.timestamp) {
+                (Some(previous), Some(current)) => {
+                    previous <= current && current - previous <= self.max_age_secs
+                }
+                _ => true,
+            }
+        });
+
+        let moved = previous.is_some_and(|previous| {
+            haversine_m(previous.position, position) >= MIN_DISTANCE_FOR_HEADING_M
+        });
+
+        let derived_bearing = match (previous, moved) {
+            (Some(previous), true) => Some(bearing_deg(previous.position, position) as f32),
+            _ => None,
+        };
+
+        // Only a heading derived recently is worth keeping over the compass point
+        let previous_bearing = previous
+            .filter(
+                |previous| match (previous.bearing_timestamp, sample.timestamp) {
+                    (Some(derived), Some(current)) => {
+                        derived <= current && current - derived <= self.max_age_secs
+                    }
+                    (derived, _) => derived.is_some(),
+                },
+            )
+            .and_then(|previous| previous.bearing);
+
+        let (bearing, bearing_timestamp) = match (previous_bearing, derived_bearing) {
+            (Some(previous), Some(derived)) => (
+                Some(blend_bearing(previous, derived, self.weight)),
+                sample.timestamp,
+            ),
+            (None, Some(derived)) => (Some(derived), sample.timestamp),
+            // Standing still or barely moving, a recent heading is better than a compass point
+            (Some(kept), None) => (
+                Some(kept),
+                previous.and_then(|previous| previous.bearing_timestamp),
+            ),
+            (None, None) => (sample.bearing, None),
+        };
+
+        let speed = match (previous.and_then(|previous| previous.speed), sample.speed) {
+            (Some(previous), Some(current)) => Some(previous + (current - previous) * self.weight),
+            (_, current) => current,
+        };
+
+        // Only move the anchor once the train went far enough, so slow trains still get a heading
+        let anchor = match (previous, moved) {
+            (Some(previous), false) => previous.position,
+            _ => position,
+        };
+
+        self.previous.insert(
+            vehicle_id.to_string(),
+            VehicleMotion {
+                position: anchor,
+                timestamp: sample.timestamp,
+                speed,
+                bearing,
+                bearing_timestamp,
+            },
+        );
+
+        (speed, bearing)
+    }
+
+    /// Smooths the position of every vehicle in the feed, keyed by entity id.
+    /// Vehicles missing from the feed are forgotten.
+    pub fn apply(&mut self, feed: &mut FeedMessage) {
+        let mut seen = vec![];
+
+        for entity in feed.entity.iter_mut() {
+            let Some(vehicle) = entity.vehicle.as_mut() else {
+                continue;
+            };
+
+            let Some(position) = vehicle.position.as_mut() else {
+                continue;
+            };
+
+            let (speed, bearing) = self.update(
+                &entity.id,
+                MotionSample {
+                    latitude: position.latitude as f64,
+                    longitude: position.longitude as f64,
+                    timestamp: vehicle.timestamp,
+                    speed: position.speed,
+                    bearing: position.bearing,
+                },
+            );
+
+            position.speed = speed;
+            position.bearing = bearing;
+
+            seen.push(entity.id.clone());
+        }
+
+        self.previous
+            .retain(|vehicle_id, _| seen.contains(vehicle_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds() {
+        assert_eq!(parse_speed(&"0".into()), Some(0.0));
+        assert_eq!(parse_speed(&" 100 ".into()), Some(44.704));
+        assert_eq!(parse_speed(&serde_json::json!(100)), Some(44.704));
+
+        for value in ["-3", "151", "fast", "", "NaN", "inf"] {
+            assert_eq!(parse_speed(&value.into()), None, "{}", value);
+        }
+
+        assert_eq!(parse_speed(&serde_json::Value::Null), None);
+    }
+
+    #[test]
+    fn compass_points() {
+        assert_eq!(compass_to_bearing("N"), Some(0.0));
+        assert_eq!(compass_to_bearing("se"), Some(135.0));
+        assert_eq!(compass_to_bearing("WNW"), Some(292.5));
+        assert_eq!(compass_to_bearing("up"), None);
+
+        assert_eq!(blend_bearing(350.0, 10.0, 0.5), 0.0);
+        assert_eq!(blend_bearing(10.0, 350.0, 0.5), 0.0);
+        assert_eq!(blend_bearing(90.0, 180.0, 1.0), 180.0);
+    }
+
+    fn sample(latitude: f64, timestamp: u64, bearing: Option<f32>) -> MotionSample {
+        MotionSample {
+            latitude,
+            longitude: -122.0,
+            timestamp: Some(timestamp),
+            speed: Some(20.0),
+            bearing,
+        }
+    }
+
+    #[test]
+    fn heading_from_consecutive_positions() {
+        let mut smoother = MotionSmoother::new(1.0, 600);
+
+        // First poll only has the compass point
+        assert_eq!(
+            smoother.update("1", sample(45.0, 0, Some(0.0))),
+            (Some(20.0), Some(0.0))
+        );
+
+        // Moving south while the compass still says north
+        let (_, bearing) = smoother.update("1", sample(44.99, 60, Some(0.0)));
+        assert!((bearing.unwrap() - 180.0).abs() < 0.1);
+
+        // A few meters of GPS noise keep the heading
+        let (_, bearing) = smoother.update("1", sample(44.99001, 120, Some(0.0)));
+        assert!((bearing.unwrap() - 180.0).abs() < 0.1);
+
+        // Too old to compare against
+        assert_eq!(
+            smoother.update("1", sample(45.5, 10_000, Some(90.0))).1,
+            Some(90.0)
+        );
+    }
+
+    #[test]
+    fn derived_heading_ages_out() {
+        let mut smoother = MotionSmoother::new(1.0, 600);
+
+        smoother.update("1", sample(45.0, 0, Some(0.0)));
+        let (_, bearing) = smoother.update("1", sample(44.99, 60, Some(180.0)));
+        assert!((bearing.unwrap() - 180.0).abs() < 0.1);
+
+        // Standing at a station, the derived heading is kept for a while
+        let (_, bearing) = smoother.update("1", sample(44.99, 300, Some(0.0)));
+        assert!((bearing.unwrap() - 180.0).abs() < 0.1);
+
+        // Then the compass point takes over, after the train reversed for example
+        assert_eq!(
+            smoother.update("1", sample(44.99, 900, Some(0.0))).1,
+            Some(0.0)
+        );
+        assert_eq!(
+            smoother.update("1", sample(44.99, 960, Some(0.0))).1,
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn smoothing() {
+        let mut smoother = MotionSmoother::new(0.5, 600);
+
+        smoother.update("1", sample(45.0, 0, None));
+        // East, then north east
+        smoother.update(
+            "1",
+            MotionSample {
+                longitude: -121.99,
+                ..sample(45.0, 60, None)
+            },
+        );
+        let (speed, bearing) = smoother.update(
+            "1",
+            MotionSample {
+                latitude: 45.01,
+                longitude: -121.98,
+                speed: Some(30.0),
+                ..sample(45.0, 120, None)
+            },
+        );
+
+        assert_eq!(speed, Some(25.0));
+        let bearing = bearing.unwrap();
+        assert!(bearing > 50.0 && bearing < 80.0, "{}", bearing);
+    }
+}
@@ -0,0 +1,570 @@
+//! On-time performance of trains, computed from the actual times observed across polls.
+//!
+//! Feed the trip updates of every poll to an [`OnTimePerformance`], then build an [`OtpReport`] with metrics
+//! per route, per station and per segment between stations. Only actual times count, i.e. stop time events
+//! with an uncertainty of 0, compared with their scheduled time.
+//!
+//! Station metrics use the arrival, or the departure at stations without an arrival like the origin.
+//! Route metrics are over every station of the route's trains, like Amtrak's all-stations on-time performance.
+
+use crate::trip_modifications::scheduled_trip;
+use gtfs_realtime::FeedMessage;
+use gtfs_realtime::trip_update::StopTimeEvent;
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Amtrak counts a train as on time up to 15 minutes late.
+pub const ON_TIME_THRESHOLD_SECS: i64 = 15 * 60;
+
+/// Delays observed at one station, seconds. Negative when early.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StationDelay {
+    pub stop_id: String,
+    pub stop_sequence: Option<u32>,
+    /// Scheduled arrival, else departure, unix seconds
+    pub scheduled_time: Option<i64>,
+    pub arrival_delay: Option<i64>,
+    pub departure_delay: Option<i64>,
+}
+
+impl StationDelay {
+    /// The delay counted for the station.
+    pub fn delay(&self) -> Option<i64> {
+        self.arrival_delay.or(self.departure_delay)
+    }
+}
+
+/// Every delay observed for one train on one service date.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TrainRun {
+    pub entity_id: String,
+    /// `YYYYMMDD`
+    pub service_date: String,
+    pub train_number: String,
+    pub route_id: Option<String>,
+    /// In travel order
+    pub stations: Vec<StationDelay>,
+}
+
+/// Train number in an entity id like `20260119-11`.
+pub(crate) fn train_number(entity_id: &str, service_date: &str) -> String {
+    entity_id
+        .strip_prefix(&format!("{}-", service_date))
+        .unwrap_or(entity_id)
+        .to_string()
+}
+
+/// Delay of an actual time, seconds.
+fn actual_delay(event: Option<&StopTimeEvent>) -> Option<i64> {
+    let event = event.filter(|event| event.uncertainty == Some(0))?;
+
+    Some(event.time? - event.scheduled_time?)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OnTimePerformance {
+    /// By entity id
+    runs: BTreeMap<String, TrainRun>,
+}
+
+impl OnTimePerformance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the actual times of a feed's trip updates. Feeds can be passed in any order, stations are kept
+    /// in travel order by scheduled time and stop sequence.
+    pub fn ingest(&mut self, feed: &FeedMessage) {
+        for entity in &feed.entity {
+            let Some(trip_update) = &entity.trip_update else {
+                continue;
+            };
+
+            let Some(trip) = scheduled_trip(entity) else {
+                continue;
+            };
+
+            let Some(service_date) = trip.start_date.clone() else {
+                continue;
+            };
+
+            let run = self
+                .runs
+                .entry(entity.id.clone())
+                .or_insert_with(|| TrainRun {
+                    entity_id: entity.id.clone(),
+                    train_number: train_number(&entity.id, &service_date),
+                    service_date,
+                    route_id: None,
+                    stations: vec![],
+                });
+
+            if trip.route_id.is_some() {
+                run.route_id = trip.route_id;
+            }
+
+            for stop_time_update in &trip_update.stop_time_update {
+                if stop_time_update.schedule_relationship
+                    == Some(ScheduleRelationship::Skipped as i32)
+                {
+                    continue;
+                }
+
+                let Some(stop_id) = &stop_time_update.stop_id else {
+                    continue;
+                };
+
+                let arrival_delay = actual_delay(stop_time_update.arrival.as_ref());
+                let departure_delay = actual_delay(stop_time_update.departure.as_ref());
+
+                if arrival_delay.is_none() && departure_delay.is_none() {
+                    continue;
+                }
+
+                // Same station, unless the train calls at it twice
+                let position = run.stations.iter().position(|station| {
+                    &station.stop_id == stop_id
+                        && (station.stop_sequence.is_none()
+                            || stop_time_update.stop_sequence.is_none()
+                            || station.stop_sequence == stop_time_update.stop_sequence)
+                });
+
+                let station = match position {
+                    Some(position) => &mut run.stations[position],
+                    None => {
+                        run.stations.push(StationDelay {
+                            stop_id: stop_id.clone(),
+                            stop_sequence: stop_time_update.stop_sequence,
+                            ..Default::default()
+                        });
+
+                        run.stations.last_mut().unwrap()
+                    }
+                };
+
+                let scheduled_time = [&stop_time_update.arrival, &stop_time_update.departure]
+                    .into_iter()
+                    .find_map(|event| event.as_ref().and_then(|event| event.scheduled_time));
+
+                station.stop_sequence = station.stop_sequence.or(stop_time_update.stop_sequence);
+                station.scheduled_time = station.scheduled_time.or(scheduled_time);
+                station.arrival_delay = arrival_delay.or(station.arrival_delay);
+                station.departure_delay = departure_delay.or(station.departure_delay);
+            }
+
+            // Stations are first seen in whatever order polls and actual times came in
+            run.stations
+                .sort_by_key(|station| (station.scheduled_time, station.stop_sequence));
+        }
+    }
+
+    /// Runs with at least one station observed.
+    pub fn runs(&self) -> impl Iterator<Item = &TrainRun> {
+        self.runs.values().filter(|run| !run.stations.is_empty())
+    }
+
+    pub fn report(&self) -> OtpReport {
+        let mut routes: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        let mut stations: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        let mut segments: BTreeMap<(String, String, String), Vec<i64>> = BTreeMap::new();
+
+        for run in self.runs() {
+            for station in &run.stations {
+                if let Some(delay) = station.delay() {
+                    stations
+                        .entry(station.stop_id.clone())
+                        .or_default()
+                        .push(delay);
+
+                    if let Some(route_id) = &run.route_id {
+                        routes.entry(route_id.clone()).or_default().push(delay);
+                    }
+                }
+            }
+
+            // Delay gained between consecutive observed stations
+            for pair in run.stations.windows(2) {
+                let from = pair[0].departure_delay.or(pair[0].arrival_delay);
+                let to = pair[1].delay();
+
+                if let (Some(from), Some(to)) = (from, to) {
+                    segments
+                        .entry((
+                            run.route_id.clone().unwrap_or_default(),
+                            pair[0].stop_id.clone(),
+                            pair[1].stop_id.clone(),
+                        ))
+                        .or_default()
+                        .push(to - from);
+                }
+            }
+        }
+
+        OtpReport {
+            routes: routes
+                .into_iter()
+                .map(|(route_id, delays)| OtpMetrics::new(route_id, delays))
+                .collect(),
+            stations: stations
+                .into_iter()
+                .map(|(stop_id, delays)| OtpMetrics::new(stop_id, delays))
+                .collect(),
+            segments: segments
+                .into_iter()
+                .map(|((route_id, from_stop_id, to_stop_id), changes)| {
+                    SegmentDelay::new(route_id, from_stop_id, to_stop_id, changes)
+                })
+                .collect(),
+        }
+    }
+}
+
+fn mean(values: &[i64]) -> f64 {
+    values.iter().sum::<i64>() as f64 / values.len() as f64
+}
+
+fn median(values: &[i64]) -> f64 {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+
+    let middle = values.len() / 2;
+
+    match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) as f64 / 2.0,
+        _ => values[middle] as f64,
+    }
+}
+
+/// On-time performance of a route or a station.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OtpMetrics {
+    /// Route id or stop id
+    pub id: String,
+    pub observations: usize,
+    /// Observations at most [`ON_TIME_THRESHOLD_SECS`] late
+    pub on_time: usize,
+    pub on_time_percent: f64,
+    pub mean_delay_secs: f64,
+    pub median_delay_secs: f64,
+}
+
+impl OtpMetrics {
+    fn new(id: String, delays: Vec<i64>) -> Self {
+        let on_time = delays
+            .iter()
+            .filter(|delay| **delay <= ON_TIME_THRESHOLD_SECS)
+            .count();
+
+        OtpMetrics {
+            id,
+            observations: delays.len(),
+            on_time,
+            on_time_percent: 100.0 * on_time as f64 / delays.len() as f64,
+            mean_delay_secs: mean(&delays),
+            median_delay_secs: median(&delays),
+        }
+    }
+}
+
+/// Delay gained by trains between two consecutive stations, of trains observed at both.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SegmentDelay {
+    /// Empty for unknown routes
+    pub route_id: String,
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    pub observations: usize,
+    /// Sum of the delay gained, ignoring time made up, minutes
+    pub delay_minutes: f64,
+    /// Mean change in delay, negative when time is made up, seconds
+    pub mean_delay_change_secs: f64,
+}
+
+impl SegmentDelay {
+    fn new(route_id: String, from_stop_id: String, to_stop_id: String, changes: Vec<i64>) -> Self {
+        SegmentDelay {
+            route_id,
+            from_stop_id,
+            to_stop_id,
+            observations: changes.len(),
+            delay_minutes: changes.iter().map(|change| change.max(&0)).sum::<i64>() as f64 / 60.0,
+            mean_delay_change_secs: mean(&changes),
+        }
+    }
+}
+
+/// Quotes a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OtpReport {
+    pub routes: Vec<OtpMetrics>,
+    pub stations: Vec<OtpMetrics>,
+    pub segments: Vec<SegmentDelay>,
+}
+
+impl OtpReport {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    fn metrics_csv(metrics: &[OtpMetrics]) -> String {
+        let mut csv = String::from(
+            "id,observations,on_time,on_time_percent,mean_delay_secs,median_delay_secs\n",
+        );
+
+        for metrics in metrics {
+            csv.push_str(&format!(
+                "{},{},{},{:.1},{:.1},{:.1}\n",
+                csv_field(&metrics.id),
+                metrics.observations,
+                metrics.on_time,
+                metrics.on_time_percent,
+                metrics.mean_delay_secs,
+                metrics.median_delay_secs
+            ));
+        }
+
+        csv
+    }
+
+    pub fn routes_csv(&self) -> String {
+        Self::metrics_csv(&self.routes)
+    }
+
+    pub fn stations_csv(&self) -> String {
+        Self::metrics_csv(&self.stations)
+    }
+
+    pub fn segments_csv(&self) -> String {
+        let mut csv = String::from(
+            "route_id,from_stop_id,to_stop_id,observations,delay_minutes,mean_delay_change_secs\n",
+        );
+
+        for segment in &self.segments {
+            csv.push_str(&format!(
+                "{},{},{},{},{:.1},{:.1}\n",
+                csv_field(&segment.route_id),
+                csv_field(&segment.from_stop_id),
+                csv_field(&segment.to_stop_id),
+                segment.observations,
+                segment.delay_minutes,
+                segment.mean_delay_change_secs
+            ));
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{event, feed, stop, train_entity};
+
+    const MINUTE: i64 = 60;
+    const SCHEDULED: i64 = 1000 * MINUTE;
+
+    /// A train with `(stop_id, arrival delay, departure delay)` in minutes, all actual.
+    fn train_delays(
+        service_date: &str,
+        train: &str,
+        stops: &[(&str, Option<i64>, Option<i64>)],
+    ) -> FeedMessage {
+        let actual =
+            |delay: Option<i64>| delay.and_then(|delay| event(SCHEDULED, delay * MINUTE, true));
+
+        feed(vec![train_entity(
+            service_date,
+            train,
+            stops
+                .iter()
+                .map(|(stop_id, arrival, departure)| {
+                    stop(stop_id, actual(*arrival), actual(*departure))
+                })
+                .collect(),
+        )])
+    }
+
+    #[test]
+    fn accumulates_actuals_across_polls() {
+        let mut otp = OnTimePerformance::new();
+
+        // Estimates don't count
+        let mut first = train_delays(
+            "20260119",
+            "11",
+            &[("SEA", None, Some(5)), ("PDX", Some(10), None)],
+        );
+        first.entity[0]
+            .trip_update
+            .as_mut()
+            .unwrap()
+            .stop_time_update[1]
+            .arrival = event(SCHEDULED, 10 * MINUTE, false);
+
+        otp.ingest(&first);
+        otp.ingest(&train_delays(
+            "20260119",
+            "11",
+            &[("PDX", Some(20), Some(25))],
+        ));
+
+        let runs = otp.runs().collect::<Vec<_>>();
+
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].train_number, "11");
+        assert_eq!(runs[0].service_date, "20260119");
+        assert_eq!(
+            runs[0].stations,
+            vec![
+                StationDelay {
+                    stop_id: "SEA".to_string(),
+                    stop_sequence: None,
+                    scheduled_time: Some(SCHEDULED),
+                    arrival_delay: None,
+                    departure_delay: Some(5 * MINUTE),
+                },
+                StationDelay {
+                    stop_id: "PDX".to_string(),
+                    stop_sequence: None,
+                    scheduled_time: Some(SCHEDULED),
+                    arrival_delay: Some(20 * MINUTE),
+                    departure_delay: Some(25 * MINUTE),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stations_in_travel_order() {
+        let mut otp = OnTimePerformance::new();
+
+        let with_sequences = |stops: &[(&str, Option<i64>, Option<i64>)], sequences: &[u32]| {
+            let mut polled = train_delays("20260119", "11", stops);
+
+            let trip_update = polled.entity[0].trip_update.as_mut().unwrap();
+
+            for (stop_time_update, sequence) in
+                trip_update.stop_time_update.iter_mut().zip(sequences)
+            {
+                stop_time_update.stop_sequence = Some(*sequence);
+            }
+
+            polled
+        };
+
+        // The destination's arrival is seen before the archived origin departure
+        otp.ingest(&with_sequences(&[("PDX", Some(10), None)], &[20]));
+        otp.ingest(&with_sequences(&[("SEA", None, Some(0))], &[10]));
+
+        let stations = otp
+            .runs()
+            .flat_map(|run| run.stations.iter())
+            .map(|station| station.stop_id.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(stations, vec!["SEA", "PDX"]);
+
+        let report = otp.report();
+        assert_eq!(report.segments.len(), 1);
+        assert_eq!(report.segments[0].from_stop_id, "SEA");
+        assert_eq!(report.segments[0].to_stop_id, "PDX");
+        assert_eq!(report.segments[0].mean_delay_change_secs, 600.0);
+    }
+
+    #[test]
+    fn metrics() {
+        let mut otp = OnTimePerformance::new();
+
+        otp.ingest(&train_delays(
+            "20260119",
+            "11",
+            &[("SEA", None, Some(0)), ("PDX", Some(10), None)],
+        ));
+        otp.ingest(&train_delays(
+            "20260120",
+            "11",
+            &[("SEA", None, Some(-2)), ("PDX", Some(40), None)],
+        ));
+        otp.ingest(&train_delays(
+            "20260121",
+            "11",
+            &[("SEA", None, Some(20)), ("PDX", Some(16), None)],
+        ));
+
+        let report = otp.report();
+
+        assert_eq!(report.routes.len(), 1);
+        assert_eq!(report.routes[0].observations, 6);
+        assert_eq!(report.routes[0].on_time, 3);
+        assert_eq!(report.routes[0].on_time_percent, 50.0);
+        assert_eq!(report.routes[0].median_delay_secs, 13.0 * MINUTE as f64);
+
+        let pdx = report
+            .stations
+            .iter()
+            .find(|station| station.id == "PDX")
+            .unwrap();
+        assert_eq!(pdx.on_time, 1);
+        assert_eq!(pdx.mean_delay_secs, 22.0 * MINUTE as f64);
+        assert_eq!(pdx.median_delay_secs, 16.0 * MINUTE as f64);
+
+        // 10 and 42 minutes lost, 4 made up
+        assert_eq!(report.segments.len(), 1);
+        assert_eq!(report.segments[0].from_stop_id, "SEA");
+        assert_eq!(report.segments[0].delay_minutes, 52.0);
+        assert_eq!(
+            report.segments[0].mean_delay_change_secs,
+            16.0 * MINUTE as f64
+        );
+
+        assert_eq!(
+            report.routes_csv(),
+            "id,observations,on_time,on_time_percent,mean_delay_secs,median_delay_secs\n\
+             94,6,3,50.0,840.0,780.0\n"
+        );
+        assert!(report.segments_csv().ends_with("94,SEA,PDX,3,52.0,960.0\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["stations"][0]["id"], "PDX");
+    }
+
+    #[test]
+    fn from_track_a_train() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+
+        let mut sea = crate::test_helpers::station("SEA", None, Some("01/19/2026 09:52:00"), "");
+        sea["postdep"] = "01/19/2026 10:00:00".into();
+
+        let mut pdx = crate::test_helpers::station(
+            "PDX",
+            Some("01/19/2026 13:30:00"),
+            Some("01/19/2026 13:45:00"),
+            "",
+        );
+        pdx["postarr"] = "01/19/2026 13:50:00".into();
+
+        let feature = crate::test_helpers::feature(
+            "11",
+            "Coast Starlight",
+            "1/19/2026 9:52:00 AM",
+            &[sea, pdx],
+        );
+
+        let mut otp = OnTimePerformance::new();
+        otp.ingest(&feed(crate::feature_to_gtfs_unified(&gtfs, &feature, None)));
+
+        let run = otp.runs().next().unwrap();
+
+        assert_eq!(run.entity_id, "20260119-11");
+        assert_eq!(run.stations[0].departure_delay, Some(8 * MINUTE));
+        assert_eq!(run.stations[1].arrival_delay, Some(20 * MINUTE));
+    }
+}
@@ -1,28 +1,33 @@
+use crate::advisory_source::{AdvisorySource, advisory_entity, fetch_advisories};
 use gtfs_realtime::FeedEntity;
 use gtfs_structures::Gtfs;
 use scraper::{Html, Selector};
-use std::time::SystemTime;
+
+pub struct PacificSurfliner;
+
+impl AdvisorySource for PacificSurfliner {
+    fn id_prefix(&self) -> &'static str {
+        "PAC_SURF"
+    }
+
+    fn route_long_name(&self) -> &'static str {
+        "Pacific Surfliner"
+    }
+
+    fn url(&self) -> &'static str {
+        "https://www.pacificsurfliner.com/plan-your-trip/alerts/travel-advisories/"
+    }
+
+    fn parse(&self, html: &str, route_id: Option<String>) -> Vec<FeedEntity> {
+        parse_pacific_surfliner_advisories(html, route_id)
+    }
+}
 
 pub async fn fetch_pacific_surfliner_advisories(
     client: &reqwest::Client,
     gtfs: &Gtfs,
 ) -> Result<Vec<FeedEntity>, Box<dyn std::error::Error + Sync + Send>> {
-    let url = "https://www.pacificsurfliner.com/plan-your-trip/alerts/travel-advisories/";
-    let resp = client.get(url).send().await?;
-    let text = resp.text().await?;
-
-    // Find Pacific Surfliner route ID
-    let route_id = gtfs
-        .routes
-        .values()
-        .find(|r| r.long_name.as_deref() == Some("Pacific Surfliner"))
-        .map(|r| r.id.clone());
-
-    if route_id.is_none() {
-        return Ok(vec![]);
-    }
-
-    Ok(parse_pacific_surfliner_advisories(&text, route_id))
+    fetch_advisories(&PacificSurfliner, client, gtfs).await
 }
 
 pub fn parse_pacific_surfliner_advisories(text: &str, route_id: Option<String>) -> Vec<FeedEntity> {
@@ -183,62 +188,13 @@ pub fn parse_pacific_surfliner_advisories(text: &str, route_id: Option<String>)
                             content_sibling = sib_node.next_sibling();
                         }
 
-                        // Create alert entity
-                        use std::collections::hash_map::DefaultHasher;
-                        use std::hash::{Hash, Hasher};
-                        let mut hasher = DefaultHasher::new();
-                        title_text.hash(&mut hasher);
-                        let id_hash = hasher.finish();
-
-                        let id = format!("PAC_SURF_{}", id_hash);
-
-                        let ent = FeedEntity {
-                            id,
-                            is_deleted: Some(false),
-                            trip_update: None,
-                            vehicle: None,
-                            stop: None,
-                            shape: None,
-                            trip_modifications: None,
-                            alert: Some(gtfs_realtime::Alert {
-                                active_period: vec![],
-                                informed_entity: vec![gtfs_realtime::EntitySelector {
-                                    agency_id: None,
-                                    route_id: route_id.clone(),
-                                    route_type: None,
-                                    trip: None,
-                                    stop_id: None,
-                                    direction_id: None,
-                                }],
-                                cause: Some(1),  // Unknown cause
-                                effect: Some(8), // Unknown effect
-                                url: None,
-                                header_text: Some(gtfs_realtime::TranslatedString {
-                                    translation: vec![
-                                        gtfs_realtime::translated_string::Translation {
-                                            text: title_text,
-                                            language: Some("en".to_string()),
-                                        },
-                                    ],
-                                }),
-                                description_text: Some(gtfs_realtime::TranslatedString {
-                                    translation: vec![
-                                        gtfs_realtime::translated_string::Translation {
-                                            text: description.trim().to_string(),
-                                            language: Some("en".to_string()),
-                                        },
-                                    ],
-                                }),
-                                tts_header_text: None,
-                                tts_description_text: None,
-                                severity_level: None,
-                                image: None,
-                                image_alternative_text: None,
-                                cause_detail: None,
-                                effect_detail: None,
-                            }),
-                        };
-                        alerts.push(ent);
+                        alerts.push(advisory_entity(
+                            PacificSurfliner.id_prefix(),
+                            &header_text_all,
+                            title_text,
+                            &description,
+                            route_id.clone(),
+                        ));
                         current_node = content_sibling;
                         continue;
                     }
@@ -0,0 +1,602 @@
+//! Arrival predictions learned from the running and dwell times of past trains.
+//!
+//! Amtrak's estimates mostly carry the current delay forward. An [`ArrivalPredictor`] is trained on the
+//! [`SegmentObservation`]s of archived history, and predicts each station after the train's last actual time
+//! from the median running time of every segment and median dwell at every station. Its predictions are
+//! blended with Amtrak's estimates, and the spread between two quantiles of the history becomes the
+//! `uncertainty` of the stop time event.
+//!
+//! Segments with too little history keep Amtrak's estimate, moved by as much as the previous prediction moved
+//! Amtrak's, and predictions continue from there. Predicted times never go back in time along the trip.
+
+use crate::archive::{Archive, ArchiveError};
+use crate::segment_times::{SegmentObservation, SegmentTimeObserver};
+use crate::trip_modifications::scheduled_trip;
+use gtfs_realtime::trip_update::StopTimeEvent;
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_realtime::{FeedMessage, TripUpdate};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Fewer observations than this, and a segment or station isn't predicted.
+pub const DEFAULT_MIN_SAMPLES: usize = 10;
+
+/// Observed durations of a segment or dwell, seconds, kept sorted.
+#[derive(Debug, Clone, Default)]
+struct Distribution {
+    sorted: Vec<i64>,
+}
+
+impl Distribution {
+    fn add(&mut self, value: i64) {
+        let index = self.sorted.partition_point(|x| *x <= value);
+        self.sorted.insert(index, value);
+    }
+
+    /// Linearly interpolated quantile, `q` from 0 to 1.
+    fn quantile(&self, q: f64) -> Option<f64> {
+        let last = self.sorted.len().checked_sub(1)?;
+
+        let rank = q.clamp(0.0, 1.0) * last as f64;
+        let below = rank.floor() as usize;
+        let above = rank.ceil() as usize;
+
+        Some(
+            self.sorted[below] as f64
+                + (self.sorted[above] - self.sorted[below]) as f64 * (rank - below as f64),
+        )
+    }
+}
+
+/// A duration predicted from history: the median, and the variance of its error.
+#[derive(Debug, Clone, Copy)]
+struct Estimate {
+    median: f64,
+    variance: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArrivalPredictor {
+    /// Routes the predictor is used on, `None` for every route
+    pub routes: Option<HashSet<String>>,
+    /// Weight of the prediction against Amtrak's estimate, from 0 to 1
+    pub weight: f64,
+    pub min_samples: usize,
+    /// Quantiles of the history the uncertainty spans
+    pub lower_quantile: f64,
+    pub upper_quantile: f64,
+    /// Running times by `(from stop id, to stop id)`
+    running: HashMap<(String, String), Distribution>,
+    /// Dwell times by stop id
+    dwells: HashMap<String, Distribution>,
+}
+
+impl Default for ArrivalPredictor {
+    fn default() -> Self {
+        ArrivalPredictor {
+            routes: None,
+            weight: 0.5,
+            min_samples: DEFAULT_MIN_SAMPLES,
+            lower_quantile: 0.1,
+            upper_quantile: 0.9,
+            running: HashMap::new(),
+            dwells: HashMap::new(),
+        }
+    }
+}
+
+/// Actual times are marked with an uncertainty of 0.
+fn is_actual(event: &Option<StopTimeEvent>) -> bool {
+    event
+        .as_ref()
+        .is_some_and(|event| event.uncertainty == Some(0))
+}
+
+fn event_time(event: &Option<StopTimeEvent>) -> Option<i64> {
+    event.as_ref().and_then(|event| event.time)
+}
+
+impl ArrivalPredictor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn train(&mut self, observation: &SegmentObservation) {
+        match observation {
+            SegmentObservation::Running(running) => self
+                .running
+                .entry((running.from_stop_id.clone(), running.to_stop_id.clone()))
+                .or_default()
+                .add(running.running_secs),
+            SegmentObservation::Dwell(dwell) => self
+                .dwells
+                .entry(dwell.stop_id.clone())
+                .or_default()
+                .add(dwell.dwell_secs),
+        }
+    }
+
+    /// Trains on the trip updates archived within `range`, unix milliseconds, returning the number of
+    /// observations.
+    pub fn train_from_archive(
+        &mut self,
+        archive: &Archive,
+        range: Range<i64>,
+    ) -> Result<usize, ArchiveError> {
+        let mut observer = SegmentTimeObserver::new();
+        let mut count = 0;
+
+        for snapshot in archive.snapshots(range)? {
+            if let Some(trip_updates) = &snapshot?.trip_updates {
+                for observation in observer.observe(trip_updates) {
+                    self.train(&observation);
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn estimate(&self, distribution: Option<&Distribution>) -> Option<Estimate> {
+        let distribution = distribution
+            .filter(|distribution| distribution.sorted.len() >= self.min_samples.max(1))?;
+
+        let spread = distribution.quantile(self.upper_quantile)?
+            - distribution.quantile(self.lower_quantile)?;
+
+        Some(Estimate {
+            median: distribution.quantile(0.5)?,
+            variance: (spread / 2.0).powi(2),
+        })
+    }
+
+    /// Median running time between two stations, if there's enough history, seconds.
+    pub fn median_running_secs(&self, from_stop_id: &str, to_stop_id: &str) -> Option<f64> {
+        self.estimate(
+            self.running
+                .get(&(from_stop_id.to_string(), to_stop_id.to_string())),
+        )
+        .map(|estimate| estimate.median)
+    }
+
+    /// Median dwell at a station, if there's enough history, seconds.
+    pub fn median_dwell_secs(&self, stop_id: &str) -> Option<f64> {
+        self.estimate(self.dwells.get(stop_id))
+            .map(|estimate| estimate.median)
+    }
+
+    pub fn is_enabled_for(&self, route_id: Option<&str>) -> bool {
+        match (&self.routes, route_id) {
+            (None, _) => true,
+            (Some(routes), Some(route_id)) => routes.contains(route_id),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// The prediction blended with Amtrak's estimate, if any.
+    fn blend(&self, prediction: f64, estimate: Option<i64>) -> f64 {
+        match estimate {
+            Some(estimate) => {
+                let weight = self.weight.clamp(0.0, 1.0);
+
+                weight * prediction + (1.0 - weight) * estimate as f64
+            }
+            None => prediction,
+        }
+    }
+
+    /// Predicts the stations after the last actual time of a trip update.
+    /// Returns the number of times predicted.
+    pub fn predict(&self, trip_update: &mut TripUpdate) -> usize {
+        let Some(last_actual) = trip_update
+            .stop_time_update
+            .iter()
+            .rposition(|stop| is_actual(&stop.arrival) || is_actual(&stop.departure))
+        else {
+            return 0;
+        };
+
+        let mut predicted = 0;
+
+        // Previous station, the time the train leaves it and the variance of that time
+        let mut previous: Option<(String, f64, f64)> = None;
+
+        // How far the last prediction moved Amtrak's estimate. Times without history keep Amtrak's
+        // estimate moved by as much, and no time is before the one of the previous event.
+        let mut offset = 0.0;
+        let mut latest = f64::MIN;
+
+        for stop in trip_update.stop_time_update.iter_mut().skip(last_actual) {
+            if matches!(
+                stop.schedule_relationship,
+                Some(x) if x == ScheduleRelationship::Skipped as i32 || x == ScheduleRelationship::NoData as i32
+            ) {
+                continue;
+            }
+
+            let Some(stop_id) = stop.stop_id.clone() else {
+                break;
+            };
+
+            let mut variance = previous.as_ref().map_or(0.0, |previous| previous.2);
+
+            let running = previous.as_ref().and_then(|(from_stop_id, departure, _)| {
+                self.estimate(self.running.get(&(from_stop_id.clone(), stop_id.clone())))
+                    .map(|estimate| (departure + estimate.median, estimate.variance))
+            });
+
+            let arrival = match (is_actual(&stop.arrival), running) {
+                (true, _) => {
+                    variance = 0.0;
+                    offset = 0.0;
+                    event_time(&stop.arrival).map(|time| time as f64)
+                }
+                (false, Some((prediction, running_variance))) => {
+                    variance += running_variance;
+
+                    let estimate = event_time(&stop.arrival);
+                    let time = self.blend(prediction, estimate).max(latest);
+                    offset = estimate.map_or(offset, |estimate| time - estimate as f64);
+
+                    set_time(&mut stop.arrival, time, variance);
+                    predicted += 1;
+
+                    Some(time)
+                }
+                (false, None) => {
+                    shift_estimate(&mut stop.arrival, offset, latest, variance, &mut predicted)
+                }
+            };
+
+            latest = arrival.map_or(latest, |arrival| arrival.max(latest));
+
+            let dwell = self.estimate(self.dwells.get(&stop_id));
+
+            let departure = match (is_actual(&stop.departure), arrival, dwell) {
+                (true, _, _) => {
+                    variance = 0.0;
+                    offset = 0.0;
+                    event_time(&stop.departure).map(|time| time as f64)
+                }
+                // Only stations Amtrak has a departure for are departed from
+                (false, Some(arrival), Some(dwell)) if stop.departure.is_some() => {
+                    variance += dwell.variance;
+
+                    // Trains don't leave before their scheduled departure
+                    let scheduled = stop
+                        .departure
+                        .as_ref()
+                        .and_then(|event| event.scheduled_time)
+                        .map_or(f64::MIN, |time| time as f64);
+
+                    let estimate = event_time(&stop.departure);
+                    let time = self
+                        .blend((arrival + dwell.median).max(scheduled), estimate)
+                        .max(latest);
+                    offset = estimate.map_or(offset, |estimate| time - estimate as f64);
+
+                    set_time(&mut stop.departure, time, variance);
+                    predicted += 1;
+
+                    Some(time)
+                }
+                (false, arrival, _) => shift_estimate(
+                    &mut stop.departure,
+                    offset,
+                    latest,
+                    variance,
+                    &mut predicted,
+                )
+                .or(arrival),
+            };
+
+            latest = departure.map_or(latest, |departure| departure.max(latest));
+
+            match departure {
+                Some(departure) => previous = Some((stop_id, departure, variance)),
+                // Nothing to continue from
+                None => break,
+            }
+        }
+
+        predicted
+    }
+
+    /// [`ArrivalPredictor::predict`] on every trip update of a feed on a selected route.
+    pub fn apply(&self, feed: &mut FeedMessage) {
+        for entity in feed.entity.iter_mut() {
+            let route_id = scheduled_trip(entity).and_then(|trip| trip.route_id);
+
+            if let Some(trip_update) = entity.trip_update.as_mut()
+                && self.is_enabled_for(route_id.as_deref())
+            {
+                self.predict(trip_update);
+            }
+        }
+    }
+}
+
+/// Amtrak's estimate of an event without history, moved by `offset` and not before `not_before`.
+/// `predicted` counts the estimates that moved.
+fn shift_estimate(
+    event: &mut Option<StopTimeEvent>,
+    offset: f64,
+    not_before: f64,
+    variance: f64,
+    predicted: &mut usize,
+) -> Option<f64> {
+    let estimate = event_time(event)? as f64;
+    let time = (estimate + offset).max(not_before);
+
+    if time.round() != estimate {
+        set_time(event, time, variance);
+        *predicted += 1;
+    }
+
+    Some(time)
+}
+
+/// Moves an event to a predicted time, keeping its delay consistent.
+fn set_time(event: &mut Option<StopTimeEvent>, time: f64, variance: f64) {
+    let time = time.round() as i64;
+    let event = event.get_or_insert_with(StopTimeEvent::default);
+
+    event.delay = match (event.delay, event.time) {
+        (Some(delay), Some(previous)) => i32::try_from(delay as i64 + time - previous).ok(),
+        _ => None,
+    };
+    event.time = Some(time);
+    event.uncertainty = Some((variance.sqrt().round() as i32).max(1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment_times::{DwellTime, RunningTime};
+    use crate::test_helpers::{NOW, event, feed, stop, train_entity};
+
+    fn running(from: &str, to: &str, running_secs: i64) -> SegmentObservation {
+        SegmentObservation::Running(RunningTime {
+            entity_id: "20260119-11".to_string(),
+            service_date: "20260119".to_string(),
+            route_id: Some("94".to_string()),
+            from_stop_id: from.to_string(),
+            to_stop_id: to.to_string(),
+            departure: NOW,
+            arrival: NOW + running_secs,
+            running_secs,
+            scheduled_running_secs: None,
+        })
+    }
+
+    fn dwell(stop_id: &str, dwell_secs: i64) -> SegmentObservation {
+        SegmentObservation::Dwell(DwellTime {
+            entity_id: "20260119-11".to_string(),
+            service_date: "20260119".to_string(),
+            route_id: Some("94".to_string()),
+            stop_id: stop_id.to_string(),
+            arrival: NOW,
+            departure: NOW + dwell_secs,
+            dwell_secs,
+            scheduled_dwell_secs: None,
+        })
+    }
+
+    /// SEA to PDX takes 3000 to 4000 seconds, dwells at PDX 100 to 500 seconds.
+    fn predictor() -> ArrivalPredictor {
+        let mut predictor = ArrivalPredictor::new();
+
+        for i in 0..=10 {
+            predictor.train(&running("SEA", "PDX", 3000 + i * 100));
+            predictor.train(&dwell("PDX", 100 + i * 40));
+        }
+
+        predictor
+    }
+
+    fn trip_update() -> TripUpdate {
+        TripUpdate {
+            stop_time_update: vec![
+                stop("SEA", None, event(NOW, 0, true)),
+                // Amtrak expects it 400 seconds late
+                stop(
+                    "PDX",
+                    event(NOW + 3600, 400, false),
+                    event(NOW + 3700, 400, false),
+                ),
+                stop("SAC", event(NOW + 20000, 0, false), None),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quantiles() {
+        let predictor = predictor();
+
+        assert_eq!(predictor.median_running_secs("SEA", "PDX"), Some(3500.0));
+        assert_eq!(predictor.median_dwell_secs("PDX"), Some(300.0));
+        assert_eq!(predictor.median_running_secs("PDX", "SAC"), None);
+
+        let mut distribution = Distribution::default();
+        for value in [10, 0, 30, 20] {
+            distribution.add(value);
+        }
+
+        assert_eq!(distribution.sorted, vec![0, 10, 20, 30]);
+        assert_eq!(distribution.quantile(0.5), Some(15.0));
+        assert_eq!(distribution.quantile(1.0), Some(30.0));
+        assert_eq!(Distribution::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn predicts_and_blends() {
+        let predictor = predictor();
+        let mut trip_update = trip_update();
+
+        assert_eq!(predictor.predict(&mut trip_update), 3);
+
+        let pdx = &trip_update.stop_time_update[1];
+
+        // Half way between 3500 and Amtrak's 4000
+        let arrival = pdx.arrival.unwrap();
+        assert_eq!(arrival.time, Some(NOW + 3750));
+        // The 10% to 90% quantiles span 800 seconds
+        assert_eq!(arrival.uncertainty, Some(400));
+
+        // 3750 + 300, blended with Amtrak's 4100
+        let departure = pdx.departure.unwrap();
+        assert_eq!(departure.time, Some(NOW + 4075));
+        assert_eq!(departure.uncertainty, Some(431));
+
+        // No history past PDX, Amtrak's estimate moves with the departure from PDX
+        let sac = trip_update.stop_time_update[2].arrival.unwrap();
+        assert_eq!(sac.time, Some(NOW + 19975));
+        assert_eq!(sac.uncertainty, Some(431));
+
+        // The actual time is untouched
+        assert_eq!(
+            trip_update.stop_time_update[0].departure,
+            event(NOW, 0, true)
+        );
+    }
+
+    #[test]
+    fn predictions_never_go_back_in_time() {
+        // SEA to PDX history only, no dwells
+        let mut predictor = ArrivalPredictor::new();
+
+        for i in 0..=10 {
+            predictor.train(&running("SEA", "PDX", 3000 + i * 100));
+        }
+
+        // Amtrak expects the train much earlier than the history does
+        let mut trip_update = TripUpdate {
+            stop_time_update: vec![
+                stop("SEA", None, event(NOW, 0, true)),
+                stop(
+                    "PDX",
+                    event(NOW + 1000, 0, false),
+                    event(NOW + 1100, 0, false),
+                ),
+                stop("SAC", event(NOW + 2000, 0, false), None),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(predictor.predict(&mut trip_update), 3);
+
+        let times = trip_update
+            .stop_time_update
+            .iter()
+            .flat_map(|stop| [event_time(&stop.arrival), event_time(&stop.departure)])
+            .flatten()
+            .collect::<Vec<i64>>();
+
+        // Half way between 3500 and Amtrak's 1000, then Amtrak's times 1250 seconds later
+        assert_eq!(times, vec![NOW, NOW + 2250, NOW + 2350, NOW + 3250]);
+
+        // Later estimates from Amtrak are still moved, but never before the previous time
+        let mut trip_update = TripUpdate {
+            stop_time_update: vec![
+                stop("SEA", None, event(NOW, 0, true)),
+                stop("PDX", event(NOW + 4000, 0, false), None),
+                stop("SAC", event(NOW + 3000, 0, false), None),
+            ],
+            ..Default::default()
+        };
+
+        predictor.predict(&mut trip_update);
+
+        assert_eq!(
+            event_time(&trip_update.stop_time_update[1].arrival),
+            Some(NOW + 3750)
+        );
+        assert_eq!(
+            event_time(&trip_update.stop_time_update[2].arrival),
+            Some(NOW + 3750)
+        );
+    }
+
+    #[test]
+    fn selected_routes_and_history() {
+        let mut predictor = predictor();
+        predictor.routes = Some(HashSet::from(["94".to_string()]));
+
+        let mut polled = feed(vec![gtfs_realtime::FeedEntity {
+            id: "20260119-11".to_string(),
+            trip_update: Some(trip_update()),
+            ..Default::default()
+        }]);
+
+        // No route, not selected
+        predictor.apply(&mut polled);
+        assert_eq!(polled.entity[0].trip_update, Some(trip_update()));
+
+        polled.entity[0].trip_update.as_mut().unwrap().trip.route_id = Some("94".to_string());
+        predictor.apply(&mut polled);
+        assert_ne!(
+            polled.entity[0]
+                .trip_update
+                .as_ref()
+                .unwrap()
+                .stop_time_update,
+            trip_update().stop_time_update
+        );
+
+        // Not enough history
+        predictor.min_samples = 20;
+        let mut unpredicted = trip_update();
+        assert_eq!(predictor.predict(&mut unpredicted), 0);
+    }
+
+    #[test]
+    fn trains_from_archive() {
+        let archive = crate::archive::test_helpers::temporary_archive("prediction");
+
+        let polls = [
+            vec![stop("SEA", None, event(NOW, 0, true))],
+            vec![
+                stop("SEA", None, event(NOW, 0, true)),
+                stop(
+                    "PDX",
+                    event(NOW + 3600, 0, true),
+                    event(NOW + 3700, 0, true),
+                ),
+            ],
+        ];
+
+        for (i, stops) in polls.into_iter().enumerate() {
+            let polled = feed(vec![train_entity("20260119", "11", stops)]);
+
+            let results = crate::GtfsAmtrakResults {
+                trip_updates: polled.clone(),
+                vehicle_positions: polled.clone(),
+                alerts: polled,
+            };
+
+            archive
+                .write_snapshot(
+                    (NOW + i as i64 * 3600) * 1000,
+                    &crate::AmtrakPayloads::default(),
+                    Some(&results),
+                )
+                .unwrap();
+        }
+
+        let mut predictor = ArrivalPredictor::new();
+        predictor.min_samples = 1;
+
+        assert_eq!(
+            predictor
+                .train_from_archive(&archive, NOW * 1000..(NOW + 7200) * 1000)
+                .unwrap(),
+            2
+        );
+        assert_eq!(predictor.median_running_secs("SEA", "PDX"), Some(3600.0));
+        assert_eq!(predictor.median_dwell_secs("PDX"), Some(100.0));
+
+        std::fs::remove_dir_all(archive.root()).unwrap();
+    }
+}
@@ -0,0 +1,424 @@
+//! Reconstruction of what each train actually did, for planning and ground truth.
+//!
+//! No single poll has the whole story: Track-A-Train drops trains a while after they terminate, and positions
+//! are only the latest. A [`RealizedTripTracker`] accumulates the feeds of every poll and produces one
+//! [`RealizedTrip`] per train, with scheduled and actual times at every station and the path it was seen on,
+//! as soon as the train reaches its terminal. Trains that never get there, e.g. canceled ones, are produced
+//! by [`RealizedTripTracker::end_service_day`].
+
+use crate::on_time_performance::train_number;
+use crate::trip_modifications::scheduled_trip;
+use gtfs_realtime::trip_descriptor::ScheduleRelationship as TripScheduleRelationship;
+use gtfs_realtime::trip_update::StopTimeEvent;
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_realtime::{FeedMessage, TripUpdate, VehiclePosition};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Scheduled and actual times at one station, unix seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RealizedStop {
+    pub stop_id: String,
+    pub stop_sequence: Option<u32>,
+    pub scheduled_arrival: Option<i64>,
+    pub scheduled_departure: Option<i64>,
+    pub actual_arrival: Option<i64>,
+    pub actual_departure: Option<i64>,
+    /// The station was canceled
+    pub skipped: bool,
+}
+
+/// A reported position of the train.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathPoint {
+    /// Time of the position, unix seconds
+    pub timestamp: u64,
+    pub latitude: f32,
+    pub longitude: f32,
+    /// Meters per second
+    pub speed: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RealizedTrip {
+    pub entity_id: String,
+    /// GTFS trip id, or the entity id for trains missing from the schedule
+    pub trip_id: Option<String>,
+    pub route_id: Option<String>,
+    /// `YYYYMMDD`
+    pub service_date: String,
+    pub train_number: String,
+    /// The whole trip was canceled
+    pub canceled: bool,
+    /// The train was seen arriving at its terminal
+    pub completed: bool,
+    /// In travel order
+    pub stops: Vec<RealizedStop>,
+    /// Oldest first
+    pub path: Vec<PathPoint>,
+}
+
+impl RealizedTrip {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// The train reached the last station it was still scheduled to call at.
+    fn reached_terminal(&self) -> bool {
+        self.stops
+            .iter()
+            .rev()
+            .find(|stop| !stop.skipped)
+            .is_some_and(|stop| stop.actual_arrival.is_some() || stop.actual_departure.is_some())
+    }
+
+    /// Merges the stations of a trip update into the known ones. Track-A-Train drops stations the train
+    /// already served, so stations missing from the update are kept, and new ones are inserted after the
+    /// station before them in the update.
+    pub(crate) fn update_stops(&mut self, trip_update: &TripUpdate) {
+        let actual = |event: Option<&StopTimeEvent>| {
+            event
+                .filter(|event| event.uncertainty == Some(0))
+                .and_then(|event| event.time)
+        };
+
+        let scheduled =
+            |event: Option<&StopTimeEvent>| event.and_then(|event| event.scheduled_time);
+
+        // Where the next station of the update goes
+        let mut next_index = 0;
+
+        for stop_time_update in &trip_update.stop_time_update {
+            let Some(stop_id) = &stop_time_update.stop_id else {
+                continue;
+            };
+
+            let index = match self.stops.iter().position(|stop| {
+                &stop.stop_id == stop_id && stop.stop_sequence == stop_time_update.stop_sequence
+            }) {
+                Some(index) => index,
+                None => {
+                    self.stops.insert(
+                        next_index,
+                        RealizedStop {
+                            stop_id: stop_id.clone(),
+                            stop_sequence: stop_time_update.stop_sequence,
+                            ..Default::default()
+                        },
+                    );
+
+                    next_index
+                }
+            };
+
+            let stop = &mut self.stops[index];
+            let arrival = stop_time_update.arrival.as_ref();
+            let departure = stop_time_update.departure.as_ref();
+
+            // Actual times stay known, even when a later poll leaves them out
+            stop.scheduled_arrival = scheduled(arrival).or(stop.scheduled_arrival);
+            stop.scheduled_departure = scheduled(departure).or(stop.scheduled_departure);
+            stop.actual_arrival = actual(arrival).or(stop.actual_arrival);
+            stop.actual_departure = actual(departure).or(stop.actual_departure);
+            stop.skipped = stop_time_update.schedule_relationship
+                == Some(ScheduleRelationship::Skipped as i32);
+
+            next_index = next_index.max(index + 1);
+        }
+    }
+
+    fn update_path(&mut self, vehicle: &VehiclePosition) {
+        let (Some(position), Some(timestamp)) = (vehicle.position, vehicle.timestamp) else {
+            return;
+        };
+
+        if position.latitude == 0.0 && position.longitude == 0.0 {
+            return;
+        }
+
+        // Track-A-Train repeats the same report until the train calls in again
+        if self
+            .path
+            .last()
+            .is_some_and(|last| last.timestamp >= timestamp)
+        {
+            return;
+        }
+
+        self.path.push(PathPoint {
+            timestamp,
+            latitude: position.latitude,
+            longitude: position.longitude,
+            speed: position.speed,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RealizedTripTracker {
+    /// By entity id
+    trips: BTreeMap<String, RealizedTrip>,
+    /// Trains already produced, which stay in Track-A-Train for a while after terminating
+    produced: HashSet<(String, String)>,
+}
+
+impl RealizedTripTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a poll, the unified feed or the trip updates and vehicle positions feeds, and returns the trains
+    /// that reached their terminal.
+    pub fn ingest(&mut self, feed: &FeedMessage) -> Vec<RealizedTrip> {
+        let mut completed = vec![];
+
+        for entity in &feed.entity {
+            let Some(trip) = scheduled_trip(entity) else {
+                continue;
+            };
+
+            let Some(service_date) = trip.start_date.clone() else {
+                continue;
+            };
+
+            if self
+                .produced
+                .contains(&(entity.id.clone(), service_date.clone()))
+            {
+                continue;
+            }
+
+            let realized = self
+                .trips
+                .entry(entity.id.clone())
+                .or_insert_with(|| RealizedTrip {
+                    entity_id: entity.id.clone(),
+                    train_number: train_number(&entity.id, &service_date),
+                    service_date,
+                    ..Default::default()
+                });
+
+            realized.trip_id = trip.trip_id.clone().or(realized.trip_id.take());
+            realized.route_id = trip.route_id.clone().or(realized.route_id.take());
+
+            if let Some(trip_update) = &entity.trip_update {
+                realized.canceled = trip_update.trip.schedule_relationship
+                    == Some(TripScheduleRelationship::Canceled as i32);
+
+                realized.update_stops(trip_update);
+            }
+
+            if let Some(vehicle) = &entity.vehicle {
+                realized.update_path(vehicle);
+            }
+
+            if !realized.canceled && realized.reached_terminal() {
+                completed.push(entity.id.clone());
+            }
+        }
+
+        completed
+            .into_iter()
+            .filter_map(|entity_id| self.produce(&entity_id, true))
+            .collect()
+    }
+
+    fn produce(&mut self, entity_id: &str, completed: bool) -> Option<RealizedTrip> {
+        let mut realized = self.trips.remove(entity_id)?;
+        realized.completed = completed;
+
+        self.produced
+            .insert((realized.entity_id.clone(), realized.service_date.clone()));
+
+        Some(realized)
+    }
+
+    /// Produces every train of `service_date` and earlier that hasn't reached its terminal, once no more
+    /// data is expected for them.
+    pub fn end_service_day(&mut self, service_date: &str) -> Vec<RealizedTrip> {
+        let ended = self
+            .trips
+            .values()
+            .filter(|realized| realized.service_date.as_str() <= service_date)
+            .map(|realized| realized.entity_id.clone())
+            .collect::<Vec<String>>();
+
+        let realized = ended
+            .iter()
+            .filter_map(|entity_id| self.produce(entity_id, false))
+            .collect();
+
+        // Trains of those days won't come back
+        self.produced
+            .retain(|(_, produced_date)| produced_date.as_str() > service_date);
+
+        realized
+    }
+
+    /// Trains being tracked, not produced yet.
+    pub fn in_progress(&self) -> impl Iterator<Item = &RealizedTrip> {
+        self.trips.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{NOW, event, feed, stop, train_entity, with_vehicle};
+    use gtfs_realtime::trip_update::StopTimeUpdate;
+
+    fn poll(stops: Vec<StopTimeUpdate>, latitude: f32, timestamp: u64) -> FeedMessage {
+        feed(vec![with_vehicle(
+            train_entity("20260119", "11", stops),
+            latitude,
+            timestamp,
+        )])
+    }
+
+    #[test]
+    fn produced_at_the_terminal() {
+        let mut tracker = RealizedTripTracker::new();
+
+        let first = poll(
+            vec![
+                stop("SEA", None, event(NOW - 300, 300, true)),
+                stop("PDX", event(NOW + 3300, 300, false), None),
+            ],
+            47.0,
+            NOW as u64,
+        );
+
+        assert!(tracker.ingest(&first).is_empty());
+        // Same report again
+        assert!(tracker.ingest(&first).is_empty());
+
+        // The departure from SEA isn't listed anymore
+        let arrived = poll(
+            vec![
+                stop("SEA", None, None),
+                stop("PDX", event(NOW + 3600, 300, true), None),
+            ],
+            45.5,
+            NOW as u64 + 3900,
+        );
+
+        let realized = tracker.ingest(&arrived);
+
+        assert_eq!(realized.len(), 1);
+
+        let realized = &realized[0];
+        assert!(realized.completed);
+        assert_eq!(realized.train_number, "11");
+        assert_eq!(realized.trip_id.as_deref(), Some("T11"));
+        assert_eq!(realized.stops[0].actual_departure, Some(NOW));
+        assert_eq!(realized.stops[0].scheduled_departure, Some(NOW - 300));
+        assert_eq!(realized.stops[1].actual_arrival, Some(NOW + 3900));
+        assert_eq!(realized.path.len(), 2);
+        assert_eq!(realized.path[1].latitude, 45.5);
+
+        let json = realized.to_json().unwrap();
+        assert_eq!(
+            serde_json::from_str::<RealizedTrip>(&json).unwrap(),
+            *realized
+        );
+
+        // Still listed after terminating, but only produced once
+        assert!(tracker.ingest(&arrived).is_empty());
+        assert_eq!(tracker.in_progress().count(), 0);
+    }
+
+    #[test]
+    fn stations_dropped_by_later_polls_are_kept() {
+        let mut tracker = RealizedTripTracker::new();
+
+        tracker.ingest(&poll(
+            vec![
+                stop("SEA", None, event(NOW - 300, 300, true)),
+                stop("PDX", event(NOW + 3300, 300, false), None),
+                stop("SAC", event(NOW + 6900, 300, false), None),
+            ],
+            47.0,
+            NOW as u64,
+        ));
+
+        // Past SEA, and a station the first poll didn't list
+        tracker.ingest(&poll(
+            vec![
+                stop(
+                    "PDX",
+                    event(NOW + 3600, 300, true),
+                    event(NOW + 3900, 300, true),
+                ),
+                stop("EUG", event(NOW + 5100, 300, false), None),
+                stop("SAC", event(NOW + 6900, 300, false), None),
+            ],
+            45.5,
+            NOW as u64 + 4200,
+        ));
+
+        let trip = tracker.in_progress().next().unwrap();
+
+        assert_eq!(
+            trip.stops
+                .iter()
+                .map(|stop| stop.stop_id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["SEA", "PDX", "EUG", "SAC"]
+        );
+        assert_eq!(trip.stops[0].actual_departure, Some(NOW));
+        assert_eq!(trip.stops[1].actual_arrival, Some(NOW + 3900));
+    }
+
+    #[test]
+    fn canceled_and_unfinished_trains_at_end_of_day() {
+        let mut tracker = RealizedTripTracker::new();
+
+        // Terminal canceled, the train ends at SEA's next stop
+        let mut polled = poll(
+            vec![
+                stop("SEA", None, event(NOW - 300, 300, true)),
+                stop("PDX", event(NOW + 3300, 300, false), None),
+                StopTimeUpdate {
+                    schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+                    ..stop("SAC", event(NOW + 6900, 300, false), None)
+                },
+            ],
+            47.0,
+            NOW as u64,
+        );
+
+        let mut canceled = polled.entity[0].clone();
+        canceled.id = "20260119-14".to_string();
+        let trip_update = canceled.trip_update.as_mut().unwrap();
+        trip_update.trip.schedule_relationship = Some(TripScheduleRelationship::Canceled as i32);
+        trip_update.stop_time_update.clear();
+        canceled.vehicle = None;
+
+        polled.entity.push(canceled);
+
+        assert!(tracker.ingest(&polled).is_empty());
+        assert!(tracker.end_service_day("20260118").is_empty());
+
+        let realized = tracker.end_service_day("20260119");
+
+        assert_eq!(realized.len(), 2);
+        assert!(!realized[0].completed);
+        assert!(!realized[0].canceled);
+        assert!(realized[0].stops[2].skipped);
+        assert!(realized[1].canceled);
+        assert!(realized[1].stops.is_empty());
+        assert!(realized[1].path.is_empty());
+
+        // Arriving at PDX completes the shortened trip
+        let mut tracker = RealizedTripTracker::new();
+        polled.entity[0]
+            .trip_update
+            .as_mut()
+            .unwrap()
+            .stop_time_update[1] = stop("PDX", event(NOW + 3300, 300, true), None);
+
+        let realized = tracker.ingest(&polled);
+        assert_eq!(realized.len(), 1);
+        assert!(realized[0].completed);
+    }
+}
@@ -0,0 +1,376 @@
+//! Replay of archived polls through the converter, to regression test it against real history offline.
+//!
+//! Every snapshot of an [`Archive`] is converted again from its raw payloads, and the feeds it produces can
+//! be compared with the feeds archived when the snapshot was taken. Headers are ignored, since they carry the
+//! time the feed was produced.
+//!
+//! ```no_run
+//! use amtrak_gtfs_rt::archive::Archive;
+//! use amtrak_gtfs_rt::monotonicity::RepairStrategy;
+//! use amtrak_gtfs_rt::replay::replay;
+//!
+//! let gtfs = gtfs_structures::Gtfs::from_path("amtrak.zip").unwrap();
+//! let archive = Archive::new("archive");
+//!
+//! let range = 1768780800000..1768867200000;
+//!
+//! for replayed in replay(&gtfs, &archive, range, Some(RepairStrategy::Clamp)).unwrap() {
+//!     let replayed = replayed.unwrap();
+//!
+//!     if let Some(diff) = replayed.diff.filter(|diff| !diff.is_empty()) {
+//!         println!("{}: {}", replayed.timestamp_ms, diff);
+//!     }
+//! }
+//! ```
+
+use crate::archive::{Archive, ArchiveError, Snapshot};
+use crate::engine::AmtrakFeedEngine;
+use crate::monotonicity::RepairStrategy;
+use crate::{
+    GtfsAmtrakResults, process_amtrak_payloads_joined, process_track_a_train_geojson_joined,
+    split_unified_feed,
+};
+use gtfs_realtime::{FeedEntity, FeedMessage};
+use gtfs_structures::Gtfs;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// Entities that differ between an archived feed and its replay, by entity id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedDiff {
+    /// Only in the replayed feed
+    pub added: Vec<String>,
+    /// Only in the archived feed
+    pub removed: Vec<String>,
+    /// In both feeds, with different contents
+    pub changed: Vec<String>,
+}
+
+impl FeedDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for FeedDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids = self
+            .added
+            .iter()
+            .map(|id| format!("+{}", id))
+            .chain(self.removed.iter().map(|id| format!("-{}", id)))
+            .chain(self.changed.iter().map(|id| format!("~{}", id)))
+            .collect::<Vec<String>>();
+
+        write!(f, "{}", ids.join(" "))
+    }
+}
+
+/// [`FeedDiff`] of each of the three feeds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResultsDiff {
+    pub trip_updates: FeedDiff,
+    pub vehicle_positions: FeedDiff,
+    pub alerts: FeedDiff,
+}
+
+impl ResultsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.trip_updates.is_empty() && self.vehicle_positions.is_empty() && self.alerts.is_empty()
+    }
+}
+
+impl fmt::Display for ResultsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let feeds = [
+            ("trip updates", &self.trip_updates),
+            ("vehicle positions", &self.vehicle_positions),
+            ("alerts", &self.alerts),
+        ]
+        .into_iter()
+        .filter(|(_, diff)| !diff.is_empty())
+        .map(|(name, diff)| format!("{} {}", name, diff))
+        .collect::<Vec<String>>();
+
+        write!(f, "{}", feeds.join(", "))
+    }
+}
+
+fn entities_by_id(feed: &FeedMessage) -> BTreeMap<&str, &FeedEntity> {
+    feed.entity
+        .iter()
+        .map(|entity| (entity.id.as_str(), entity))
+        .collect()
+}
+
+/// Compares the entities of two feeds, ignoring their headers.
+pub fn diff_feeds(archived: &FeedMessage, replayed: &FeedMessage) -> FeedDiff {
+    let archived = entities_by_id(archived);
+    let replayed = entities_by_id(replayed);
+
+    let mut diff = FeedDiff::default();
+
+    for (id, entity) in &replayed {
+        match archived.get(id) {
+            None => diff.added.push(id.to_string()),
+            Some(archived_entity) if archived_entity != entity => diff.changed.push(id.to_string()),
+            Some(_) => {}
+        }
+    }
+
+    diff.removed = archived
+        .keys()
+        .filter(|id| !replayed.contains_key(*id))
+        .map(|id| id.to_string())
+        .collect();
+
+    diff
+}
+
+pub fn diff_results(archived: &GtfsAmtrakResults, replayed: &GtfsAmtrakResults) -> ResultsDiff {
+    ResultsDiff {
+        trip_updates: diff_feeds(&archived.trip_updates, &replayed.trip_updates),
+        vehicle_positions: diff_feeds(&archived.vehicle_positions, &replayed.vehicle_positions),
+        alerts: diff_feeds(&archived.alerts, &replayed.alerts),
+    }
+}
+
+/// One snapshot converted again.
+#[derive(Debug, Clone)]
+pub struct ReplayedSnapshot {
+    /// Unix time of the poll, milliseconds
+    pub timestamp_ms: i64,
+    pub results: GtfsAmtrakResults,
+    /// Differences with the archived feeds, `None` when the snapshot has no feeds
+    pub diff: Option<ResultsDiff>,
+}
+
+impl ReplayedSnapshot {
+    fn new(snapshot: &Snapshot, results: GtfsAmtrakResults) -> Self {
+        ReplayedSnapshot {
+            timestamp_ms: snapshot.timestamp_ms,
+            diff: snapshot
+                .results()
+                .map(|archived| diff_results(&archived, &results)),
+            results,
+        }
+    }
+}
+
+/// Converts a snapshot again, as [`crate::fetch_amtrak_gtfs_rt`] would have at the time of the poll with the
+/// same `repair_strategy`.
+/// The Track-A-Train response decrypted when the snapshot was read is used. Without it, the encrypted response
+/// is processed and its decryption error returned.
+pub fn replay_snapshot(
+    gtfs: &Gtfs,
+    snapshot: &Snapshot,
+    repair_strategy: Option<RepairStrategy>,
+) -> Result<ReplayedSnapshot, Box<dyn std::error::Error + Sync + Send>> {
+    let payloads = snapshot.payloads().unwrap_or_default();
+
+    let joined = match (&snapshot.track_a_train_geojson, &snapshot.track_a_train) {
+        (Some(geojson), _) => process_track_a_train_geojson_joined(
+            gtfs,
+            geojson,
+            payloads.asm.as_deref(),
+            snapshot.timestamp(),
+            repair_strategy,
+        )?,
+        (None, Some(track_a_train)) => process_amtrak_payloads_joined(
+            gtfs,
+            track_a_train,
+            payloads.asm.as_deref(),
+            snapshot.timestamp(),
+            repair_strategy,
+        )?,
+        (None, None) => return Err("no Track-A-Train response archived".into()),
+    };
+
+    Ok(ReplayedSnapshot::new(
+        snapshot,
+        split_unified_feed(joined.unified_feed, payloads.advisories),
+    ))
+}
+
+/// [`replay_snapshot`] on every snapshot of the archive within `range`, unix milliseconds, oldest first.
+pub fn replay<'a>(
+    gtfs: &'a Gtfs,
+    archive: &'a Archive,
+    range: Range<i64>,
+    repair_strategy: Option<RepairStrategy>,
+) -> Result<
+    impl Iterator<Item = Result<ReplayedSnapshot, Box<dyn std::error::Error + Sync + Send>>> + 'a,
+    ArchiveError,
+> {
+    Ok(archive
+        .snapshots(range)?
+        .map(move |snapshot| replay_snapshot(gtfs, &snapshot?, repair_strategy)))
+}
+
+/// Replays the snapshots through an [`AmtrakFeedEngine`] instead, for archives of the engine's feeds.
+/// The engine keeps its state across snapshots, so the range should start where the archive does, or the
+/// first polls will differ.
+pub fn replay_with_engine<'a>(
+    engine: &'a mut AmtrakFeedEngine,
+    archive: &'a Archive,
+    range: Range<i64>,
+) -> Result<
+    impl Iterator<Item = Result<ReplayedSnapshot, Box<dyn std::error::Error + Sync + Send>>> + 'a,
+    ArchiveError,
+> {
+    Ok(archive.snapshots(range)?.map(move |snapshot| {
+        let snapshot = snapshot?;
+
+        let payloads = snapshot
+            .payloads()
+            .ok_or("no Track-A-Train response archived")?;
+
+        let results = engine.update_at(&payloads, snapshot.timestamp());
+
+        Ok(ReplayedSnapshot::new(&snapshot, results))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AmtrakPayloads;
+    use crate::archive::test_helpers::temporary_archive;
+    use crate::test_helpers::{coast_starlight_gtfs, feature, station};
+    use std::sync::Arc;
+
+    // 2026-01-19 17:00:00 UTC
+    const NOW_MS: i64 = 1_768_842_000_000;
+
+    fn geojson() -> String {
+        let train = feature(
+            "11",
+            "Coast Starlight",
+            "1/19/2026 9:52:00 AM",
+            &[
+                station("SEA", None, Some("01/19/2026 09:52:00"), ""),
+                station(
+                    "PDX",
+                    Some("01/19/2026 13:30:00"),
+                    Some("01/19/2026 13:45:00"),
+                    "",
+                ),
+                station("SAC", Some("01/20/2026 06:00:00"), None, ""),
+            ],
+        );
+
+        geojson::GeoJson::FeatureCollection(geojson::FeatureCollection {
+            bbox: None,
+            features: vec![train],
+            foreign_members: None,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn replays_and_diffs_snapshots() {
+        let gtfs = coast_starlight_gtfs();
+
+        let mut snapshot = Snapshot {
+            timestamp_ms: NOW_MS,
+            track_a_train_geojson: Some(geojson()),
+            ..Default::default()
+        };
+
+        // Nothing to compare with yet
+        let replayed = replay_snapshot(&gtfs, &snapshot, None).unwrap();
+
+        assert_eq!(replayed.timestamp_ms, NOW_MS);
+        assert_eq!(replayed.results.trip_updates.entity[0].id, "20260119-11");
+        assert!(replayed.diff.is_none());
+
+        // Archived output of an older version of the converter
+        let mut archived = replayed.results.clone();
+        archived.trip_updates.entity[0]
+            .trip_update
+            .as_mut()
+            .unwrap()
+            .trip
+            .trip_id = None;
+        archived.vehicle_positions.entity.clear();
+        archived.alerts.entity.push(FeedEntity {
+            id: "gone".to_string(),
+            ..Default::default()
+        });
+        archived.alerts.header.timestamp = Some(0);
+
+        snapshot.trip_updates = Some(archived.trip_updates);
+        snapshot.vehicle_positions = Some(archived.vehicle_positions);
+        snapshot.alerts = Some(archived.alerts);
+
+        let diff = replay_snapshot(&gtfs, &snapshot, None)
+            .unwrap()
+            .diff
+            .unwrap();
+
+        assert_eq!(diff.trip_updates.changed, vec!["20260119-11".to_string()]);
+        assert_eq!(
+            diff.vehicle_positions.added,
+            vec!["20260119-11".to_string()]
+        );
+        assert_eq!(diff.alerts.removed, vec!["gone".to_string()]);
+        assert_eq!(
+            diff.to_string(),
+            "trip updates ~20260119-11, vehicle positions +20260119-11, alerts -gone"
+        );
+
+        // Identical output, whatever the headers say
+        let diff = diff_results(
+            &replayed.results,
+            &replay_snapshot(
+                &gtfs,
+                &Snapshot {
+                    timestamp_ms: NOW_MS + 30_000,
+                    ..snapshot.clone()
+                },
+                None,
+            )
+            .unwrap()
+            .results,
+        );
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn replays_archives() {
+        let gtfs = coast_starlight_gtfs();
+        let archive = temporary_archive("replay");
+
+        let payloads = AmtrakPayloads {
+            track_a_train: "not encrypted".to_string(),
+            ..Default::default()
+        };
+
+        archive.write_snapshot(NOW_MS, &payloads, None).unwrap();
+        archive
+            .write_snapshot(NOW_MS + 30_000, &payloads, None)
+            .unwrap();
+
+        // The stateless converter can't decrypt the response
+        let replayed = replay(&gtfs, &archive, NOW_MS..NOW_MS + 60_000, None)
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(replayed.len(), 2);
+        assert!(replayed.iter().all(|replayed| replayed.is_err()));
+
+        // The engine treats it as an empty poll
+        let mut engine = AmtrakFeedEngine::new(Arc::new(gtfs));
+
+        let replayed = replay_with_engine(&mut engine, &archive, NOW_MS..NOW_MS + 60_000)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(replayed[1].timestamp_ms, NOW_MS + 30_000);
+        assert!(replayed[1].results.trip_updates.entity.is_empty());
+
+        std::fs::remove_dir_all(archive.root()).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+//! Lookups into the static schedule shared by the realtime conversion.
+
+use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use gtfs_structures::{Exception, Gtfs, Trip};
+
+/// Whether a service runs on a date, taking `calendar_dates.txt` exceptions into account.
+pub fn service_runs_on_date(gtfs: &Gtfs, service_id: &str, date: NaiveDate) -> bool {
+    if let Some(exception) = gtfs
+        .calendar_dates
+        .get(service_id)
+        .and_then(|dates| dates.iter().find(|x| x.date == date))
+    {
+        return exception.exception_type == Exception::Added;
+    }
+
+    match gtfs.calendar.get(service_id) {
+        Some(calendar) => {
+            date >= calendar.start_date && date <= calendar.end_date && calendar.valid_weekday(date)
+        }
+        None => false,
+    }
+}
+
+pub fn trip_runs_on_date(gtfs: &Gtfs, trip: &Trip, date: NaiveDate) -> bool {
+    service_runs_on_date(gtfs, &trip.service_id, date)
+}
+
+/// Every trip whose `trip_short_name` is the train number.
+pub fn trips_for_train_number<'a>(gtfs: &'a Gtfs, train_number: &str) -> Vec<&'a Trip> {
+    let mut trips = gtfs
+        .trips
+        .values()
+        .filter(|trip| trip.trip_short_name.as_deref() == Some(train_number))
+        .collect::<Vec<&Trip>>();
+
+    // HashMap order isn't stable, keep results deterministic
+    trips.sort_by(|a, b| a.id.cmp(&b.id));
+
+    trips
+}
+
+/// The trip operated by a train number on a service date, if the schedule has exactly one candidate running that day.
+pub fn scheduled_trip_for_train<'a>(
+    gtfs: &'a Gtfs,
+    train_number: &str,
+    service_date: NaiveDate,
+) -> Option<&'a Trip> {
+    let running = trips_for_train_number(gtfs, train_number)
+        .into_iter()
+        .filter(|trip| trip_runs_on_date(gtfs, trip, service_date))
+        .collect::<Vec<&Trip>>();
+
+    match running.len() {
+        1 => Some(running[0]),
+        _ => None,
+    }
+}
+
+/// Matches station codes, in travel order, to the `stop_sequence` of the trip's stop times.
+///
+/// Matching only moves forward, so a station served twice (e.g. a loop) gets the right sequence for each visit.
+/// Codes that aren't part of the trip are `None`.
+pub fn stop_sequences_for_codes(trip: &Trip, codes: &[&str]) -> Vec<Option<u32>> {
+    let mut next_index = 0;
+
+    codes
+        .iter()
+        .map(|code| {
+            let found = trip.stop_times[next_index..]
+                .iter()
+                .position(|stop_time| stop_time.stop.id == *code);
+
+            found.map(|offset| {
+                next_index += offset + 1;
+                trip.stop_times[next_index - 1].stop_sequence
+            })
+        })
+        .collect()
+}
+
+/// Unix time of a stop time `seconds` into a service date.
+///
+/// GTFS times count from noon minus 12 hours in the agency's timezone, which is midnight except on the days
+/// daylight saving time starts or ends.
+pub fn scheduled_unix_time(service_date: NaiveDate, seconds: u32, agency_tz: Tz) -> Option<i64> {
+    let noon = agency_tz
+        .from_local_datetime(&service_date.and_hms_opt(12, 0, 0)?)
+        .single()?;
+
+    Some(noon.timestamp() - 12 * 3600 + seconds as i64)
+}
+
+/// Whether the trip arrives at or departs from a station at unix time `time` on a service date, to the minute.
+pub fn trip_scheduled_at(
+    trip: &Trip,
+    service_date: NaiveDate,
+    agency_tz: Tz,
+    station_code: &str,
+    time: i64,
+) -> bool {
+    trip.stop_times
+        .iter()
+        .filter(|stop_time| stop_time.stop.id == station_code)
+        .flat_map(|stop_time| [stop_time.arrival_time, stop_time.departure_time])
+        .flatten()
+        .filter_map(|seconds| scheduled_unix_time(service_date, seconds, agency_tz))
+        .any(|scheduled| (scheduled - time).abs() < 60)
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use chrono::NaiveDate;
+    use gtfs_structures::{Calendar, CalendarDate, Exception, Gtfs, Stop, StopTime, Trip};
+    use std::sync::Arc;
+
+    pub fn daily_calendar(id: &str, start: NaiveDate, end: NaiveDate) -> Calendar {
+        Calendar {
+            id: id.to_string(),
+            monday: true,
+            tuesday: true,
+            wednesday: true,
+            thursday: true,
+            friday: true,
+            saturday: true,
+            sunday: true,
+            start_date: start,
+            end_date: end,
+        }
+    }
+
+    pub fn trip(id: &str, service_id: &str, route_id: &str, train_number: &str) -> Trip {
+        Trip {
+            id: id.to_string(),
+            service_id: service_id.to_string(),
+            route_id: route_id.to_string(),
+            trip_short_name: Some(train_number.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Stop times at the given stations, arrival and departure in seconds after midnight
+    pub fn stop_times(stops: &[(&str, u32, u32)]) -> Vec<StopTime> {
+        stops
+            .iter()
+            .enumerate()
+            .map(|(i, (code, arrival, departure))| StopTime {
+                stop: Arc::new(Stop {
+                    id: code.to_string(),
+                    name: Some(format!("{} Station", code)),
+                    ..Default::default()
+                }),
+                arrival_time: Some(*arrival),
+                departure_time: Some(*departure),
+                stop_sequence: (i as u32 + 1) * 10,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub fn removed_date(service_id: &str, date: NaiveDate) -> CalendarDate {
+        CalendarDate {
+            service_id: service_id.to_string(),
+            date,
+            exception_type: Exception::Deleted,
+        }
+    }
+
+    /// Train 11 runs daily during January 2026 except on the 20th, times are Pacific
+    pub fn january_gtfs() -> Gtfs {
+        let start = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2026, 1, 31).unwrap();
+
+        let mut gtfs = Gtfs::default();
+
+        gtfs.agencies.push(gtfs_structures::Agency {
+            timezone: "America/Los_Angeles".to_string(),
+            ..Default::default()
+        });
+
+        gtfs.calendar
+            .insert("JAN".to_string(), daily_calendar("JAN", start, end));
+        gtfs.calendar_dates.insert(
+            "JAN".to_string(),
+            vec![removed_date(
+                "JAN",
+                NaiveDate::from_ymd_opt(2026, 1, 20).unwrap(),
+            )],
+        );
+        let mut t11 = trip("T11", "JAN", "94", "11");
+        t11.stop_times = stop_times(&[
+            ("SEA", 35520, 35520),
+            ("PDX", 48600, 49500),
+            ("SAC", 108000, 108900),
+            ("LAX", 162000, 162000),
+        ]);
+
+        gtfs.trips.insert("T11".to_string(), t11);
+
+        gtfs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_helpers::january_gtfs;
+    use super::*;
+
+    #[test]
+    fn calendar_and_exceptions() {
+        let gtfs = january_gtfs();
+
+        let date = |d| NaiveDate::from_ymd_opt(2026, 1, d).unwrap();
+
+        assert!(service_runs_on_date(&gtfs, "JAN", date(19)));
+        assert!(!service_runs_on_date(&gtfs, "JAN", date(20)));
+        assert!(!service_runs_on_date(
+            &gtfs,
+            "JAN",
+            NaiveDate::from_ymd_opt(2026, 2, 1).unwrap()
+        ));
+        assert!(!service_runs_on_date(&gtfs, "UNKNOWN", date(19)));
+
+        assert_eq!(
+            scheduled_trip_for_train(&gtfs, "11", date(19)).map(|x| x.id.as_str()),
+            Some("T11")
+        );
+        assert!(scheduled_trip_for_train(&gtfs, "11", date(20)).is_none());
+        assert!(scheduled_trip_for_train(&gtfs, "14", date(19)).is_none());
+    }
+
+    #[test]
+    fn stop_sequences() {
+        let gtfs = january_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+
+        assert_eq!(
+            stop_sequences_for_codes(trip, &["SEA", "XYZ", "SAC", "LAX"]),
+            vec![Some(10), None, Some(30), Some(40)]
+        );
+
+        // Out of order stations don't match backwards
+        assert_eq!(
+            stop_sequences_for_codes(trip, &["SAC", "PDX"]),
+            vec![Some(30), None]
+        );
+    }
+
+    #[test]
+    fn scheduled_times() {
+        let trip = january_gtfs().trips.remove("T11").unwrap();
+        let date = NaiveDate::from_ymd_opt(2026, 1, 19).unwrap();
+        let pacific = chrono_tz::America::Los_Angeles;
+
+        // 2026-01-19 09:52 and 2026-01-20 06:00 Pacific
+        assert_eq!(scheduled_unix_time(date, 35520, pacific), Some(1768845120));
+        assert!(trip_scheduled_at(&trip, date, pacific, "SEA", 1768845120));
+        assert!(trip_scheduled_at(&trip, date, pacific, "SAC", 1768917600));
+
+        // Another station, day or timezone
+        assert!(!trip_scheduled_at(&trip, date, pacific, "PDX", 1768845120));
+        assert!(!trip_scheduled_at(
+            &trip,
+            date.succ_opt().unwrap(),
+            pacific,
+            "SEA",
+            1768845120
+        ));
+        assert!(!trip_scheduled_at(
+            &trip,
+            date,
+            chrono_tz::America::New_York,
+            "SEA",
+            1768845120
+        ));
+
+        // Noon minus 12 hours is 23:00 the day before on the day daylight saving time starts
+        let spring_forward = NaiveDate::from_ymd_opt(2026, 3, 8).unwrap();
+        assert_eq!(
+            scheduled_unix_time(spring_forward, 0, pacific),
+            Some(1772953200)
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Running times between stations and dwell times at stations, measured from actual times.
+//!
+//! A [`SegmentTimeObserver`] is given the feed of every poll and emits each measurement once, as soon as both
+//! of its actual times are known: a dwell once the train left a station it was seen arriving at, and a
+//! running time once it arrived at the next station it called at. Segments are keyed by GTFS stop ids, and
+//! come with their scheduled duration so they can be compared or fed to a prediction model.
+
+use crate::realized_trips::{RealizedStop, RealizedTrip};
+use crate::trip_modifications::scheduled_trip;
+use gtfs_realtime::FeedMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Time between the departure from a station and the arrival at the next one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunningTime {
+    pub entity_id: String,
+    /// `YYYYMMDD`
+    pub service_date: String,
+    pub route_id: Option<String>,
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    /// Actual departure from `from_stop_id`, unix seconds
+    pub departure: i64,
+    /// Actual arrival at `to_stop_id`, unix seconds
+    pub arrival: i64,
+    pub running_secs: i64,
+    pub scheduled_running_secs: Option<i64>,
+}
+
+/// Time between the arrival at a station and the departure from it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DwellTime {
+    pub entity_id: String,
+    /// `YYYYMMDD`
+    pub service_date: String,
+    pub route_id: Option<String>,
+    pub stop_id: String,
+    /// Actual arrival, unix seconds
+    pub arrival: i64,
+    /// Actual departure, unix seconds
+    pub departure: i64,
+    pub dwell_secs: i64,
+    pub scheduled_dwell_secs: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SegmentObservation {
+    Running(RunningTime),
+    Dwell(DwellTime),
+}
+
+/// A station of a trip, by stop id and stop sequence, since Track-A-Train lists can change between polls.
+type StationKey = (String, Option<u32>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Measurement {
+    Dwell(StationKey),
+    Running(StationKey, StationKey),
+}
+
+fn station_key(stop: &RealizedStop) -> StationKey {
+    (stop.stop_id.clone(), stop.stop_sequence)
+}
+
+fn scheduled_difference(from: Option<i64>, to: Option<i64>) -> Option<i64> {
+    Some(to? - from?)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SegmentTimeObserver {
+    /// By entity id
+    trips: BTreeMap<String, RealizedTrip>,
+    /// Measurements already emitted, by entity id
+    emitted: BTreeMap<String, HashSet<Measurement>>,
+}
+
+impl SegmentTimeObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the trip updates of a poll, and returns the measurements that became known.
+    pub fn observe(&mut self, feed: &FeedMessage) -> Vec<SegmentObservation> {
+        let mut observations = vec![];
+
+        for entity in &feed.entity {
+            let Some(trip_update) = &entity.trip_update else {
+                continue;
+            };
+
+            let Some(scheduled_trip) = scheduled_trip(entity) else {
+                continue;
+            };
+
+            let Some(service_date) = scheduled_trip.start_date else {
+                continue;
+            };
+
+            let trip = self
+                .trips
+                .entry(entity.id.clone())
+                .or_insert_with(|| RealizedTrip {
+                    entity_id: entity.id.clone(),
+                    service_date,
+                    ..Default::default()
+                });
+
+            trip.route_id = scheduled_trip.route_id.or(trip.route_id.take());
+            trip.update_stops(trip_update);
+
+            let emitted = self.emitted.entry(entity.id.clone()).or_default();
+
+            observations.extend(measurements(trip).into_iter().filter_map(
+                |(measurement, observation)| emitted.insert(measurement).then_some(observation),
+            ));
+        }
+
+        observations
+    }
+
+    /// Forgets the trains of `service_date` and earlier.
+    pub fn end_service_day(&mut self, service_date: &str) {
+        let ended = self
+            .trips
+            .values()
+            .filter(|trip| trip.service_date.as_str() <= service_date)
+            .map(|trip| trip.entity_id.clone())
+            .collect::<Vec<String>>();
+
+        for entity_id in ended {
+            self.trips.remove(&entity_id);
+            self.emitted.remove(&entity_id);
+        }
+    }
+}
+
+/// Every measurement the actual times of a trip allow.
+fn measurements(trip: &RealizedTrip) -> Vec<(Measurement, SegmentObservation)> {
+    let mut measurements = vec![];
+
+    for stop in &trip.stops {
+        if let (Some(arrival), Some(departure)) = (stop.actual_arrival, stop.actual_departure) {
+            measurements.push((
+                Measurement::Dwell(station_key(stop)),
+                SegmentObservation::Dwell(DwellTime {
+                    entity_id: trip.entity_id.clone(),
+                    service_date: trip.service_date.clone(),
+                    route_id: trip.route_id.clone(),
+                    stop_id: stop.stop_id.clone(),
+                    arrival,
+                    departure,
+                    dwell_secs: departure - arrival,
+                    scheduled_dwell_secs: scheduled_difference(
+                        stop.scheduled_arrival,
+                        stop.scheduled_departure,
+                    ),
+                }),
+            ));
+        }
+    }
+
+    // Consecutive stations the train called at
+    let called_at = trip
+        .stops
+        .iter()
+        .filter(|stop| !stop.skipped)
+        .collect::<Vec<&RealizedStop>>();
+
+    for pair in called_at.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+
+        if let (Some(departure), Some(arrival)) = (from.actual_departure, to.actual_arrival) {
+            measurements.push((
+                Measurement::Running(station_key(from), station_key(to)),
+                SegmentObservation::Running(RunningTime {
+                    entity_id: trip.entity_id.clone(),
+                    service_date: trip.service_date.clone(),
+                    route_id: trip.route_id.clone(),
+                    from_stop_id: from.stop_id.clone(),
+                    to_stop_id: to.stop_id.clone(),
+                    departure,
+                    arrival,
+                    running_secs: arrival - departure,
+                    scheduled_running_secs: scheduled_difference(
+                        from.scheduled_departure,
+                        to.scheduled_arrival,
+                    ),
+                }),
+            ));
+        }
+    }
+
+    measurements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{NOW, event, feed, stop, train_entity};
+    use gtfs_realtime::trip_update::StopTimeUpdate;
+    use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+
+    fn poll(stops: Vec<StopTimeUpdate>) -> FeedMessage {
+        feed(vec![train_entity("20260119", "11", stops)])
+    }
+
+    #[test]
+    fn emits_each_measurement_once() {
+        let mut observer = SegmentTimeObserver::new();
+
+        let observations = observer.observe(&poll(vec![
+            stop("SEA", None, event(NOW, 300, true)),
+            stop(
+                "PDX",
+                event(NOW + 3600, 300, false),
+                event(NOW + 4500, 300, false),
+            ),
+        ]));
+        assert!(observations.is_empty());
+
+        // Arrived at PDX
+        let arrived = stop(
+            "PDX",
+            event(NOW + 3600, 300, true),
+            event(NOW + 4500, 300, false),
+        );
+
+        let observations = observer.observe(&poll(vec![stop("SEA", None, None), arrived.clone()]));
+
+        assert_eq!(
+            observations,
+            vec![SegmentObservation::Running(RunningTime {
+                entity_id: "20260119-11".to_string(),
+                service_date: "20260119".to_string(),
+                route_id: Some("94".to_string()),
+                from_stop_id: "SEA".to_string(),
+                to_stop_id: "PDX".to_string(),
+                departure: NOW + 300,
+                arrival: NOW + 3900,
+                running_secs: 3600,
+                scheduled_running_secs: Some(3600),
+            })]
+        );
+
+        // Nothing new
+        assert!(observer.observe(&poll(vec![arrived])).is_empty());
+
+        // Left PDX
+        let observations = observer.observe(&poll(vec![stop(
+            "PDX",
+            event(NOW + 3600, 300, true),
+            event(NOW + 4500, 300, true),
+        )]));
+
+        match &observations[..] {
+            [SegmentObservation::Dwell(dwell)] => {
+                assert_eq!(dwell.stop_id, "PDX");
+                assert_eq!(dwell.dwell_secs, 900);
+                assert_eq!(dwell.scheduled_dwell_secs, Some(900));
+            }
+            _ => panic!("expected a dwell, got {:?}", observations),
+        }
+
+        observer.end_service_day("20260119");
+        assert!(observer.trips.is_empty());
+    }
+
+    #[test]
+    fn running_times_from_stations_no_longer_listed() {
+        let mut observer = SegmentTimeObserver::new();
+
+        observer.observe(&poll(vec![
+            stop("SEA", None, event(NOW, 300, true)),
+            stop("PDX", event(NOW + 3600, 300, false), None),
+        ]));
+
+        // Track-A-Train dropped SEA by the time the train arrived at PDX
+        let observations =
+            observer.observe(&poll(vec![stop("PDX", event(NOW + 3600, 300, true), None)]));
+
+        match &observations[..] {
+            [SegmentObservation::Running(running)] => {
+                assert_eq!(running.from_stop_id, "SEA");
+                assert_eq!(running.to_stop_id, "PDX");
+                assert_eq!(running.running_secs, 3600);
+            }
+            _ => panic!("expected a running time, got {:?}", observations),
+        }
+    }
+
+    #[test]
+    fn skipped_stations_are_not_segment_ends() {
+        let mut observer = SegmentTimeObserver::new();
+
+        let observations = observer.observe(&poll(vec![
+            stop("SEA", None, event(NOW, 300, true)),
+            StopTimeUpdate {
+                schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+                ..stop(
+                    "TAC",
+                    event(NOW + 1800, 300, false),
+                    event(NOW + 1900, 300, false),
+                )
+            },
+            stop("PDX", event(NOW + 3600, 300, true), None),
+        ]));
+
+        match &observations[..] {
+            [SegmentObservation::Running(running)] => {
+                assert_eq!(running.from_stop_id, "SEA");
+                assert_eq!(running.to_stop_id, "PDX");
+            }
+            _ => panic!("expected a running time, got {:?}", observations),
+        }
+    }
+}
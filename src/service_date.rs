@@ -0,0 +1,195 @@
+//! GTFS service dates of Amtrak trains.
+//!
+//! GTFS schedules a trip on a service date in the agency's timezone, with times past `24:00:00` for trips
+//! departing after midnight. Track-A-Train only gives the origin's scheduled departure in local time, so the
+//! service date is that departure in the agency's timezone, minus the days the trip's first departure is
+//! offset by in the schedule.
+//!
+//! Track-A-Train sometimes reports a train running days late with the origin departure of the current day.
+//! Delays close to whole days move the service date back, and the result is checked against the days the
+//! matched trip actually runs.
+
+use crate::schedule::trip_runs_on_date;
+use chrono::{DateTime, Duration, NaiveDate};
+use chrono_tz::Tz;
+use gtfs_structures::{Gtfs, Trip};
+
+/// Trains are never considered more than this many days late.
+pub const MAX_DAYS_OFFSET: i64 = 3;
+
+/// How far a delay can be from a whole number of days and still be treated as a date mix-up.
+const DAY_TOLERANCE_SECS: i64 = 2 * 3600;
+
+/// Whole days the service date should move by, given the delay at a stop in seconds.
+///
+/// A delay of about +24 hours means the train is yesterday's, so this returns -1.
+/// Returns 0 for delays that aren't close to a whole number of days.
+pub fn day_offset_from_delay(delay_secs: i64) -> i64 {
+    let days = (delay_secs as f64 / 86400.0).round() as i64;
+
+    if days == 0 || days.abs() > MAX_DAYS_OFFSET {
+        return 0;
+    }
+
+    match (delay_secs - days * 86400).abs() < DAY_TOLERANCE_SECS {
+        true => -days,
+        false => 0,
+    }
+}
+
+/// Service date of a trip departing its origin at `origin_departure`, before any delay correction.
+///
+/// When the trip is known, its first scheduled departure past midnight moves the date back.
+pub fn base_service_date(
+    origin_departure: &DateTime<Tz>,
+    agency_tz: Tz,
+    trip: Option<&Trip>,
+) -> NaiveDate {
+    let date = origin_departure.with_timezone(&agency_tz).date_naive();
+
+    let first_departure = trip
+        .and_then(|trip| trip.stop_times.first())
+        .and_then(|stop_time| stop_time.departure_time.or(stop_time.arrival_time));
+
+    match first_departure {
+        Some(first_departure) => date - Duration::days((first_departure / 86400) as i64),
+        None => date,
+    }
+}
+
+/// Returns `date` if the trip runs on it, otherwise the closest date it runs on within [`MAX_DAYS_OFFSET`].
+/// Earlier dates win ties, since trains run late far more often than early.
+/// Falls back to `date` when the trip doesn't run on any of them.
+pub fn validate_service_date(gtfs: &Gtfs, trip: &Trip, date: NaiveDate) -> NaiveDate {
+    if trip_runs_on_date(gtfs, trip, date) {
+        return date;
+    }
+
+    (1..=MAX_DAYS_OFFSET)
+        .flat_map(|days| [date - Duration::days(days), date + Duration::days(days)])
+        .find(|candidate| trip_runs_on_date(gtfs, trip, *candidate))
+        .unwrap_or(date)
+}
+
+/// Service date of a train, `day_offset` coming from [`day_offset_from_delay`].
+pub fn service_date(
+    gtfs: &Gtfs,
+    trip: Option<&Trip>,
+    origin_departure: &DateTime<Tz>,
+    agency_tz: Tz,
+    day_offset: i64,
+) -> NaiveDate {
+    let date = base_service_date(origin_departure, agency_tz, trip) + Duration::days(day_offset);
+
+    match trip {
+        Some(trip) => validate_service_date(gtfs, trip, date),
+        None => date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::test_helpers::{january_gtfs, stop_times, trip};
+    use chrono::TimeZone;
+
+    const NEW_YORK: Tz = chrono_tz::America::New_York;
+    const LOS_ANGELES: Tz = chrono_tz::America::Los_Angeles;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    fn pacific(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        LOS_ANGELES
+            .with_ymd_and_hms(2026, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn delays_close_to_whole_days() {
+        assert_eq!(day_offset_from_delay(0), 0);
+        assert_eq!(day_offset_from_delay(5 * 3600), 0);
+        assert_eq!(day_offset_from_delay(23 * 3600), -1);
+        assert_eq!(day_offset_from_delay(25 * 3600 + 59 * 60), -1);
+        // Halfway between days is a real delay
+        assert_eq!(day_offset_from_delay(36 * 3600), 0);
+        assert_eq!(day_offset_from_delay(48 * 3600 + 600), -2);
+        assert_eq!(day_offset_from_delay(-24 * 3600), 1);
+        assert_eq!(day_offset_from_delay(10 * 86400), 0);
+    }
+
+    #[test]
+    fn evening_departures_out_west() {
+        let gtfs = january_gtfs();
+
+        // 21:30 Pacific is already the next day in New York
+        let departure = pacific(1, 18, 21, 30);
+
+        assert_eq!(base_service_date(&departure, NEW_YORK, None), date(1, 19));
+
+        // The schedule writes that departure as 24:30:00 on the previous service date
+        let mut late_trip = trip("T1", "JAN", "94", "1");
+        late_trip.stop_times = stop_times(&[("LAX", 88200, 88200), ("SAC", 120000, 120000)]);
+
+        assert_eq!(
+            service_date(&gtfs, Some(&late_trip), &departure, NEW_YORK, 0),
+            date(1, 18)
+        );
+    }
+
+    #[test]
+    fn year_boundary() {
+        let departure = LOS_ANGELES
+            .with_ymd_and_hms(2025, 12, 31, 23, 30, 0)
+            .unwrap();
+
+        let mut trip = trip("T1", "JAN", "94", "1");
+        trip.stop_times = stop_times(&[("LAX", 95400, 95400)]);
+
+        assert_eq!(
+            base_service_date(&departure, NEW_YORK, Some(&trip)),
+            NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn days_late_and_validation() {
+        let gtfs = january_gtfs();
+        let t11 = gtfs.trips.get("T11").unwrap();
+
+        let departure = pacific(1, 23, 9, 52);
+
+        assert_eq!(
+            service_date(&gtfs, Some(t11), &departure, NEW_YORK, 0),
+            date(1, 23)
+        );
+
+        // Reported with today's origin departure while two days late
+        assert_eq!(
+            service_date(&gtfs, Some(t11), &departure, NEW_YORK, -2),
+            date(1, 21)
+        );
+
+        // Train 11 doesn't run on January 20, the previous day is the closest it does
+        let departure = pacific(1, 20, 9, 52);
+
+        assert_eq!(
+            service_date(&gtfs, Some(t11), &departure, NEW_YORK, 0),
+            date(1, 19)
+        );
+
+        // Trains without a schedule keep the computed date
+        assert_eq!(
+            service_date(&gtfs, None, &departure, NEW_YORK, 0),
+            date(1, 20)
+        );
+
+        // Nothing runs in the first days of March, keep the computed date
+        let departure = pacific(3, 5, 9, 52);
+        assert_eq!(
+            service_date(&gtfs, Some(t11), &departure, NEW_YORK, 0),
+            date(3, 5)
+        );
+    }
+}
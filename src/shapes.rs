@@ -0,0 +1,234 @@
+//! Geometry helpers for GTFS shapes.
+//!
+//! Points are `(latitude, longitude)` in degrees, distances are meters.
+//! Projections use a local equirectangular approximation per segment, which is precise enough
+//! at the scale of the gaps between shape points.
+
+use gtfs_structures::Gtfs;
+
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Points of a GTFS shape, ordered by `shape_pt_sequence`.
+pub fn shape_points(gtfs: &Gtfs, shape_id: &str) -> Option<Vec<(f64, f64)>> {
+    let mut shape = gtfs.shapes.get(shape_id)?.clone();
+
+    shape.sort_by_key(|point| point.sequence);
+
+    Some(
+        shape
+            .iter()
+            .map(|point| (point.latitude, point.longitude))
+            .collect(),
+    )
+}
+
+/// Great circle distance between two points.
+pub fn haversine_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+/// Initial bearing from `a` to `b`, degrees clockwise from north in `[0, 360)`.
+pub fn bearing_deg(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+
+    let y = (lon2 - lon1).sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * (lon2 - lon1).cos();
+
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Distance from the start of a polyline at each of its points.
+pub fn cumulative_distances(points: &[(f64, f64)]) -> Vec<f64> {
+    let mut total = 0.0;
+
+    let mut distances = Vec::with_capacity(points.len());
+
+    for (i, point) in points.iter().enumerate() {
+        if i > 0 {
+            total += haversine_m(points[i - 1], *point);
+        }
+
+        distances.push(total);
+    }
+
+    distances
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// Closest point on the polyline
+    pub point: (f64, f64),
+    /// Index of the segment the point lies on, from `points[segment]` to `points[segment + 1]`
+    pub segment: usize,
+    /// Distance from the start of the polyline to the projected point
+    pub distance_along_m: f64,
+    /// Distance between the original point and the polyline
+    pub distance_from_shape_m: f64,
+}
+
+/// Projects a point onto the closest segment of a polyline.
+/// Returns `None` for polylines with fewer than two points.
+pub fn project_onto_polyline(points: &[(f64, f64)], point: (f64, f64)) -> Option<Projection> {
+    project_onto_measured_polyline(points, &cumulative_distances(points), point)
+}
+
+/// [`project_onto_polyline`] with the polyline's [`cumulative_distances`] already known.
+pub fn project_onto_measured_polyline(
+    points: &[(f64, f64)],
+    distances: &[f64],
+    point: (f64, f64),
+) -> Option<Projection> {
+    if points.len() < 2 || distances.len() != points.len() {
+        return None;
+    }
+
+    let mut best: Option<Projection> = None;
+
+    for segment in 0..points.len() - 1 {
+        let (a, b) = (points[segment], points[segment + 1]);
+
+        // Local planar coordinates around the segment start, in degrees of latitude
+        let scale = a.0.to_radians().cos();
+        let (bx, by) = ((b.1 - a.1) * scale, b.0 - a.0);
+        let (px, py) = ((point.1 - a.1) * scale, point.0 - a.0);
+
+        let length_squared = bx * bx + by * by;
+
+        let t = match length_squared > 0.0 {
+            true => ((px * bx + py * by) / length_squared).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+
+        let projected = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+
+        let candidate = Projection {
+            point: projected,
+            segment,
+            distance_along_m: distances[segment]
+                + (distances[segment + 1] - distances[segment]) * t,
+            distance_from_shape_m: haversine_m(point, projected),
+        };
+
+        if best
+            .map(|best| candidate.distance_from_shape_m < best.distance_from_shape_m)
+            .unwrap_or(true)
+        {
+            best = Some(candidate);
+        }
+    }
+
+    best
+}
+
+/// Point at a given distance along a polyline, clamped to its ends.
+pub fn point_at_distance(points: &[(f64, f64)], distance_m: f64) -> Option<(f64, f64)> {
+    point_at_measured_distance(points, &cumulative_distances(points), distance_m)
+}
+
+/// [`point_at_distance`] with the polyline's [`cumulative_distances`] already known.
+pub fn point_at_measured_distance(
+    points: &[(f64, f64)],
+    distances: &[f64],
+    distance_m: f64,
+) -> Option<(f64, f64)> {
+    if distances.len() != points.len() {
+        return None;
+    }
+
+    let total = *distances.last()?;
+
+    if distance_m <= 0.0 || points.len() == 1 {
+        return points.first().copied();
+    }
+
+    if distance_m >= total {
+        return points.last().copied();
+    }
+
+    let segment = distances.iter().rposition(|d| *d <= distance_m)?;
+
+    let (a, b) = (points[segment], points[segment + 1]);
+    let length = distances[segment + 1] - distances[segment];
+
+    let t = match length > 0.0 {
+        true => (distance_m - distances[segment]) / length,
+        false => 0.0,
+    };
+
+    Some((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t))
+}
+
+/// Encodes points with Google's encoded polyline algorithm at precision 5, as used by GTFS-rt `Shape`.
+pub fn encode_polyline(points: &[(f64, f64)]) -> String {
+    let mut encoded = String::new();
+
+    let mut previous = (0i64, 0i64);
+
+    for point in points {
+        let current = (
+            (point.0 * 1e5).round() as i64,
+            (point.1 * 1e5).round() as i64,
+        );
+
+        encode_value(current.0 - previous.0, &mut encoded);
+        encode_value(current.1 - previous.1, &mut encoded);
+
+        previous = current;
+    }
+
+    encoded
+}
+
+fn encode_value(value: i64, encoded: &mut String) {
+    let mut value = match value < 0 {
+        true => !(value << 1),
+        false => value << 1,
+    };
+
+    while value >= 0x20 {
+        encoded.push((((0x20 | (value & 0x1f)) + 63) as u8) as char);
+        value >>= 5;
+    }
+
+    encoded.push(((value + 63) as u8) as char);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polyline_encoding() {
+        // Example from Google's documentation
+        assert_eq!(
+            encode_polyline(&[(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)]),
+            "_p~iF~ps|U_ulLnnqC_mqNvxq`@"
+        );
+    }
+
+    #[test]
+    fn projection() {
+        let line = [(45.0, -122.0), (45.0, -121.0), (46.0, -121.0)];
+
+        let projection = project_onto_polyline(&line, (45.01, -121.5)).unwrap();
+
+        assert_eq!(projection.segment, 0);
+        assert!((projection.point.0 - 45.0).abs() < 1e-9);
+        assert!((projection.point.1 + 121.5).abs() < 1e-9);
+        assert!((projection.distance_from_shape_m - 1112.0).abs() < 5.0);
+        assert!((projection.distance_along_m - haversine_m(line[0], (45.0, -121.5))).abs() < 100.0);
+
+        let end = point_at_distance(&line, 1e9).unwrap();
+        assert_eq!(end, (46.0, -121.0));
+
+        assert!((bearing_deg(line[1], line[2]) - 0.0).abs() < 1e-6);
+        assert!((bearing_deg(line[0], line[1]) - 90.0).abs() < 1.0);
+    }
+}
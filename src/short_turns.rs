@@ -0,0 +1,253 @@
+//! Trains that terminate short of their destination or originate past their origin.
+//!
+//! Track-A-Train marks every station a truncated train won't serve as `Canceled`. When those stations
+//! form a contiguous run at the start or the end of the station list, the train is short-turned.
+//! The stations are already SKIPPED in the trip update, this module describes the truncation to riders
+//! with an alert linked to the trip.
+
+use crate::AmtrakArrivalJson;
+use gtfs_realtime::FeedEntity;
+use gtfs_realtime::alert::{Cause, Effect, SeverityLevel};
+use gtfs_structures::Gtfs;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortTurn {
+    /// Station codes skipped before the train starts running, in travel order
+    pub skipped_at_origin: Vec<String>,
+    /// Station codes skipped after the train stops running, in travel order
+    pub skipped_at_destination: Vec<String>,
+    /// First station the train serves
+    pub first_served: String,
+    /// Last station the train serves
+    pub last_served: String,
+}
+
+impl ShortTurn {
+    pub fn originates_late(&self) -> bool {
+        !self.skipped_at_origin.is_empty()
+    }
+
+    pub fn terminates_short(&self) -> bool {
+        !self.skipped_at_destination.is_empty()
+    }
+}
+
+fn is_canceled(stop: &AmtrakArrivalJson) -> bool {
+    stop.schcmnt == "Canceled"
+}
+
+/// Finds contiguous canceled stations at either end of a Track-A-Train station list.
+///
+/// Returns `None` when both ends are served, and when every station is canceled since that's a canceled trip instead.
+pub(crate) fn detect_short_turn(stops: &[AmtrakArrivalJson]) -> Option<ShortTurn> {
+    let first_served = stops.iter().position(|stop| !is_canceled(stop))?;
+    let last_served = stops.iter().rposition(|stop| !is_canceled(stop))?;
+
+    if first_served == 0 && last_served == stops.len() - 1 {
+        return None;
+    }
+
+    let codes = |stops: &[AmtrakArrivalJson]| {
+        stops
+            .iter()
+            .map(|stop| stop.code.clone())
+            .collect::<Vec<String>>()
+    };
+
+    Some(ShortTurn {
+        skipped_at_origin: codes(&stops[..first_served]),
+        skipped_at_destination: codes(&stops[last_served + 1..]),
+        first_served: stops[first_served].code.clone(),
+        last_served: stops[last_served].code.clone(),
+    })
+}
+
+fn station_name(gtfs: &Gtfs, code: &str) -> String {
+    gtfs.stops
+        .get(code)
+        .and_then(|stop| stop.name.clone())
+        .unwrap_or_else(|| code.to_string())
+}
+
+fn station_names(gtfs: &Gtfs, codes: &[String]) -> String {
+    codes
+        .iter()
+        .map(|code| station_name(gtfs, code))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Builds the alert describing a short-turn, informing the trip and every station it no longer serves.
+///
+/// The alert's entity id is `{entity_id}-short-turn`, so it stays linked to the trip's entity.
+pub(crate) fn short_turn_alert(
+    gtfs: &Gtfs,
+    short_turn: &ShortTurn,
+    train_num: &str,
+    entity_id: &str,
+    trip_entity: gtfs_realtime::EntitySelector,
+) -> FeedEntity {
+    let first_served = station_name(gtfs, &short_turn.first_served);
+    let last_served = station_name(gtfs, &short_turn.last_served);
+
+    let header = match (short_turn.originates_late(), short_turn.terminates_short()) {
+        (true, true) => format!(
+            "Train {} runs only between {} and {}",
+            train_num, first_served, last_served
+        ),
+        (true, false) => format!("Train {} originates at {}", train_num, first_served),
+        _ => format!("Train {} terminates at {}", train_num, last_served),
+    };
+
+    let mut description = vec![];
+
+    if short_turn.originates_late() {
+        description.push(format!(
+            "Train {} will not serve {}. Service begins at {}.",
+            train_num,
+            station_names(gtfs, &short_turn.skipped_at_origin),
+            first_served
+        ));
+    }
+
+    if short_turn.terminates_short() {
+        description.push(format!(
+            "Train {} will not serve {}. Service ends at {}.",
+            train_num,
+            station_names(gtfs, &short_turn.skipped_at_destination),
+            last_served
+        ));
+    }
+
+    let route_id = trip_entity.route_id.clone();
+
+    let mut informed_entity = vec![trip_entity];
+
+    for code in short_turn
+        .skipped_at_origin
+        .iter()
+        .chain(short_turn.skipped_at_destination.iter())
+    {
+        informed_entity.push(gtfs_realtime::EntitySelector {
+            agency_id: None,
+            route_id: route_id.clone(),
+            route_type: None,
+            trip: None,
+            stop_id: Some(code.clone()),
+            direction_id: None,
+        });
+    }
+
+    FeedEntity {
+        id: format!("{}-short-turn", entity_id),
+        is_deleted: Some(false),
+        trip_update: None,
+        vehicle: None,
+        stop: None,
+        shape: None,
+        trip_modifications: None,
+        alert: Some(gtfs_realtime::Alert {
+            informed_entity,
+            cause: Some(Cause::UnknownCause as i32),
+            effect: Some(Effect::ReducedService as i32),
+            severity_level: Some(SeverityLevel::Warning as i32),
+            header_text: Some(gtfs_realtime::TranslatedString {
+                translation: vec![gtfs_realtime::translated_string::Translation {
+                    text: header,
+                    language: Some("en".to_string()),
+                }],
+            }),
+            description_text: Some(gtfs_realtime::TranslatedString {
+                translation: vec![gtfs_realtime::translated_string::Translation {
+                    text: description.join("\n\n"),
+                    language: Some("en".to_string()),
+                }],
+            }),
+            ..Default::default()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::advisory_source::test_helpers::{description, header};
+
+    fn stops(schcmnt: &[(&str, &str)]) -> Vec<AmtrakArrivalJson> {
+        schcmnt
+            .iter()
+            .map(|(code, schcmnt)| {
+                serde_json::from_value(crate::test_helpers::station(code, None, None, schcmnt))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_runs_at_both_ends() {
+        assert_eq!(
+            detect_short_turn(&stops(&[("SEA", ""), ("PDX", ""), ("SAC", "")])),
+            None
+        );
+
+        // Canceled intermediate stops aren't a short-turn
+        assert_eq!(
+            detect_short_turn(&stops(&[("SEA", ""), ("PDX", "Canceled"), ("SAC", "")])),
+            None
+        );
+
+        assert_eq!(
+            detect_short_turn(&stops(&[
+                ("SEA", "Canceled"),
+                ("PDX", "Canceled"),
+                ("SAC", "Canceled")
+            ])),
+            None
+        );
+
+        assert_eq!(
+            detect_short_turn(&stops(&[
+                ("SEA", "Canceled"),
+                ("PDX", ""),
+                ("SAC", "Canceled"),
+                ("LAX", "Canceled")
+            ])),
+            Some(ShortTurn {
+                skipped_at_origin: vec!["SEA".to_string()],
+                skipped_at_destination: vec!["SAC".to_string(), "LAX".to_string()],
+                first_served: "PDX".to_string(),
+                last_served: "PDX".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn alert_text() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+
+        let short_turn =
+            detect_short_turn(&stops(&[("SEA", ""), ("PDX", ""), ("SAC", "Canceled")])).unwrap();
+
+        let entity = short_turn_alert(
+            &gtfs,
+            &short_turn,
+            "11",
+            "20260119-11",
+            gtfs_realtime::EntitySelector {
+                route_id: Some("94".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(entity.id, "20260119-11-short-turn");
+        assert_eq!(header(&entity), "Train 11 terminates at PDX");
+        assert_eq!(
+            description(&entity),
+            "Train 11 will not serve SAC. Service ends at PDX."
+        );
+
+        let informed_entity = &entity.alert.as_ref().unwrap().informed_entity;
+        assert_eq!(informed_entity.len(), 2);
+        assert_eq!(informed_entity[1].stop_id.as_deref(), Some("SAC"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Conversion of the local times in a Track-A-Train station list to unix time.
+//!
+//! Times are resolved in travel order so daylight saving time transitions can be disambiguated: a stop can't
+//! be scheduled before the previous stop, and a train can't arrive before it left the previous station.
+//! Scheduled and realtime times are separate chains, since a late train's actual times can be hours after
+//! the schedule.
+
+use crate::AmtrakArrivalJson;
+use crate::timestamps::{TimestampError, parse_amtrak_timestamp};
+use chrono_tz::Tz;
+
+/// Unix times of one station. Realtime times are actual if known, else estimated.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct StationTimes {
+    pub scheduled_arrival: Option<i64>,
+    pub scheduled_departure: Option<i64>,
+    pub arrival: Option<i64>,
+    pub departure: Option<i64>,
+    /// The train was seen arriving, `arrival` isn't an estimate
+    pub actual_arrival: bool,
+    pub actual_departure: bool,
+}
+
+/// Converts one local time, keeping `previous` as the latest time of its chain.
+/// `not_before` is an extra lower bound for the time, on top of the chain.
+/// Times that can't be parsed are added to `errors`, an empty field is just a missing time.
+fn resolve(
+    text: Option<&String>,
+    tz: Option<Tz>,
+    previous: &mut Option<i64>,
+    not_before: Option<i64>,
+    errors: &mut Vec<TimestampError>,
+) -> Option<i64> {
+    let timestamp = match parse_amtrak_timestamp(text?) {
+        Ok(timestamp) => timestamp,
+        Err(TimestampError::Empty) => return None,
+        Err(e) => {
+            errors.push(e);
+            return None;
+        }
+    };
+
+    let hint = match (*previous, not_before) {
+        (Some(previous), Some(not_before)) => Some(previous.max(not_before)),
+        (previous, not_before) => previous.or(not_before),
+    };
+
+    let time = timestamp.to_unix(tz?, hint)?;
+
+    *previous = Some(previous.map_or(time, |previous| previous.max(time)));
+
+    Some(time)
+}
+
+/// Actual time if known, else the estimate, which can't be before the train's last report.
+/// The flag is whether the time is actual.
+fn resolve_realtime(
+    actual: Option<&String>,
+    estimate: Option<&String>,
+    tz: Option<Tz>,
+    previous: &mut Option<i64>,
+    last_update: Option<i64>,
+    errors: &mut Vec<TimestampError>,
+) -> (Option<i64>, bool) {
+    match resolve(actual, tz, previous, None, errors) {
+        Some(actual) => (Some(actual), true),
+        None => (resolve(estimate, tz, previous, last_update, errors), false),
+    }
+}
+
+/// Resolves the times of every station, `timezones` being in the same order as `stations`.
+///
+/// `last_update` is when Track-A-Train last heard from the train, unix seconds.
+/// Also returns the times that couldn't be parsed with their station codes, they are left out of the stations.
+pub(crate) fn resolve_station_times(
+    stations: &[AmtrakArrivalJson],
+    timezones: &[Option<Tz>],
+    last_update: Option<i64>,
+) -> (Vec<StationTimes>, Vec<(String, TimestampError)>) {
+    let mut previous_scheduled = None;
+    let mut previous_realtime = None;
+    let mut errors = vec![];
+
+    let times = stations
+        .iter()
+        .zip(timezones)
+        .map(|(station, tz)| {
+            let mut station_errors = vec![];
+
+            let scheduled_arrival = resolve(
+                station.scharr.as_ref(),
+                *tz,
+                &mut previous_scheduled,
+                None,
+                &mut station_errors,
+            );
+            let scheduled_departure = resolve(
+                station.schdep.as_ref(),
+                *tz,
+                &mut previous_scheduled,
+                None,
+                &mut station_errors,
+            );
+
+            let (arrival, actual_arrival) = resolve_realtime(
+                station.postarr.as_ref(),
+                station.estarr.as_ref(),
+                *tz,
+                &mut previous_realtime,
+                last_update,
+                &mut station_errors,
+            );
+            let (departure, actual_departure) = resolve_realtime(
+                station.postdep.as_ref(),
+                station.estdep.as_ref(),
+                *tz,
+                &mut previous_realtime,
+                last_update,
+                &mut station_errors,
+            );
+
+            errors.extend(
+                station_errors
+                    .into_iter()
+                    .map(|e| (station.code.clone(), e)),
+            );
+
+            StationTimes {
+                scheduled_arrival,
+                scheduled_departure,
+                arrival,
+                departure,
+                actual_arrival,
+                actual_departure,
+            }
+        })
+        .collect();
+
+    (times, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station(
+        scharr: Option<&str>,
+        schdep: Option<&str>,
+        estarr: Option<&str>,
+    ) -> AmtrakArrivalJson {
+        let mut station = crate::test_helpers::station("XYZ", scharr, schdep, "");
+        station["estarr"] = estarr.into();
+        serde_json::from_value(station).unwrap()
+    }
+
+    #[test]
+    fn overnight_train_across_fall_back() {
+        // Chicago, November 2, 2025: 1:00 to 1:59 happens twice, CDT (UTC-5) then CST (UTC-6)
+        let stations = [
+            station(None, Some("11/02/2025 00:50:00"), None),
+            station(
+                Some("11/02/2025 01:40:00"),
+                Some("11/02/2025 01:45:00"),
+                None,
+            ),
+            // An hour later in real time, but the clocks went back
+            station(
+                Some("11/02/2025 01:20:00"),
+                None,
+                Some("11/02/2025 01:35:00"),
+            ),
+        ];
+
+        let (times, _) =
+            resolve_station_times(&stations, &[Some(chrono_tz::America::Chicago); 3], None);
+
+        let cdt_0050 = 1762062600;
+
+        assert_eq!(times[0].scheduled_departure, Some(cdt_0050));
+        assert_eq!(times[1].scheduled_arrival, Some(cdt_0050 + 50 * 60));
+        assert_eq!(times[1].scheduled_departure, Some(cdt_0050 + 55 * 60));
+        assert_eq!(times[2].scheduled_arrival, Some(cdt_0050 + 90 * 60));
+
+        // First realtime time of the train, no earlier time to order it against
+        assert_eq!(times[2].arrival, Some(cdt_0050 + 45 * 60));
+
+        // The train last reported at 1:10 CST, so the estimate is after that
+        let last_update = cdt_0050 + 80 * 60;
+        let (times, _) = resolve_station_times(
+            &stations,
+            &[Some(chrono_tz::America::Chicago); 3],
+            Some(last_update),
+        );
+
+        assert_eq!(times[2].arrival, Some(cdt_0050 + 105 * 60));
+        assert_eq!(times[2].scheduled_arrival, Some(cdt_0050 + 90 * 60));
+    }
+
+    #[test]
+    fn spring_forward_never_goes_backwards() {
+        // New York, March 9, 2025: 2:00 to 2:59 doesn't exist
+        let stations = [
+            station(None, Some("03/09/2025 01:50:00"), None),
+            station(
+                Some("03/09/2025 02:20:00"),
+                Some("03/09/2025 03:25:00"),
+                None,
+            ),
+        ];
+
+        let (times, _) =
+            resolve_station_times(&stations, &[Some(chrono_tz::America::New_York); 2], None);
+
+        let est_0150 = 1741503000;
+
+        assert_eq!(times[0].scheduled_departure, Some(est_0150));
+        // 2:20 is read as 3:20 EDT, 30 minutes after 1:50 EST
+        assert_eq!(times[1].scheduled_arrival, Some(est_0150 + 30 * 60));
+        assert_eq!(times[1].scheduled_departure, Some(est_0150 + 35 * 60));
+    }
+
+    #[test]
+    fn unparseable_times_are_skipped() {
+        let stations = [station(Some("tomorrow"), Some("03/09/2025 01:50:00"), None)];
+
+        let (times, errors) =
+            resolve_station_times(&stations, &[Some(chrono_tz::America::New_York)], None);
+
+        assert_eq!(
+            errors,
+            vec![(
+                "XYZ".to_string(),
+                TimestampError::UnrecognizedFormat("tomorrow".to_string())
+            )]
+        );
+        assert_eq!(times[0].scheduled_arrival, None);
+        assert_eq!(times[0].scheduled_departure, Some(1741503000));
+        assert!(!times[0].actual_arrival);
+        assert_eq!(
+            resolve_station_times(&stations, &[None], None).0[0],
+            StationTimes::default()
+        );
+    }
+
+    #[test]
+    fn actual_times_win_over_estimates() {
+        let mut station = station(
+            Some("03/09/2025 01:50:00"),
+            None,
+            Some("03/09/2025 01:55:00"),
+        );
+        station.postarr = Some("03/09/2025 01:52:00".to_string());
+
+        let (times, _) =
+            resolve_station_times(&[station], &[Some(chrono_tz::America::New_York)], None);
+
+        assert_eq!(times[0].arrival, Some(1741503120));
+        assert!(times[0].actual_arrival);
+        assert!(!times[0].actual_departure);
+    }
+}
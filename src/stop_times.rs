@@ -1,4 +1,6 @@
-use chrono::NaiveDate;
+use crate::timestamps::{TimestampError, parse_amtrak_datetime};
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -109,6 +111,13 @@ pub struct Schedule {
     pub date_time: String,
 }
 
+impl Schedule {
+    /// The scheduled time, in `tz` when Amtrak leaves the offset out.
+    pub fn parse_date_time(&self, tz: Tz) -> Result<DateTime<Tz>, TimestampError> {
+        parse_amtrak_datetime(&self.date_time, tz)
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusInfo {
@@ -122,6 +131,15 @@ pub struct StatusInfo {
     pub as_of: String,
 }
 
+impl StatusInfo {
+    /// The estimated or actual time, `None` when there isn't one.
+    pub fn parse_date_time(&self, tz: Tz) -> Option<Result<DateTime<Tz>, TimestampError>> {
+        self.date_time
+            .as_ref()
+            .map(|date_time| parse_amtrak_datetime(date_time, tz))
+    }
+}
+
 pub async fn query_all_trips_simultaniously(
     train_numbers: &Vec<(String, NaiveDate)>,
 ) -> HashMap<(String, NaiveDate), RootTripData> {
@@ -209,4 +227,41 @@ mod tests {
 
         assert_eq!(trip_data.id, "059520240513");
     }
+
+    #[test]
+    fn schedule_and_status_times() {
+        let tz = chrono_tz::America::Los_Angeles;
+
+        // Solana Beach, train 595 of May 13, 2024
+        let departure = DepartureOrArrival {
+            schedule: Schedule {
+                date_time: "2024-05-13T21:40:00-07:00".to_string(),
+            },
+            status_info: StatusInfo {
+                date_time: Some("2024-05-13T21:41:00-07:00".to_string()),
+                ..Default::default()
+            },
+        };
+
+        assert_eq!(
+            departure.schedule.parse_date_time(tz).unwrap().timestamp(),
+            1715661600
+        );
+        assert_eq!(
+            departure
+                .status_info
+                .parse_date_time(tz)
+                .unwrap()
+                .unwrap()
+                .timestamp(),
+            1715661660
+        );
+        assert_eq!(
+            Schedule {
+                date_time: "soon".to_string(),
+            }
+            .parse_date_time(tz),
+            Err(TimestampError::UnrecognizedFormat("soon".to_string()))
+        );
+    }
 }
@@ -0,0 +1,174 @@
+//! Parser for every timestamp format Amtrak publishes.
+//!
+//! Track-A-Train uses 24 hour station times (`12/11/2023 17:36:00`) and 12 hour train times
+//! (`11/18/2023 4:58:09 PM`), Amtrak.com uses ISO-8601 (`2024-05-13T08:15:00-04:00`). Seconds are
+//! sometimes left out, and hours are sometimes padded with a space instead of a zero.
+
+use crate::timezones::local_to_datetime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use chrono_tz::Tz;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimestampError {
+    Empty,
+    /// None of the known formats matched
+    UnrecognizedFormat(String),
+    /// The time falls outside of what can be represented in the timezone
+    OutOfRange(String),
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::Empty => write!(f, "empty timestamp"),
+            TimestampError::UnrecognizedFormat(text) => {
+                write!(f, "unrecognized timestamp format '{}'", text)
+            }
+            TimestampError::OutOfRange(text) => write!(f, "timestamp out of range '{}'", text),
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AmtrakTimestamp {
+    /// Wall clock time, in the timezone of the station or train it describes
+    Local(NaiveDateTime),
+    /// ISO-8601 time with an explicit offset
+    Absolute(DateTime<FixedOffset>),
+}
+
+const LOCAL_FORMATS: [&str; 6] = [
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M",
+    "%m/%d/%Y %I:%M:%S %p",
+    "%m/%d/%Y %I:%M %p",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses a timestamp in any of the formats Amtrak uses.
+pub fn parse_amtrak_timestamp(text: &str) -> Result<AmtrakTimestamp, TimestampError> {
+    // Collapses padding like "1/19/2026  9:00:00 AM"
+    let normalized = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if normalized.is_empty() {
+        return Err(TimestampError::Empty);
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(&normalized) {
+        return Ok(AmtrakTimestamp::Absolute(datetime));
+    }
+
+    LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&normalized, format).ok())
+        .map(AmtrakTimestamp::Local)
+        .ok_or(TimestampError::UnrecognizedFormat(text.to_string()))
+}
+
+impl AmtrakTimestamp {
+    /// The instant described, reading local times in `tz`.
+    /// `not_before` disambiguates repeated local times, see [`local_to_datetime`].
+    pub fn to_datetime(&self, tz: Tz, not_before: Option<i64>) -> Option<DateTime<Tz>> {
+        match self {
+            AmtrakTimestamp::Local(naive) => local_to_datetime(tz, naive, not_before),
+            AmtrakTimestamp::Absolute(datetime) => Some(datetime.with_timezone(&tz)),
+        }
+    }
+
+    pub fn to_unix(&self, tz: Tz, not_before: Option<i64>) -> Option<i64> {
+        self.to_datetime(tz, not_before)
+            .map(|datetime| datetime.timestamp())
+    }
+}
+
+/// Parses a timestamp and converts it to an instant in `tz`.
+pub fn parse_amtrak_datetime(text: &str, tz: Tz) -> Result<DateTime<Tz>, TimestampError> {
+    parse_amtrak_timestamp(text)?
+        .to_datetime(tz, None)
+        .ok_or(TimestampError::OutOfRange(text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpus() {
+        // Strings seen in Track-A-Train, ASM and Amtrak.com responses
+        let corpus = [
+            ("12/11/2023 17:36:00", "2023-12-11 17:36:00"),
+            ("01/20/2026 06:00:00", "2026-01-20 06:00:00"),
+            ("12/11/2023 17:36", "2023-12-11 17:36:00"),
+            ("11/18/2023 4:58:09 PM", "2023-11-18 16:58:09"),
+            ("8/8/2025 9:51:00 AM", "2025-08-08 09:51:00"),
+            ("1/19/2026  9:00:00 AM", "2026-01-19 09:00:00"),
+            ("1/19/2026 12:05:00 AM", "2026-01-19 00:05:00"),
+            ("1/19/2026 12:05:00 PM", "2026-01-19 12:05:00"),
+            ("1/19/2026 9:00 pm", "2026-01-19 21:00:00"),
+            (" 3/9/2025 2:30:00 AM ", "2025-03-09 02:30:00"),
+            ("2024-05-13T08:15:00", "2024-05-13 08:15:00"),
+            ("2024-05-13T08:15:00.000", "2024-05-13 08:15:00"),
+            ("2024-05-13T08:15", "2024-05-13 08:15:00"),
+        ];
+
+        for (text, expected) in corpus {
+            let expected = NaiveDateTime::parse_from_str(expected, "%Y-%m-%d %H:%M:%S").unwrap();
+
+            assert_eq!(
+                parse_amtrak_timestamp(text),
+                Ok(AmtrakTimestamp::Local(expected)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn iso_with_offset() {
+        let tz = chrono_tz::America::Los_Angeles;
+
+        for text in [
+            "2024-05-13T08:15:00-04:00",
+            "2024-05-13T12:15:00Z",
+            "2024-05-13T12:15:00.000Z",
+        ] {
+            let parsed = parse_amtrak_timestamp(text).unwrap();
+
+            assert!(matches!(parsed, AmtrakTimestamp::Absolute(_)), "{}", text);
+            // The explicit offset wins over the station's timezone
+            assert_eq!(parsed.to_unix(tz, None), Some(1715602500), "{}", text);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_amtrak_timestamp("   "), Err(TimestampError::Empty));
+
+        for text in [
+            "",
+            "tomorrow",
+            "13/45/2025 10:00:00",
+            "1/19/2026 25:00:00",
+            "1/19/2026",
+        ] {
+            assert!(parse_amtrak_timestamp(text).is_err(), "{}", text);
+        }
+
+        assert_eq!(
+            parse_amtrak_timestamp("tomorrow"),
+            Err(TimestampError::UnrecognizedFormat("tomorrow".to_string()))
+        );
+    }
+
+    #[test]
+    fn local_times_use_the_timezone() {
+        let datetime =
+            parse_amtrak_datetime("8/8/2025 9:51:00 AM", chrono_tz::America::Los_Angeles).unwrap();
+
+        assert_eq!(datetime.timestamp(), 1754671860);
+    }
+}
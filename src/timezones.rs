@@ -0,0 +1,321 @@
+//! Time zones of Amtrak stations.
+//!
+//! Track-A-Train reports times in the local time of each station, tagged with a single letter zone code.
+//! The letters can't tell Arizona from Mountain time, or Canadian stations from US ones, so the schedule's
+//! `stop_timezone` (inherited from the parent station, else the agency's timezone, as GTFS specifies) wins
+//! whenever the station is in the GTFS. The letter is only used for stations the schedule doesn't know.
+
+use chrono::offset::LocalResult;
+use chrono::{DateTime, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use gtfs_structures::Gtfs;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimezoneError {
+    /// Track-A-Train zone letter that isn't known
+    UnknownCode(char),
+    /// IANA timezone name from the GTFS that chrono-tz doesn't know
+    InvalidTimezone(String),
+}
+
+impl fmt::Display for TimezoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimezoneError::UnknownCode(code) => write!(f, "unknown timezone code '{}'", code),
+            TimezoneError::InvalidTimezone(name) => write!(f, "invalid timezone '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for TimezoneError {}
+
+/// Maps a Track-A-Train zone letter to a timezone.
+pub fn tz_from_code(code: char) -> Result<Tz, TimezoneError> {
+    match code {
+        'E' => Ok(chrono_tz::America::New_York),
+        'C' => Ok(chrono_tz::America::Chicago),
+        'M' => Ok(chrono_tz::America::Denver),
+        'P' => Ok(chrono_tz::America::Los_Angeles),
+        // Arizona doesn't observe daylight saving time
+        'A' => Ok(chrono_tz::America::Phoenix),
+        _ => Err(TimezoneError::UnknownCode(code)),
+    }
+}
+
+fn parse_timezone(name: &str) -> Result<Tz, TimezoneError> {
+    name.parse::<Tz>()
+        .map_err(|_| TimezoneError::InvalidTimezone(name.to_string()))
+}
+
+/// Timezone of the first agency in the schedule.
+pub fn agency_timezone(gtfs: &Gtfs) -> Option<Result<Tz, TimezoneError>> {
+    gtfs.agencies
+        .first()
+        .map(|agency| parse_timezone(&agency.timezone))
+}
+
+/// Timezone of a station in the schedule, `None` when the station isn't in it.
+pub fn gtfs_station_timezone(gtfs: &Gtfs, station_code: &str) -> Option<Result<Tz, TimezoneError>> {
+    let stop = gtfs.stops.get(station_code)?;
+
+    let timezone = stop.timezone.clone().or_else(|| {
+        stop.parent_station
+            .as_ref()
+            .and_then(|parent| gtfs.stops.get(parent))
+            .and_then(|parent| parent.timezone.clone())
+    });
+
+    match timezone {
+        Some(timezone) => Some(parse_timezone(&timezone)),
+        None => agency_timezone(gtfs),
+    }
+}
+
+/// Resolves the timezone of a Track-A-Train station, preferring the schedule over the zone letter.
+pub fn station_timezone(
+    gtfs: &Gtfs,
+    station_code: &str,
+    tz_code: char,
+) -> Result<Tz, TimezoneError> {
+    match gtfs_station_timezone(gtfs, station_code) {
+        Some(timezone) => timezone,
+        None => tz_from_code(tz_code),
+    }
+}
+
+/// Converts a local time to an instant, resolving daylight saving time transitions.
+///
+/// During fall-back a local time happens twice: the earliest instant at or after `not_before` is used, so
+/// times of consecutive stops keep increasing. Without a hint, or when both are before it, the earliest wins.
+/// During spring-forward a local time doesn't exist. Timetables are written in the offset before the change,
+/// so e.g. 2:30 is read as 3:30 daylight time.
+pub fn local_to_datetime(
+    tz: Tz,
+    naive: &NaiveDateTime,
+    not_before: Option<i64>,
+) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(datetime) => Some(datetime),
+        LocalResult::Ambiguous(earliest, latest) => match not_before {
+            Some(not_before)
+                if earliest.timestamp() < not_before && latest.timestamp() >= not_before =>
+            {
+                Some(latest)
+            }
+            _ => Some(earliest),
+        },
+        LocalResult::None => {
+            // Every transition in the zones Amtrak serves is one hour
+            let gap = chrono::Duration::hours(1);
+
+            tz.from_local_datetime(&(*naive - gap))
+                .earliest()
+                .map(|datetime| datetime + gap)
+        }
+    }
+}
+
+/// [`local_to_datetime`] as unix seconds.
+pub fn local_to_unix(tz: Tz, naive: &NaiveDateTime, not_before: Option<i64>) -> Option<i64> {
+    local_to_datetime(tz, naive, not_before).map(|datetime| datetime.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::sync::Arc;
+
+    fn stop(
+        id: &str,
+        timezone: Option<&str>,
+        parent_station: Option<&str>,
+    ) -> Arc<gtfs_structures::Stop> {
+        Arc::new(gtfs_structures::Stop {
+            id: id.to_string(),
+            timezone: timezone.map(|x| x.to_string()),
+            parent_station: parent_station.map(|x| x.to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn gtfs() -> Gtfs {
+        let mut gtfs = Gtfs::default();
+
+        let stops = [
+            stop("NYP", Some("America/New_York"), None),
+            stop("CHI", Some("America/Chicago"), None),
+            stop("DEN", Some("America/Denver"), None),
+            stop("FLG", Some("America/Phoenix"), None),
+            stop("LAX", Some("America/Los_Angeles"), None),
+            stop("IND", Some("America/Indiana/Indianapolis"), None),
+            stop("DET", Some("America/Detroit"), None),
+            stop("TWO", Some("America/Toronto"), None),
+            stop("MTR", Some("America/Toronto"), None),
+            stop("VAC", Some("America/Vancouver"), None),
+            stop("BOS", None, None),
+            stop("ABQ", Some("America/Denver"), None),
+            stop("ABQ_PLATFORM", None, Some("ABQ")),
+            stop("BAD", Some("Mars/Olympus_Mons"), None),
+        ];
+
+        for stop in stops {
+            gtfs.stops.insert(stop.id.clone(), stop);
+        }
+
+        gtfs.agencies.push(gtfs_structures::Agency {
+            timezone: "America/New_York".to_string(),
+            ..Default::default()
+        });
+
+        gtfs
+    }
+
+    /// UTC offset in hours on a summer day, when daylight saving time is in effect
+    fn summer_offset(timezone: Tz) -> i32 {
+        use chrono::Offset;
+
+        timezone
+            .from_utc_datetime(
+                &NaiveDate::from_ymd_opt(2025, 7, 1)
+                    .unwrap()
+                    .and_hms_opt(12, 0, 0)
+                    .unwrap(),
+            )
+            .offset()
+            .fix()
+            .local_minus_utc()
+            / 3600
+    }
+
+    #[test]
+    fn every_zone_amtrak_serves() {
+        let gtfs = gtfs();
+
+        let cases = [
+            ("NYP", 'E', -4),
+            ("CHI", 'C', -5),
+            ("DEN", 'M', -6),
+            // Track-A-Train tags Arizona as Mountain time, but there's no daylight saving time
+            ("FLG", 'M', -7),
+            ("LAX", 'P', -7),
+            ("IND", 'E', -4),
+            ("DET", 'E', -4),
+            ("TWO", 'E', -4),
+            ("MTR", 'E', -4),
+            ("VAC", 'P', -7),
+        ];
+
+        for (code, tz_code, offset) in cases {
+            let timezone = station_timezone(&gtfs, code, tz_code).unwrap();
+
+            assert_eq!(summer_offset(timezone), offset, "{}", code);
+        }
+
+        assert_eq!(
+            station_timezone(&gtfs, "VAC", 'P').unwrap(),
+            chrono_tz::America::Vancouver
+        );
+        assert_eq!(
+            station_timezone(&gtfs, "TWO", 'E').unwrap(),
+            chrono_tz::America::Toronto
+        );
+    }
+
+    #[test]
+    fn fallbacks_and_errors() {
+        let gtfs = gtfs();
+
+        // Parent station, then agency timezone
+        assert_eq!(
+            station_timezone(&gtfs, "ABQ_PLATFORM", 'M').unwrap(),
+            chrono_tz::America::Denver
+        );
+        assert_eq!(
+            station_timezone(&gtfs, "BOS", 'C').unwrap(),
+            chrono_tz::America::New_York
+        );
+
+        // Stations missing from the schedule use the zone letter
+        assert_eq!(
+            station_timezone(&gtfs, "XYZ", 'A').unwrap(),
+            chrono_tz::America::Phoenix
+        );
+        assert_eq!(
+            station_timezone(&gtfs, "XYZ", 'P').unwrap(),
+            chrono_tz::America::Los_Angeles
+        );
+
+        assert_eq!(
+            station_timezone(&gtfs, "XYZ", 'X'),
+            Err(TimezoneError::UnknownCode('X'))
+        );
+        assert_eq!(
+            station_timezone(&gtfs, "BAD", 'E'),
+            Err(TimezoneError::InvalidTimezone(
+                "Mars/Olympus_Mons".to_string()
+            ))
+        );
+    }
+
+    fn local(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn fall_back_uses_ordering() {
+        let tz = chrono_tz::America::Chicago;
+
+        // 1:30 happens at 06:30 UTC (CDT) and again at 07:30 UTC (CST) on November 2, 2025
+        let first = 1762065000;
+        let second = first + 3600;
+
+        assert_eq!(
+            local_to_unix(tz, &local("2025-11-02 01:30"), None),
+            Some(first)
+        );
+
+        // The previous stop was at 1:50 CDT, so 1:30 must be the second one
+        let previous_stop = first + 20 * 60;
+        assert_eq!(
+            local_to_unix(tz, &local("2025-11-02 01:30"), Some(previous_stop)),
+            Some(second)
+        );
+
+        // Stops before the repeated hour don't change anything
+        assert_eq!(
+            local_to_unix(tz, &local("2025-11-02 01:30"), Some(first - 3600)),
+            Some(first)
+        );
+    }
+
+    #[test]
+    fn spring_forward_gap() {
+        let tz = chrono_tz::America::New_York;
+
+        // 2:30 doesn't exist on March 9, 2025, it's read as 3:30 EDT (07:30 UTC)
+        assert_eq!(
+            local_to_unix(tz, &local("2025-03-09 02:30"), None),
+            Some(1741505400)
+        );
+        assert_eq!(
+            local_to_unix(tz, &local("2025-03-09 03:30"), None),
+            Some(1741505400)
+        );
+        assert_eq!(
+            local_to_unix(tz, &local("2025-03-09 01:30"), None),
+            Some(1741505400 - 3600)
+        );
+
+        // Arizona has no transitions
+        assert_eq!(
+            local_to_unix(
+                chrono_tz::America::Phoenix,
+                &local("2025-03-09 02:30"),
+                None
+            ),
+            Some(1741512600)
+        );
+    }
+}
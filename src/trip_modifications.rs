@@ -0,0 +1,688 @@
+//! GTFS-rt `TripModifications` for detoured trains.
+//!
+//! A detour, a run of consecutive skipped stations of a scheduled trip replaced by stations the schedule doesn't
+//! call there, becomes one `Modification` with its replacement stops. A replacement `Shape` is published with
+//! the modifications, so consumers can redraw the detoured route. When the train kept reporting positions far
+//! away from the GTFS shape, the replacement shape goes through them.
+//!
+//! Skipped stations without a replacement, like a short turn, stay `SKIPPED` stop time updates of the
+//! unmodified trip. Stations missing from the GTFS are bad data, not replacement stops. Trains that run the
+//! scheduled stop list get no modifications, however far they are from the shape. The trip update of a
+//! modified trip links to its modifications through `TripDescriptor.modified_trip`, see [`link_modified_trip`].
+
+use crate::shapes;
+use gtfs_realtime::trip_descriptor::ModifiedTripSelector;
+use gtfs_realtime::trip_modifications::{Modification, SelectedTrips};
+use gtfs_realtime::trip_update::StopTimeUpdate;
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_realtime::{FeedEntity, ReplacementStop, StopSelector, TripDescriptor};
+use gtfs_structures::{Gtfs, Trip};
+use std::collections::{HashMap, HashSet};
+
+/// A train further than this from its GTFS shape is considered detoured.
+pub const SHAPE_DIVERGENCE_THRESHOLD_M: f64 = 1500.0;
+
+/// Positions in a row a train has to be reported off its shape for, before the shape is redrawn through
+/// them. A single position far from the shape is more likely bad GPS than a detour.
+pub const MIN_DIVERGENT_POSITIONS: usize = 3;
+
+fn is_skipped(update: &StopTimeUpdate) -> bool {
+    update.schedule_relationship == Some(ScheduleRelationship::Skipped as i32)
+}
+
+fn event_time(update: &StopTimeUpdate) -> Option<i64> {
+    update
+        .arrival
+        .as_ref()
+        .and_then(|event| event.time)
+        .or_else(|| update.departure.as_ref().and_then(|event| event.time))
+}
+
+fn selector(update: &StopTimeUpdate) -> Option<StopSelector> {
+    Some(StopSelector {
+        stop_sequence: update.stop_sequence,
+        stop_id: update.stop_id.clone(),
+    })
+}
+
+#[derive(Default)]
+struct Run<'a> {
+    skipped: Vec<&'a StopTimeUpdate>,
+    added: Vec<&'a StopTimeUpdate>,
+}
+
+/// Replacement stops of a modification.
+///
+/// Travel times are relative to the reference stop, the served stop before the modification.
+/// A modification at the start of the trip is relative to its first replacement stop.
+fn replacement_stops(
+    stops: &[&StopTimeUpdate],
+    reference: Option<&StopTimeUpdate>,
+) -> Vec<ReplacementStop> {
+    let reference_time = match reference {
+        Some(reference) => event_time(reference),
+        None => stops.first().and_then(|stop| event_time(stop)),
+    };
+
+    stops
+        .iter()
+        .map(|stop| ReplacementStop {
+            travel_time_to_stop: match (event_time(stop), reference_time) {
+                (Some(time), Some(reference_time)) => i32::try_from(time - reference_time).ok(),
+                _ => None,
+            },
+            stop_id: stop.stop_id.clone(),
+        })
+        .collect()
+}
+
+/// Groups consecutive skipped stations of a scheduled trip's stop time updates, with the stations served
+/// instead of them, into modifications.
+///
+/// Updates are in travel order. Scheduled stations have a `stop_sequence`, stations the trip doesn't call at don't.
+/// Runs of skipped stations without replacement stops, and served stations without skipped ones, aren't detours.
+pub fn detect_modifications(
+    gtfs: &Gtfs,
+    updates: &[StopTimeUpdate],
+    last_modified_time: Option<u64>,
+) -> Vec<Modification> {
+    let mut modifications = vec![];
+
+    let mut reference: Option<&StopTimeUpdate> = None;
+    let mut run = Run::default();
+
+    let detour = |run: Run, reference: Option<&StopTimeUpdate>| match (
+        run.skipped.first(),
+        run.skipped.last(),
+    ) {
+        (Some(start), Some(end)) if !run.added.is_empty() => Some(Modification {
+            start_stop_selector: selector(start),
+            end_stop_selector: selector(end),
+            propagated_modification_delay: None,
+            replacement_stops: replacement_stops(&run.added, reference),
+            service_alert_id: None,
+            last_modified_time,
+        }),
+        _ => None,
+    };
+
+    for update in updates {
+        match update.stop_sequence {
+            Some(_) if is_skipped(update) => run.skipped.push(update),
+            Some(_) => {
+                // A served, scheduled station ends the run
+                modifications.extend(detour(std::mem::take(&mut run), reference));
+                reference = Some(update);
+            }
+            None if !is_skipped(update) && is_known_stop(gtfs, update) => run.added.push(update),
+            None => {}
+        }
+    }
+
+    modifications.extend(detour(run, reference));
+
+    modifications
+}
+
+fn is_known_stop(gtfs: &Gtfs, update: &StopTimeUpdate) -> bool {
+    update
+        .stop_id
+        .as_ref()
+        .is_some_and(|stop_id| gtfs.stops.contains_key(stop_id))
+}
+
+fn stop_coordinates(gtfs: &Gtfs, stop_id: &str) -> Option<(f64, f64)> {
+    let stop = gtfs.stops.get(stop_id)?;
+
+    Some((stop.latitude?, stop.longitude?))
+}
+
+struct Waypoint {
+    point: (f64, f64),
+    /// Index in the scheduled stop times, for stops of the original trip
+    original_index: Option<usize>,
+}
+
+/// The last [`MIN_DIVERGENT_POSITIONS`] positions of `vehicle_path` with how far along the shape they are,
+/// when all of them are off the shape. Empty otherwise.
+fn divergent_positions(
+    shape: &[(f64, f64)],
+    vehicle_path: &[(f64, f64)],
+) -> Vec<((f64, f64), f64)> {
+    if vehicle_path.len() < MIN_DIVERGENT_POSITIONS {
+        return vec![];
+    }
+
+    let mut divergent = vec![];
+
+    for point in &vehicle_path[vehicle_path.len() - MIN_DIVERGENT_POSITIONS..] {
+        match shapes::project_onto_polyline(shape, *point) {
+            Some(projection) if projection.distance_from_shape_m > SHAPE_DIVERGENCE_THRESHOLD_M => {
+                divergent.push((*point, projection.distance_along_m))
+            }
+            _ => return vec![],
+        }
+    }
+
+    divergent.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    divergent
+}
+
+/// Builds the shape of the modified trip: the GTFS shape between consecutive scheduled stops,
+/// straight lines to replacement stations, and a detour through the train when the last positions of
+/// `vehicle_path`, oldest first, are all off the shape.
+///
+/// Returns `None` when the shape wouldn't differ from the schedule.
+pub fn replacement_shape(
+    gtfs: &Gtfs,
+    trip: &Trip,
+    updates: &[StopTimeUpdate],
+    modified: bool,
+    vehicle_path: &[(f64, f64)],
+) -> Option<Vec<(f64, f64)>> {
+    let original_shape = trip
+        .shape_id
+        .as_ref()
+        .and_then(|shape_id| shapes::shape_points(gtfs, shape_id))
+        .filter(|points| points.len() >= 2);
+
+    let divergent = original_shape
+        .as_ref()
+        .map(|original_shape| divergent_positions(original_shape, vehicle_path))
+        .unwrap_or_default();
+
+    if !modified && divergent.is_empty() {
+        return None;
+    }
+
+    let skipped = updates
+        .iter()
+        .filter(|update| is_skipped(update))
+        .filter_map(|update| update.stop_sequence)
+        .collect::<HashSet<u32>>();
+
+    // Unscheduled stations, keyed by the stop sequence of the scheduled station before them
+    let mut added_after: HashMap<Option<u32>, Vec<&str>> = HashMap::new();
+    let mut last_sequence = None;
+
+    for update in updates {
+        match update.stop_sequence {
+            Some(stop_sequence) => last_sequence = Some(stop_sequence),
+            None if !is_skipped(update) => {
+                if let Some(stop_id) = update.stop_id.as_deref() {
+                    added_after.entry(last_sequence).or_default().push(stop_id);
+                }
+            }
+            None => {}
+        }
+    }
+
+    let mut waypoints = vec![];
+
+    let push_added = |waypoints: &mut Vec<Waypoint>, after: Option<u32>| {
+        for stop_id in added_after.get(&after).into_iter().flatten() {
+            if let Some(point) = stop_coordinates(gtfs, stop_id) {
+                waypoints.push(Waypoint {
+                    point,
+                    original_index: None,
+                });
+            }
+        }
+    };
+
+    push_added(&mut waypoints, None);
+
+    for (index, stop_time) in trip.stop_times.iter().enumerate() {
+        if !skipped.contains(&stop_time.stop_sequence) {
+            let point = stop_coordinates(gtfs, &stop_time.stop.id)
+                .or_else(|| Some((stop_time.stop.latitude?, stop_time.stop.longitude?)));
+
+            if let Some(point) = point {
+                waypoints.push(Waypoint {
+                    point,
+                    original_index: Some(index),
+                });
+            }
+        }
+
+        push_added(&mut waypoints, Some(stop_time.stop_sequence));
+    }
+
+    if waypoints.len() < 2 {
+        return None;
+    }
+
+    let shape_distances = original_shape
+        .as_ref()
+        .map(|points| shapes::cumulative_distances(points));
+
+    let distance_along = |point: (f64, f64)| {
+        original_shape
+            .as_ref()
+            .and_then(|points| shapes::project_onto_polyline(points, point))
+            .map(|projection| projection.distance_along_m)
+    };
+
+    let mut polyline = vec![];
+
+    for pair in waypoints.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+
+        polyline.push(from.point);
+
+        let consecutive = matches!(
+            (from.original_index, to.original_index),
+            (Some(a), Some(b)) if b == a + 1
+        );
+
+        if !consecutive {
+            continue;
+        }
+
+        let (start, end) = match (distance_along(from.point), distance_along(to.point)) {
+            (Some(start), Some(end)) if start < end => (start, end),
+            _ => continue,
+        };
+
+        let detour = divergent
+            .iter()
+            .filter(|(_, distance_along)| *distance_along >= start && *distance_along <= end)
+            .map(|(point, _)| *point)
+            .collect::<Vec<(f64, f64)>>();
+
+        if !detour.is_empty() {
+            polyline.extend(detour);
+            continue;
+        }
+
+        if let (Some(points), Some(distances)) = (&original_shape, &shape_distances) {
+            for (point, distance) in points.iter().zip(distances) {
+                if *distance > start && *distance < end {
+                    polyline.push(*point);
+                }
+            }
+        }
+    }
+
+    polyline.push(waypoints.last().unwrap().point);
+
+    Some(polyline)
+}
+
+/// Builds the `TripModifications` entity `{entity_id}-modifications` for a scheduled trip,
+/// followed by its replacement shape entity `{entity_id}-shape`, see [`replacement_shape`].
+///
+/// Returns no entities when the train isn't detoured, see [`detect_modifications`].
+pub fn trip_modification_entities(
+    gtfs: &Gtfs,
+    trip: &Trip,
+    entity_id: &str,
+    start_date: &str,
+    updates: &[StopTimeUpdate],
+    vehicle_path: &[(f64, f64)],
+    last_modified_time: Option<u64>,
+) -> Vec<FeedEntity> {
+    let modifications = detect_modifications(gtfs, updates, last_modified_time);
+
+    if modifications.is_empty() {
+        return vec![];
+    }
+
+    let shape = replacement_shape(gtfs, trip, updates, true, vehicle_path);
+
+    let shape_entity = shape.map(|points| {
+        let shape_id = format!("{}-shape", entity_id);
+
+        FeedEntity {
+            id: shape_id.clone(),
+            is_deleted: Some(false),
+            trip_update: None,
+            vehicle: None,
+            alert: None,
+            stop: None,
+            shape: Some(gtfs_realtime::Shape {
+                shape_id: Some(shape_id),
+                encoded_polyline: Some(shapes::encode_polyline(&points)),
+            }),
+            trip_modifications: None,
+        }
+    });
+
+    let modifications_entity = FeedEntity {
+        id: format!("{}-modifications", entity_id),
+        is_deleted: Some(false),
+        trip_update: None,
+        vehicle: None,
+        alert: None,
+        stop: None,
+        shape: None,
+        trip_modifications: Some(gtfs_realtime::TripModifications {
+            selected_trips: vec![SelectedTrips {
+                trip_ids: vec![trip.id.clone()],
+                shape_id: shape_entity
+                    .as_ref()
+                    .and_then(|entity| entity.shape.as_ref())
+                    .and_then(|shape| shape.shape_id.clone()),
+            }],
+            start_times: vec![],
+            service_dates: vec![start_date.to_string()],
+            modifications,
+        }),
+    };
+
+    std::iter::once(modifications_entity)
+        .chain(shape_entity)
+        .collect()
+}
+
+/// Points the trip of a trip update at its modifications `{entity_id}-modifications`. The fields identifying
+/// the scheduled trip move to `modified_trip`, since the spec requires them to be left empty.
+pub fn link_modified_trip(trip: &mut TripDescriptor, entity_id: &str) {
+    trip.modified_trip = Some(ModifiedTripSelector {
+        modifications_id: Some(format!("{}-modifications", entity_id)),
+        affected_trip_id: trip.trip_id.take(),
+        start_time: trip.start_time.take(),
+        start_date: trip.start_date.take(),
+    });
+
+    trip.route_id = None;
+    trip.direction_id = None;
+}
+
+/// The scheduled trip an entity is for, including trip updates linked to their modifications.
+pub fn scheduled_trip(entity: &FeedEntity) -> Option<TripDescriptor> {
+    let vehicle_trip = entity
+        .vehicle
+        .as_ref()
+        .and_then(|vehicle| vehicle.trip.clone());
+
+    let Some(trip_update) = &entity.trip_update else {
+        return vehicle_trip;
+    };
+
+    let Some(modified_trip) = &trip_update.trip.modified_trip else {
+        return Some(trip_update.trip.clone());
+    };
+
+    Some(vehicle_trip.unwrap_or_else(|| TripDescriptor {
+        trip_id: modified_trip.affected_trip_id.clone(),
+        start_time: modified_trip.start_time.clone(),
+        start_date: modified_trip.start_date.clone(),
+        schedule_relationship: trip_update.trip.schedule_relationship,
+        ..Default::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(
+        stop_id: &str,
+        stop_sequence: Option<u32>,
+        skipped: bool,
+        time: i64,
+    ) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            stop_sequence,
+            arrival: Some(gtfs_realtime::trip_update::StopTimeEvent {
+                time: Some(time),
+                ..Default::default()
+            }),
+            schedule_relationship: match skipped {
+                true => Some(ScheduleRelationship::Skipped as i32),
+                false => None,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn skipped_run_with_replacement() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+
+        // PDX and SAC skipped, the train detours through EUG instead
+        let updates = vec![
+            update("SEA", Some(10), false, 0),
+            update("PDX", Some(20), true, 0),
+            update("EUG", None, false, 3600),
+            update("SAC", Some(30), true, 0),
+            update("LAX", Some(40), false, 7200),
+        ];
+
+        let modifications = detect_modifications(&gtfs, &updates, Some(1));
+
+        assert_eq!(modifications.len(), 1);
+        assert_eq!(
+            modifications[0]
+                .start_stop_selector
+                .as_ref()
+                .unwrap()
+                .stop_sequence,
+            Some(20)
+        );
+        assert_eq!(
+            modifications[0]
+                .end_stop_selector
+                .as_ref()
+                .unwrap()
+                .stop_sequence,
+            Some(30)
+        );
+        assert_eq!(
+            modifications[0].replacement_stops,
+            vec![ReplacementStop {
+                travel_time_to_stop: Some(3600),
+                stop_id: Some("EUG".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_detours_are_modifications() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+
+        let not_detoured = [
+            // Running as scheduled
+            vec![
+                update("PDX", Some(20), false, 0),
+                update("SAC", Some(30), false, 0),
+            ],
+            // A skipped station, or a short turn, without a replacement
+            vec![
+                update("PDX", Some(20), true, 0),
+                update("SAC", Some(30), false, 0),
+            ],
+            vec![
+                update("PDX", Some(20), false, 0),
+                update("SAC", Some(30), true, 0),
+                update("LAX", Some(40), true, 0),
+            ],
+            // A station the schedule doesn't call at, without skipping any
+            vec![
+                update("PDX", Some(20), false, 100),
+                update("EUG", None, false, 200),
+                update("SAC", Some(30), false, 400),
+            ],
+            // A station code missing from the GTFS isn't a replacement stop
+            vec![
+                update("PDX", Some(20), false, 100),
+                update("XYZ", None, false, 200),
+                update("SAC", Some(30), true, 0),
+            ],
+        ];
+
+        for updates in not_detoured {
+            assert!(detect_modifications(&gtfs, &updates, None).is_empty());
+        }
+
+        // A detour at the end of the trip
+        let modifications = detect_modifications(
+            &gtfs,
+            &[
+                update("PDX", Some(20), false, 100),
+                update("SAC", Some(30), true, 0),
+                update("LAX", Some(40), true, 0),
+                update("EUG", None, false, 400),
+            ],
+            None,
+        );
+
+        assert_eq!(modifications.len(), 1);
+        assert_eq!(
+            modifications[0]
+                .end_stop_selector
+                .as_ref()
+                .unwrap()
+                .stop_id
+                .as_deref(),
+            Some("LAX")
+        );
+        assert_eq!(
+            modifications[0].replacement_stops,
+            vec![ReplacementStop {
+                travel_time_to_stop: Some(300),
+                stop_id: Some("EUG".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn shapes_for_skips_and_detours() {
+        let gtfs = crate::test_helpers::coast_starlight_gtfs();
+        let trip = gtfs.trips.get("T11").unwrap();
+
+        let on_schedule = vec![
+            update("SEA", Some(10), false, 0),
+            update("PDX", Some(20), false, 0),
+        ];
+
+        // Off the shape between SEA and PDX for three positions in a row
+        let detour = [(46.9, -121.0), (46.5, -121.0), (46.1, -121.0)];
+
+        // Running the scheduled stations, there is nothing to modify however far the train is
+        assert!(
+            trip_modification_entities(&gtfs, trip, "X", "20260119", &on_schedule, &detour, None)
+                .is_empty()
+        );
+
+        let shape = replacement_shape(&gtfs, trip, &on_schedule, false, &detour).unwrap();
+        assert_eq!(shape[0], (47.598, -122.330));
+        assert_eq!(shape[1..4], detour);
+        assert_eq!(shape[4], (45.529, -122.677));
+        assert_eq!(shape.len(), 8);
+
+        // A single position, or a path back on the shape, is no detour
+        assert!(replacement_shape(&gtfs, trip, &on_schedule, false, &detour[2..]).is_none());
+        assert!(
+            replacement_shape(
+                &gtfs,
+                trip,
+                &on_schedule,
+                false,
+                &[detour[0], (45.53, -122.68), detour[2]]
+            )
+            .is_none()
+        );
+
+        // Skipping SAC goes straight from PDX to LAX
+        let skipped = vec![update("SAC", Some(30), true, 0)];
+        let shape = replacement_shape(&gtfs, trip, &skipped, true, &[]).unwrap();
+
+        assert_eq!(
+            shape,
+            vec![
+                (47.598, -122.330),
+                (46.8, -122.8),
+                (45.529, -122.677),
+                (34.056, -118.236)
+            ]
+        );
+
+        // Skipping SAC for EUG is a detour, the shape is published and goes through the train
+        let detoured = vec![
+            update("PDX", Some(20), false, 0),
+            update("EUG", None, false, 0),
+            update("SAC", Some(30), true, 0),
+        ];
+        assert!(
+            trip_modification_entities(&gtfs, trip, "X", "20260119", &skipped, &detour, None)
+                .is_empty()
+        );
+
+        let entities =
+            trip_modification_entities(&gtfs, trip, "X", "20260119", &detoured, &detour, None);
+
+        assert_eq!(entities.len(), 2);
+
+        let trip_modifications = entities[0].trip_modifications.as_ref().unwrap();
+        assert_eq!(trip_modifications.modifications.len(), 1);
+        assert_eq!(
+            trip_modifications.selected_trips[0].shape_id.as_deref(),
+            Some("X-shape")
+        );
+
+        let encoded = entities[1].shape.as_ref().unwrap().encoded_polyline.clone();
+        assert_eq!(
+            encoded,
+            Some(shapes::encode_polyline(&[
+                (47.598, -122.330),
+                detour[0],
+                detour[1],
+                detour[2],
+                (45.529, -122.677),
+                (44.055, -123.094),
+                (34.056, -118.236)
+            ]))
+        );
+    }
+
+    #[test]
+    fn modified_trips_are_linked() {
+        let mut trip = TripDescriptor {
+            trip_id: Some("T11".to_string()),
+            route_id: Some("94".to_string()),
+            start_date: Some("20260119".to_string()),
+            ..Default::default()
+        };
+
+        let vehicle_trip = trip.clone();
+
+        link_modified_trip(&mut trip, "20260119-11");
+
+        assert_eq!(trip.trip_id, None);
+        assert_eq!(trip.route_id, None);
+        assert_eq!(trip.start_date, None);
+        assert_eq!(
+            trip.modified_trip,
+            Some(ModifiedTripSelector {
+                modifications_id: Some("20260119-11-modifications".to_string()),
+                affected_trip_id: Some("T11".to_string()),
+                start_time: None,
+                start_date: Some("20260119".to_string()),
+            })
+        );
+
+        let mut entity = FeedEntity {
+            trip_update: Some(gtfs_realtime::TripUpdate {
+                trip,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let scheduled = scheduled_trip(&entity).unwrap();
+        assert_eq!(scheduled.trip_id.as_deref(), Some("T11"));
+        assert_eq!(scheduled.start_date.as_deref(), Some("20260119"));
+
+        // The vehicle keeps the route
+        entity.vehicle = Some(gtfs_realtime::VehiclePosition {
+            trip: Some(vehicle_trip.clone()),
+            ..Default::default()
+        });
+
+        assert_eq!(scheduled_trip(&entity), Some(vehicle_trip));
+    }
+}